@@ -1,18 +1,17 @@
 use std::collections::HashMap;
-use std::fs::File;
 use std::sync::Arc;
 use std::time::Duration;
 use arrow::array::{ArrayRef, Int64Array, Int64Builder};
-use arrow::csv;
-use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::datatypes::{DataType, SchemaRef};
 use arrow::record_batch::RecordBatch;
 use criterion::{criterion_group, criterion_main, Criterion, black_box};
+use rustchristmasdb::csv_loader::CsvLoader;
 use rustchristmasdb::datastore::{CHUNK_DEFAULT_SIZE, MAIN_SCENARIO_NAME, SCENARIO_FIELD_NAME, Store};
 use rustchristmasdb::query::Query;
 use rustchristmasdb::query_engine::QueryEngine;
 
 const N: i32 = 10_000;
-const KEY_INDICES: [u32; 1] = [4; 1];
+const KEY_FIELD: &str = "OrderDetailID";
 
 fn criterion_benchmark(c: &mut Criterion) {
     let store = load();
@@ -34,16 +33,14 @@ fn execute_query(store: &Store) {
 }
 
 fn load() -> Store {
-    let schema = create_schema();
-    let schema_ref: SchemaRef = Arc::new(schema);
+    let loader = create_loader();
+    let schema_ref: SchemaRef = Arc::new(loader.infer_schema(scenario_path(MAIN_SCENARIO_NAME)).unwrap());
+    let key_index = schema_ref.index_of(KEY_FIELD).unwrap();
     let mut m: HashMap<&str, Vec<RecordBatch>> = HashMap::new();
     for i in 0..N {
         if i == 0 {
             for scenario in list_of_scenarios() {
-                let path = format!("test/data/data_{}_scenario.csv", scenario);
-                let file = File::open(path).unwrap();
-                let mut csv = csv::Reader::new(file, Arc::clone(&schema_ref), true, None, 1024, None, None, None);
-                let batch = csv.next().unwrap().unwrap();
+                let batch = loader.read(&schema_ref, scenario_path(scenario)).unwrap();
                 let mut vec = Vec::new();
                 vec.push(batch);
                 m.insert(scenario, vec);
@@ -54,7 +51,7 @@ fn load() -> Store {
                 let mut new_columns: Vec<ArrayRef> = Vec::new();
                 for index in 0..batch.num_columns() {
                     let col = batch.column(index);
-                    if index == KEY_INDICES[0] as usize {
+                    if index == key_index {
                         // Create a new column
                         let arr = col.as_any().downcast_ref::<Int64Array>().unwrap();
                         let mut builder = Int64Builder::new(batch.num_rows());
@@ -76,7 +73,7 @@ fn load() -> Store {
         }
     }
 
-    let mut store = create_store();
+    let mut store = Store::new(Arc::clone(&schema_ref), vec![key_index as u32], CHUNK_DEFAULT_SIZE as u32);
     for scenario in list_of_scenarios() {
         let batches = m.get(scenario).unwrap();
         let new_batch = RecordBatch::concat(&schema_ref, batches.as_slice()).unwrap();
@@ -85,27 +82,19 @@ fn load() -> Store {
     store
 }
 
-fn create_schema() -> Schema {
-    return Schema::new(vec![
-        Field::new("OrderId", DataType::UInt64, false),
-        Field::new("CustomerID", DataType::UInt64, false),
-        Field::new("EmployeeID", DataType::UInt64, false),
-        Field::new("OrderDate", DataType::Utf8, false),
-        Field::new("OrderDetailID", DataType::Int64, false),
-        Field::new("Quantity", DataType::UInt32, false),
-        Field::new("ProductName", DataType::Utf8, false),
-        Field::new("Unit", DataType::Utf8, false),
-        Field::new("Price", DataType::Float64, false),
-        Field::new("CategoryName", DataType::Utf8, false),
-        Field::new("SupplierName", DataType::Utf8, false),
-        Field::new("City", DataType::Utf8, false),
-        Field::new("Country", DataType::Utf8, false),
-        Field::new("ShipperName", DataType::Utf8, false),
-    ]);
+/// The ids are read as unsigned and the quantity is narrowed to be aggregated.
+fn create_loader() -> CsvLoader {
+    let mut loader = CsvLoader::new();
+    loader
+        .add_type_override("OrderID", DataType::UInt64)
+        .add_type_override("CustomerID", DataType::UInt64)
+        .add_type_override("EmployeeID", DataType::UInt64)
+        .add_type_override("Quantity", DataType::UInt32);
+    loader
 }
 
-fn create_store() -> Store {
-    Store::new(Arc::new(create_schema()), Vec::from(KEY_INDICES), CHUNK_DEFAULT_SIZE as u32)
+fn scenario_path(scenario: &str) -> String {
+    format!("test/data/data_{}_scenario.csv", scenario)
 }
 
 fn list_of_scenarios() -> Vec<&'static str> {
@@ -0,0 +1,48 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use rustchristmasdb::row_mapping::{BitmapRowMapping, IntIntMapRowMapping, RowMapping, SortedArrayRowMapping};
+
+const ROW_COUNT: u32 = 1 << 20;
+const LOOKUP_STEP: usize = 7;
+
+/// Reads every seventh row of the store through the mappings of scenarios modifying more or less
+/// rows.
+fn criterion_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("row_mapping_get");
+    for density in [0.001, 0.01, 0.1, 0.5] {
+        let rows = mapped_rows(density);
+        let map = IntIntMapRowMapping::new();
+        for (target_row, row) in rows.iter().enumerate() {
+            map.map(*row, target_row as u32);
+        }
+        let mappings: Vec<(&str, Box<dyn RowMapping>)> = vec![
+            ("int_int_map", Box::new(map)),
+            ("sorted_array", Box::new(SortedArrayRowMapping::new(rows.clone()))),
+            ("bitmap", Box::new(BitmapRowMapping::new(rows))),
+        ];
+        for (name, mapping) in mappings.iter() {
+            group.bench_with_input(BenchmarkId::new(*name, density), mapping, |b, mapping| b.iter(|| get_all(mapping.as_ref())));
+        }
+    }
+    group.finish();
+}
+
+fn get_all(mapping: &dyn RowMapping) {
+    let mut found = 0u32;
+    for row in (0..ROW_COUNT).step_by(LOOKUP_STEP) {
+        if let Some(target_row) = mapping.get(&row) {
+            found = found.wrapping_add(target_row);
+        }
+    }
+    black_box(found);
+}
+
+/// Pseudo random rows, in the order of the store.
+fn mapped_rows(density: f64) -> Vec<u32> {
+    let threshold = (density * u32::MAX as f64) as u32;
+    (0..ROW_COUNT)
+        .filter(|row| row.wrapping_mul(2654435761) < threshold)
+        .collect()
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
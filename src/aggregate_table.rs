@@ -0,0 +1,237 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use arrow::array::{Array, ArrayRef, Float64Array, UInt64Array};
+use arrow::datatypes::{DataType, Field};
+use serde::Deserialize;
+
+use crate::datastore::{MAIN_SCENARIO_NAME, SCENARIO_FIELD_NAME, Store};
+use crate::point_dictionary::PointDictionary;
+use crate::point_list_aggregates_result::DetachedResult;
+use crate::query::{AggregatedMeasure, Query, ValidationError};
+use crate::query_engine::QueryEngine;
+
+/// The coordinates and the measures an aggregate table is aggregated on, e.g.
+///
+/// ```json
+/// {
+///   "name": "by_category",
+///   "coordinates": ["category"],
+///   "measures": [{ "field": "price", "aggregation_function": "sum" }]
+/// }
+/// ```
+///
+/// The scenario is always a coordinate of the table, it does not need to be listed.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct AggregateTableDefinition {
+    pub name: String,
+    pub coordinates: Vec<String>,
+    pub measures: Vec<AggregatedMeasure>,
+}
+
+impl AggregateTableDefinition {
+    pub fn new(name: &str, coordinates: Vec<&str>) -> AggregateTableDefinition {
+        AggregateTableDefinition {
+            name: name.to_string(),
+            coordinates: coordinates.iter().map(|c| c.to_string()).collect(),
+            measures: Vec::new(),
+        }
+    }
+
+    pub fn add_aggregated_measure(&mut self, field: &str, agg: &str) -> &mut AggregateTableDefinition {
+        self.measures.push(AggregatedMeasure::new(field, agg));
+        self
+    }
+
+    /// Checks the coordinates and the measures of the table like the ones of a query, see
+    /// [`Query::validate`].
+    pub fn validate(&self, store: &Store) -> Result<(), Vec<ValidationError>> {
+        let mut errors = Vec::new();
+        if self.coordinates.iter().any(|c| c == SCENARIO_FIELD_NAME) {
+            errors.push(ValidationError::new(
+                "coordinates".to_string(),
+                format!("the {} is always a coordinate of an aggregate table", SCENARIO_FIELD_NAME)));
+        }
+        if let Err(query_errors) = self.query(MAIN_SCENARIO_NAME).validate(store) {
+            errors.extend(query_errors);
+        }
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+
+    /// Whether the result of the query can be rolled up from the table: the table has all the
+    /// coordinates and all the measures of the query.
+    pub fn can_answer(&self, query: &Query) -> bool {
+        query.coordinates.keys().all(|field| field == SCENARIO_FIELD_NAME || self.coordinates.contains(field))
+            && query.measures.iter().all(|measure| self.measures.contains(measure))
+    }
+
+    /// The query aggregating the rows of a scenario into the table, the scenario is the first
+    /// coordinate.
+    fn query(&self, scenario: &str) -> Query {
+        let mut query = Query::new();
+        query.add_coordinates(SCENARIO_FIELD_NAME, vec![scenario]);
+        for field in self.coordinates.iter() {
+            query.add_wildcard_coordinate(field);
+        }
+        query.measures = self.measures.clone();
+        query
+    }
+}
+
+/// The aggregates of the points of the coordinates of its definition, by scenario. Only the base
+/// scenario and the scenarios modifying some values have their own aggregates, the other ones read
+/// the aggregates of the base scenario, as they read its values.
+#[derive(Debug, Clone)]
+pub struct AggregateTable {
+    definition: AggregateTableDefinition,
+    slices: HashMap<String, DetachedResult>,
+}
+
+impl AggregateTable {
+    pub(crate) fn new(definition: AggregateTableDefinition) -> AggregateTable {
+        AggregateTable { definition, slices: HashMap::new() }
+    }
+
+    pub fn definition(&self) -> &AggregateTableDefinition {
+        &self.definition
+    }
+
+    /// The number of points of the table, for all the scenarios.
+    pub fn size(&self) -> usize {
+        self.slices.values().map(|slice| slice.point_dictionary.size()).sum()
+    }
+
+    /// Aggregates again the rows of the scenario, or drops the aggregates of a scenario that no
+    /// longer modifies any value.
+    pub(crate) fn refresh(&mut self, store: &Store, scenario: &str) {
+        if scenario == MAIN_SCENARIO_NAME || store.vector_by_field_by_scenario.contains_key(scenario) {
+            let slice = QueryEngine::new(store).scan(&self.definition.query(scenario)).detach();
+            self.slices.insert(scenario.to_string(), slice);
+        } else {
+            self.slices.remove(scenario);
+        }
+    }
+
+    /// The number of points of the table the scenario reads matching the accepted values.
+    pub(crate) fn scenario_size(&self, scenario: &str, accepted_values_by_field: &HashMap<String, HashSet<u32>>) -> usize {
+        let filters = self.filters(accepted_values_by_field);
+        self.slice(scenario).map_or(0, |slice| (0..slice.point_dictionary.size() as u32)
+            .filter(|row| Self::accepts(&filters, slice.point_dictionary.read(row).unwrap()))
+            .count())
+    }
+
+    /// The index in the points of the table of each field with accepted values, and the values.
+    fn filters<'f>(&self, accepted_values_by_field: &'f HashMap<String, HashSet<u32>>) -> Vec<(usize, &'f HashSet<u32>)> {
+        accepted_values_by_field.iter()
+            .map(|(field, values)| (self.index(field), values))
+            .collect()
+    }
+
+    fn accepts(filters: &[(usize, &HashSet<u32>)], table_point: &[u32]) -> bool {
+        filters.iter().all(|(index, values)| values.contains(&table_point[*index]))
+    }
+
+    /// The index of a coordinate in the points of the table, the first coordinate is the scenario.
+    fn index(&self, field: &str) -> usize {
+        1 + self.definition.coordinates.iter().position(|c| c == field).unwrap()
+    }
+
+    fn slice(&self, scenario: &str) -> Option<&DetachedResult> {
+        self.slices.get(scenario).or_else(|| self.slices.get(MAIN_SCENARIO_NAME))
+    }
+
+    /// Sums the aggregates of the points of the table matching the accepted values into the points
+    /// of the query, in the order the rows of the store would give them. `scenarios` are the
+    /// dictionary positions and the names of the queried scenarios.
+    pub(crate) fn roll_up(&self,
+                          query: &Query,
+                          accepted_values_by_field: &HashMap<String, HashSet<u32>>,
+                          scenarios: &[(u32, &str)],
+                          point_names: &[String],
+                          cardinalities: &[u32]) -> (PointDictionary, Vec<Field>, Vec<ArrayRef>) {
+        let indices: Vec<Option<usize>> = point_names.iter()
+            .map(|name| if name == SCENARIO_FIELD_NAME { None } else { Some(self.index(name)) })
+            .collect();
+        let filters = self.filters(accepted_values_by_field);
+        let measures: Vec<usize> = query.measures.iter()
+            .map(|measure| self.definition.measures.iter().position(|m| m == measure).unwrap())
+            .collect();
+
+        let mut point_dictionary = PointDictionary::with_cardinalities(cardinalities);
+        let mut point = vec![0; point_names.len()];
+        let mut sums: Vec<Sums> = Vec::with_capacity(measures.len());
+        let mut fields: Vec<Field> = Vec::with_capacity(measures.len());
+        for (scenario_position, scenario) in scenarios {
+            let slice = match self.slice(scenario) {
+                Some(slice) => slice,
+                None => continue,
+            };
+            if fields.is_empty() {
+                fields = measures.iter().map(|m| slice.aggregate_fields[*m].clone()).collect();
+                sums = fields.iter().map(|f| Sums::new(f.data_type())).collect();
+            }
+            for row in 0..slice.point_dictionary.size() as u32 {
+                let table_point = slice.point_dictionary.read(&row).unwrap();
+                if !Self::accepts(&filters, table_point) {
+                    continue;
+                }
+                for (value, index) in point.iter_mut().zip(indices.iter()) {
+                    *value = index.map_or(*scenario_position, |index| table_point[index]);
+                }
+                let destination = point_dictionary.map(&point) as usize;
+                for (sum, measure) in sums.iter_mut().zip(measures.iter()) {
+                    sum.add(destination, slice.aggregates[*measure].as_ref(), row as usize);
+                }
+            }
+        }
+        let size = point_dictionary.size();
+        let aggregates = sums.into_iter().map(|sum| sum.finish(size)).collect();
+        (point_dictionary, fields, aggregates)
+    }
+}
+
+/// The sums of an aggregate of the table by point of the query.
+enum Sums {
+    UInt64(Vec<u64>),
+    Float64(Vec<f64>),
+}
+
+impl Sums {
+    fn new(data_type: &DataType) -> Sums {
+        match data_type {
+            DataType::UInt64 => Sums::UInt64(Vec::new()),
+            DataType::Float64 => Sums::Float64(Vec::new()),
+            _ => panic!("{} not supported", data_type),
+        }
+    }
+
+    fn add(&mut self, destination: usize, source: &dyn Array, row: usize) {
+        match self {
+            Sums::UInt64(sums) => {
+                if sums.len() <= destination {
+                    sums.resize(destination + 1, 0);
+                }
+                sums[destination] += source.as_any().downcast_ref::<UInt64Array>().unwrap().value(row);
+            }
+            Sums::Float64(sums) => {
+                if sums.len() <= destination {
+                    sums.resize(destination + 1, 0f64);
+                }
+                sums[destination] += source.as_any().downcast_ref::<Float64Array>().unwrap().value(row);
+            }
+        }
+    }
+
+    fn finish(self, size: usize) -> ArrayRef {
+        match self {
+            Sums::UInt64(mut sums) => {
+                sums.resize(size, 0);
+                Arc::new(UInt64Array::from(sums))
+            }
+            Sums::Float64(mut sums) => {
+                sums.resize(size, 0f64);
+                Arc::new(Float64Array::from(sums))
+            }
+        }
+    }
+}
@@ -10,7 +10,9 @@ use crate::datastore::CHUNK_DEFAULT_SIZE;
 
 
 pub trait Aggregator {
-    fn aggregate(&mut self, source_position: u32, destination_position: u32);
+    /// Aggregates the value of each source position into the destination position at the same
+    /// index. The capacity must have been ensured for the largest destination position.
+    fn aggregate_batch(&mut self, source_positions: &[u32], destination_positions: &[u32]);
 
     fn finish(&mut self);
 
@@ -31,6 +33,8 @@ pub struct SumUIntAggregator {
     source: Arc<ChunkArrayReader>,
     destination: Option<PrimitiveArray<UInt64Type>>,
     buffer: Rc<RefCell<Vec<u64>>>,
+    /// The values of the source positions of the current batch.
+    values: Vec<u32>,
     field: Field,
 }
 
@@ -43,6 +47,7 @@ impl SumUIntAggregator {
             source: Arc::clone(&source),
             destination: None,
             buffer: Rc::new(RefCell::new(buffer)),
+            values: Vec::new(),
             field,
         })
     }
@@ -52,6 +57,7 @@ impl SumUIntAggregator {
             source: Arc::clone(&source),
             destination: None,
             buffer: destination,
+            values: Vec::new(),
             field,
         })
     }
@@ -62,14 +68,17 @@ impl SumUIntAggregator {
 }
 
 impl Aggregator for SumUIntAggregator {
-    fn aggregate(&mut self, source_position: u32, destination_position: u32) {
-        let a: u64 = self.source.read::<UInt32Type>(source_position) as u64;
-        let buff = &*self.buffer;
-        let b: u64 = match buff.borrow().get(destination_position as usize) {
-            None => { 0u64 }
-            Some(v) => { *v }
-        };
-        self.buffer.borrow_mut()[destination_position as usize] = a + b;
+    fn aggregate_batch(&mut self, source_positions: &[u32], destination_positions: &[u32]) {
+        self.source.gather::<UInt32Type>(source_positions, &mut self.values);
+        let mut buffer = self.buffer.borrow_mut();
+        match single_destination(destination_positions) {
+            Some(destination) => buffer[destination] += self.values.iter().map(|value| *value as u64).sum::<u64>(),
+            None => {
+                for (value, destination) in self.values.iter().zip(destination_positions) {
+                    buffer[*destination as usize] += *value as u64;
+                }
+            }
+        }
     }
 
     fn finish(&mut self) {
@@ -85,7 +94,7 @@ impl Aggregator for SumUIntAggregator {
         let buff = &*self.buffer;
         let len = buff.borrow().len();
         if destination_position >= len {
-            buff.borrow_mut().resize(len + CHUNK_DEFAULT_SIZE, 0);
+            buff.borrow_mut().resize((len + CHUNK_DEFAULT_SIZE).max(destination_position + 1), 0);
         }
     }
 
@@ -106,6 +115,8 @@ pub struct SumFloat64Aggregator {
     source: Arc<ChunkArrayReader>,
     destination: Option<PrimitiveArray<Float64Type>>,
     buffer: Rc<RefCell<Vec<f64>>>,
+    /// The values of the source positions of the current batch.
+    values: Vec<f64>,
     field: Field,
 }
 
@@ -118,6 +129,7 @@ impl SumFloat64Aggregator {
             source,
             destination: None,
             buffer: Rc::new(RefCell::new(buffer)),
+            values: Vec::new(),
             field,
         })
     }
@@ -127,6 +139,7 @@ impl SumFloat64Aggregator {
             source,
             destination: None,
             buffer: destination,
+            values: Vec::new(),
             field,
         })
     }
@@ -137,14 +150,17 @@ impl SumFloat64Aggregator {
 }
 
 impl Aggregator for SumFloat64Aggregator {
-    fn aggregate(&mut self, source_position: u32, destination_position: u32) {
-        let a: f64 = self.source.read::<Float64Type>(source_position);
-        let buff = &*self.buffer;
-        let b: f64 = match buff.borrow().get(destination_position as usize) {
-            None => { 0f64 }
-            Some(v) => { *v }
-        };
-        buff.borrow_mut()[destination_position as usize] = a + b;
+    fn aggregate_batch(&mut self, source_positions: &[u32], destination_positions: &[u32]) {
+        self.source.gather::<Float64Type>(source_positions, &mut self.values);
+        let mut buffer = self.buffer.borrow_mut();
+        match single_destination(destination_positions) {
+            Some(destination) => buffer[destination] += self.values.iter().sum::<f64>(),
+            None => {
+                for (value, destination) in self.values.iter().zip(destination_positions) {
+                    buffer[*destination as usize] += *value;
+                }
+            }
+        }
     }
 
     fn finish(&mut self) {
@@ -161,7 +177,7 @@ impl Aggregator for SumFloat64Aggregator {
         let buff = &*self.buffer;
         let len = buff.borrow().len();
         if destination_position >= len {
-            buff.borrow_mut().resize(len + CHUNK_DEFAULT_SIZE, 0f64);
+            buff.borrow_mut().resize((len + CHUNK_DEFAULT_SIZE).max(destination_position + 1), 0f64);
         }
     }
 
@@ -178,6 +194,13 @@ impl Aggregator for SumFloat64Aggregator {
     }
 }
 
+/// The destination position of a batch whose source positions all go to the same destination,
+/// e.g. when the scenario is the only coordinate. Such a batch is summed in a vectorized loop.
+fn single_destination(destination_positions: &[u32]) -> Option<usize> {
+    let first = *destination_positions.first()?;
+    destination_positions.iter().all(|destination| *destination == first).then_some(first as usize)
+}
+
 pub struct AggregatorFactory;
 
 impl AggregatorFactory {
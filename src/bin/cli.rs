@@ -0,0 +1,52 @@
+use std::env;
+use std::io;
+use std::path::PathBuf;
+
+use rustyline::error::ReadlineError;
+use rustyline::Editor;
+
+use rustchristmasdb::cli::{Cli, Control};
+
+const HISTORY_FILE: &str = ".rustchristmasdb_history";
+
+/// An interactive shell on a store. The arguments, if any, are executed as commands before the
+/// prompt shows up, e.g. `rustchristmasdb-cli '\open csv test/data OrderDetailID'`.
+fn main() {
+    let mut cli = Cli::new();
+    let mut stdout = io::stdout();
+    for command in env::args().skip(1) {
+        if let Err(e) = cli.execute(&command, &mut stdout) {
+            eprintln!("error: {}", e);
+        }
+    }
+
+    let history = env::var_os("HOME")
+        .map(PathBuf::from)
+        .unwrap_or_default()
+        .join(HISTORY_FILE);
+    let mut editor = Editor::<()>::new();
+    // The history does not exist on the first run.
+    let _ = editor.load_history(&history);
+    println!("Type \\help for the list of commands.");
+    loop {
+        match editor.readline("rustchristmasdb> ") {
+            Ok(line) => {
+                editor.add_history_entry(line.as_str());
+                match cli.execute(&line, &mut stdout) {
+                    Ok(Control::Continue) => {}
+                    Ok(Control::Quit) => break,
+                    Err(e) => eprintln!("error: {}", e),
+                }
+            }
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => {
+                eprintln!("error: {}", e);
+                break;
+            }
+        }
+    }
+    if let Err(e) = editor.save_history(&history) {
+        eprintln!("cannot save the history to {}: {}", history.display(), e);
+    }
+}
@@ -0,0 +1,33 @@
+use std::env;
+use std::net::SocketAddr;
+use std::process;
+
+use rustchristmasdb::flight_server::{DEFAULT_FLIGHT_ADDRESS, FlightServer};
+use rustchristmasdb::server::ServerConfig;
+
+/// Starts the Arrow Flight server. The arguments are the path of the json configuration, see
+/// [`ServerConfig`], and the address to listen on. The address of the configuration is the one of
+/// the http server and is ignored.
+#[tokio::main]
+async fn main() {
+    let path = env::args().nth(1).unwrap_or_else(|| "config.json".to_string());
+    let address = env::args().nth(2).unwrap_or_else(|| DEFAULT_FLIGHT_ADDRESS.to_string());
+    let address: SocketAddr = address.parse().unwrap_or_else(|e| {
+        eprintln!("invalid address {}: {}", address, e);
+        process::exit(1);
+    });
+    let config = ServerConfig::from_file(&path).unwrap_or_else(|e| {
+        eprintln!("cannot read the configuration {}: {}", path, e);
+        process::exit(1);
+    });
+
+    let server = FlightServer::new(move || config.create_store().unwrap_or_else(|e| {
+        eprintln!("cannot load the store: {}", e);
+        process::exit(1);
+    }));
+    println!("listening on {}", address);
+    if let Err(e) = server.serve(address).await {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
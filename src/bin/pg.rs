@@ -0,0 +1,27 @@
+use std::env;
+use std::process;
+
+use rustchristmasdb::pg_server::{DEFAULT_PG_ADDRESS, PgServer};
+use rustchristmasdb::server::ServerConfig;
+
+/// Starts the postgres wire protocol server. The arguments are the path of the json configuration,
+/// see [`ServerConfig`], and the address to listen on. The address of the configuration is the one
+/// of the http server and is ignored.
+fn main() {
+    let path = env::args().nth(1).unwrap_or_else(|| "config.json".to_string());
+    let address = env::args().nth(2).unwrap_or_else(|| DEFAULT_PG_ADDRESS.to_string());
+    let config = ServerConfig::from_file(&path).unwrap_or_else(|e| {
+        eprintln!("cannot read the configuration {}: {}", path, e);
+        process::exit(1);
+    });
+
+    let server = PgServer::new(move || config.create_store().unwrap_or_else(|e| {
+        eprintln!("cannot load the store: {}", e);
+        process::exit(1);
+    }));
+    println!("listening on {}", address);
+    if let Err(e) = server.serve(&address) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
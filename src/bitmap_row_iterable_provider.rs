@@ -1,14 +1,13 @@
 use std::collections::{HashMap, HashSet};
-use std::ops::{BitAndAssign, Range};
+use std::ops::Range;
 
 use arrow::datatypes::UInt32Type;
 
 use roaring::RoaringBitmap;
-use crate::chunk_array::{ChunkArrayReader};
-use crate::datastore::{MAIN_SCENARIO_NAME, SCENARIO_FIELD_NAME, Store};
+use crate::datastore::{SCENARIO_FIELD_NAME, Store};
 
 pub trait RowIterableProvider {
-    fn get(&self, scenario: &str) -> RowIterable;
+    fn get(&self, scenario: &str) -> RowIterable<'_>;
 }
 
 pub struct RangeRowIterable {
@@ -16,22 +15,58 @@ pub struct RangeRowIterable {
 }
 
 impl RowIterableProvider for RangeRowIterable {
-    fn get(&self, _: &str) -> RowIterable {
+    fn get(&self, _: &str) -> RowIterable<'_> {
         RowIterable::Range(self.range.clone())
     }
 }
 
-pub enum RowIterable {
-    RoaringBitmap(RoaringBitmap),
+pub enum RowIterable<'a> {
+    RoaringBitmap(&'a RoaringBitmap),
+    /// The rows of `base` without the `removed` ones, plus the `added` ones.
+    PatchedRoaringBitmap {
+        base: &'a RoaringBitmap,
+        removed: RoaringBitmap,
+        added: RoaringBitmap,
+    },
     Range(Range<u32>),
 }
 
-impl RowIterable {
+impl<'a> RowIterable<'a> {
+    /// The number of rows, without iterating them.
+    pub fn len(&self) -> u64 {
+        match self {
+            RowIterable::RoaringBitmap(bitmap) => bitmap.len(),
+            // The removed rows are base rows, the added ones are not.
+            RowIterable::PatchedRoaringBitmap { base, removed, added } => {
+                debug_assert!(removed.is_subset(base), "the removed rows are not base rows");
+                base.len().saturating_sub(removed.len()) + added.len()
+            }
+            RowIterable::Range(range) => range.len() as u64,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
     pub fn for_each<F: FnMut(u32) -> ()>(&self, mut f: F) {
         match self {
             RowIterable::RoaringBitmap(bitmap) => {
                 bitmap.iter().for_each(f);
             }
+            RowIterable::PatchedRoaringBitmap { base, removed, added } => {
+                // Merge the added rows to keep the rows sorted.
+                let mut added = added.iter().peekable();
+                for row in base.iter() {
+                    while let Some(added_row) = added.next_if(|added_row| *added_row < row) {
+                        f(added_row);
+                    }
+                    if !removed.contains(row) {
+                        f(row);
+                    }
+                }
+                added.for_each(f);
+            }
             RowIterable::Range(range) => {
                 for r in range.start..range.end {
                     f(r);
@@ -44,12 +79,13 @@ impl RowIterable {
 pub struct BitmapRowIterableProvider<'a> {
     accepted_values_by_field: HashMap<String, HashSet<u32>>,
     store: &'a Store,
+    /// The base rows matching the accepted values of each field.
+    matching_rows_by_field: Vec<(String, RoaringBitmap)>,
     initial_iterator: RoaringBitmap,
-    fields_with_sim: Vec<String>,
 }
 
 impl<'a> RowIterableProvider for BitmapRowIterableProvider<'a> {
-    fn get(&self, scenario: &str) -> RowIterable {
+    fn get(&self, scenario: &str) -> RowIterable<'_> {
         self.create(scenario)
     }
 }
@@ -60,90 +96,61 @@ impl<'a> BitmapRowIterableProvider<'a> {
             // The scenarios accepted values should be handled differently. This is a bug...
             panic!("Not expected {:?}", accepted_values_by_field);
         }
-        let mut fields_with_sim = Vec::new();
-        let bitmap = BitmapRowIterableProvider::create_initial_iterator(&accepted_values_by_field, store, &mut fields_with_sim);
+        // Lexical sort to have a deterministic order
+        let mut matching_rows_by_field: Vec<(String, RoaringBitmap)> = accepted_values_by_field.iter()
+            .map(|(field, values)| (field.clone(), store.get_matching_rows(field, values)))
+            .collect();
+        matching_rows_by_field.sort_by(|a, b| a.0.cmp(&b.0));
+        let initial_iterator = BitmapRowIterableProvider::intersect(matching_rows_by_field.iter().map(|(_, rows)| rows.clone()));
         BitmapRowIterableProvider {
             accepted_values_by_field,
             store,
-            initial_iterator: bitmap,
-            fields_with_sim,
+            matching_rows_by_field,
+            initial_iterator,
         }
     }
 
-    pub fn create(&self, scenario: &str) -> RowIterable {
-        if !self.fields_with_sim.is_empty() {
-            // Clone it because will be modified in-place
-            let mut bitmap = self.initial_iterator.clone();
-            BitmapRowIterableProvider::apply_conditions(&self.accepted_values_by_field, self.store, &mut bitmap, &self.fields_with_sim, scenario);
-            RowIterable::RoaringBitmap(bitmap)
-        } else {
-            RowIterable::RoaringBitmap(self.initial_iterator.clone())
-            // Box::new(RoaringBitmapIntIterableAdapter { bitmap: self.initial_iterator.clone() }) //FIXME can we avoid the cloning here? issue with lifetime if we remove it
-        }
-    }
-
-    fn create_initial_iterator(accepted_values_by_field: &HashMap<String, HashSet<u32>>, store: &'a Store, fields_with_sim: &mut Vec<String>) -> RoaringBitmap {
-        // Keep only the fields that are not simulated
-        let mut fields_without_sim = Vec::new();
-        for (field, _values) in accepted_values_by_field.iter() {
-            let c = store.vector_by_field_by_scenario
-                .iter()
-                .flat_map(|s| s.1.iter())
-                .filter(|e| e.0 == field)
-                .count();
-            if c > 1 {
-                fields_with_sim.push(field.clone());
-            } else {
-                fields_without_sim.push(field.clone());
+    /// Starts from the rows matching in the base scenario and only checks again the rows the
+    /// scenario overrides for one of the filtered fields, i.e. the rows of its row mappings.
+    pub fn create(&self, scenario: &str) -> RowIterable<'_> {
+        let mut overridden_rows = RoaringBitmap::new();
+        for (field, _) in self.matching_rows_by_field.iter() {
+            if let Some(rows) = self.store.get_overridden_rows(scenario, field) {
+                overridden_rows |= rows;
             }
         }
+        if overridden_rows.is_empty() {
+            return RowIterable::RoaringBitmap(&self.initial_iterator);
+        }
 
-        // Lexical sort to have a deterministic order
-        fields_without_sim.sort();
-        fields_with_sim.sort();
-
-        let mut res = Vec::new();
-        fields_without_sim.iter().for_each(|f| res.push(store.vector_by_field_by_scenario.get(MAIN_SCENARIO_NAME).unwrap().get(f).unwrap()));
-
-        let first_field = fields_without_sim.remove(0);
-        let reader = store.get_scenario_chunk_array(MAIN_SCENARIO_NAME, first_field.as_str());
-        // "cast" into BaseReader because we know the underlying type for MAIN_SCENARIO_NAME
-        // let r = if let ChunkArrayReader::BaseReader { base_array } = reader { Arc::&base_array } else { unreachable!() };
-        let mut bitmap = BitmapRowIterableProvider::initialize_bitmap(
-            store,
-            accepted_values_by_field.get(first_field.as_str()).unwrap(),
-            reader);
-        BitmapRowIterableProvider::apply_conditions(
-            accepted_values_by_field,
-            store,
-            &mut bitmap,
-            &fields_without_sim,
-            MAIN_SCENARIO_NAME);
-
-        bitmap
-    }
-
-    fn initialize_bitmap(store: &'a Store, accepted_values: &HashSet<u32>, vector: ChunkArrayReader) -> RoaringBitmap {
-        let mut matching_rows = RoaringBitmap::new();
-        for row in 0..*store.row_count.borrow() {
-            if accepted_values.contains(&vector.read::<UInt32Type>(row as u32)) {
-                matching_rows.insert(row as u32);
+        let columns: Vec<_> = self.matching_rows_by_field.iter()
+            .map(|(field, matching_rows)| (
+                self.accepted_values_by_field.get(field).unwrap(),
+                matching_rows,
+                self.store.get_overridden_rows(scenario, field).map(|rows| (rows, self.store.get_scenario_chunk_array(scenario, field))),
+            ))
+            .collect();
+        let mut removed = RoaringBitmap::new();
+        let mut added = RoaringBitmap::new();
+        for row in overridden_rows.iter() {
+            let matches = columns.iter().all(|(values, matching_rows, overridden)| match overridden {
+                Some((rows, column)) if rows.contains(row) => values.contains(&column.read::<UInt32Type>(row)),
+                _ => matching_rows.contains(row),
+            });
+            match (self.initial_iterator.contains(row), matches) {
+                (true, false) => { removed.insert(row); }
+                (false, true) => { added.insert(row); }
+                _ => {}
             }
         }
-        matching_rows
+        RowIterable::PatchedRoaringBitmap { base: &self.initial_iterator, removed, added }
     }
 
-    fn apply_conditions(accepted_values_by_field: &HashMap<String, HashSet<u32>>, store: &'a Store, bitmap: &mut RoaringBitmap, fields: &[String], scenario: &str) {
-        for field in fields {
-            let mut tmp = RoaringBitmap::new();
-            let values = accepted_values_by_field.get(field.as_str()).unwrap();
-            let column = store.get_scenario_chunk_array(scenario, field.as_str());
-            for row in bitmap.iter() {
-                if values.contains(&column.read::<UInt32Type>(row as u32)) {
-                    tmp.insert(row as u32);
-                }
-            }
-            bitmap.bitand_assign(tmp);
+    fn intersect<I: Iterator<Item=RoaringBitmap>>(mut bitmaps: I) -> RoaringBitmap {
+        let mut bitmap = bitmaps.next().unwrap_or_default();
+        for other in bitmaps {
+            bitmap &= other;
         }
+        bitmap
     }
-}
\ No newline at end of file
+}
@@ -30,6 +30,41 @@ impl ChunkArrayReader {
         }
     }
 
+    /// Replaces the content of `values` with the values of the given rows. The values of the base
+    /// array are copied from a typed slice, in one copy when the rows are contiguous. The rows must
+    /// be strictly increasing, as the row providers give them: the first and the last rows tell
+    /// whether they are contiguous.
+    pub fn gather<T: ArrowPrimitiveType>(&self, rows: &[u32], values: &mut Vec<T::Native>) {
+        debug_assert!(rows.windows(2).all(|w| w[0] < w[1]), "the rows are not strictly increasing");
+        values.clear();
+        match self {
+            ChunkArrayReader::BaseReader { base_array } => {
+                let array_ref = base_array.array.borrow();
+                let base_values = ChunkArrayReader::values::<T>(array_ref.as_ref().unwrap());
+                match (rows.first(), rows.last()) {
+                    (Some(first), Some(last)) if (last - first) as usize == rows.len() - 1 => {
+                        values.extend_from_slice(&base_values[*first as usize..=*last as usize]);
+                    }
+                    _ => values.extend(rows.iter().map(|row| base_values[*row as usize])),
+                }
+            }
+            ChunkArrayReader::ScenarioReader { base_array, scenario_array, scenario: _, row_mapping } => {
+                let base_ref = base_array.array.borrow();
+                let base_values = ChunkArrayReader::values::<T>(base_ref.as_ref().unwrap());
+                let scenario_ref = scenario_array.array.borrow();
+                let scenario_values = ChunkArrayReader::values::<T>(scenario_ref.as_ref().unwrap());
+                values.extend(rows.iter().map(|row| match row_mapping.get(row) {
+                    None => base_values[*row as usize],
+                    Some(scenario_row) => scenario_values[scenario_row as usize],
+                }));
+            }
+        }
+    }
+
+    fn values<T: ArrowPrimitiveType>(array: &ArrayRef) -> &[T::Native] {
+        array.as_any().downcast_ref::<PrimitiveArray<T>>().unwrap().values()
+    }
+
     fn read_array_at_position<T: ArrowPrimitiveType>(array: &ChunkArray, row: u32) -> T::Native {
         let array_ref = array.array.borrow();
         let array = array_ref.as_ref().unwrap().as_any().downcast_ref::<PrimitiveArray<T>>().unwrap();
@@ -0,0 +1,477 @@
+use std::error::Error;
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Instant;
+
+use arrow::datatypes::DataType;
+use comfy_table::Table;
+
+use crate::csv_loader::CsvLoader;
+use crate::datastore::{SCENARIO_FIELD_NAME, Store};
+use crate::having::{Comparison, Having};
+use crate::order_by::OrderBy;
+use crate::parquet_loader::ParquetLoader;
+use crate::query::Query;
+use crate::query_engine::QueryEngine;
+use crate::result_writer::ResultWriter;
+use crate::sql;
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+pub const HELP: &str = "\
+\\open csv <directory> <key field>   create a store from the csv files of a directory
+  [--type <field>=<type>]...        with the type of a field instead of the inferred one,
+                                    e.g. --type Quantity=UInt32 to aggregate the quantities
+\\open parquet <file> <key field>    create a store from the base scenario parquet file
+\\load <scenario> <file>             load a csv or parquet file as a new scenario
+\\scenarios                          list the scenarios
+\\fields                             list the fields
+\\members <field>                    list the values of a Utf8 field
+\\timing [on|off]                    print the execution time of the queries
+\\format [table|csv|json|jsonl]      set the output format of the results
+\\explain <query>                    print how a query would be executed
+\\analyze <query>                    execute a query and print how it was executed
+\\help                               print this help
+\\quit                               exit
+
+Anything else is a query, written in sql:
+  SELECT scenario, CategoryName, sum(Price) FROM store GROUP BY scenario, CategoryName
+in json, see Query, or with the builder syntax:
+  add_wildcard_coordinate(\"scenario\").add_aggregated_measure(\"Price\", \"sum\")";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Table,
+    Csv,
+    Json,
+    JsonLines,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "table" => Ok(OutputFormat::Table),
+            "csv" => Ok(OutputFormat::Csv),
+            "json" => Ok(OutputFormat::Json),
+            "jsonl" => Ok(OutputFormat::JsonLines),
+            _ => Err(format!("unknown format '{}', expected table, csv, json or jsonl", s)),
+        }
+    }
+}
+
+/// What the caller should do after a line has been executed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Control {
+    Continue,
+    Quit,
+}
+
+/// Executes the lines typed in the interactive shell. The meta commands start with a backslash,
+/// see [`HELP`], anything else is a query.
+pub struct Cli {
+    store: Option<Store>,
+    format: OutputFormat,
+    timing: bool,
+}
+
+impl Cli {
+    pub fn new() -> Cli {
+        Cli { store: None, format: OutputFormat::Table, timing: false }
+    }
+
+    pub fn with_store(store: Store) -> Cli {
+        Cli { store: Some(store), format: OutputFormat::Table, timing: false }
+    }
+
+    pub fn execute<W: Write>(&mut self, line: &str, out: &mut W) -> Result<Control> {
+        let line = line.trim();
+        if line.is_empty() {
+            return Ok(Control::Continue);
+        }
+        if !line.starts_with('\\') {
+            self.query(line, out)?;
+            return Ok(Control::Continue);
+        }
+
+        if let Some((command, query)) = line[1..].split_once(char::is_whitespace) {
+            if command == "explain" || command == "analyze" {
+                self.explain(query.trim(), command == "analyze", out)?;
+                return Ok(Control::Continue);
+            }
+        }
+
+        let args: Vec<&str> = line[1..].split_whitespace().collect();
+        match args.as_slice() {
+            ["q"] | ["quit"] => return Ok(Control::Quit),
+            ["?"] | ["help"] => writeln!(out, "{}", HELP)?,
+            ["open", "csv", directory, key_field, options @ ..] => {
+                self.store = Some(csv_loader(options)?.create_store(directory, key_field)?);
+                self.scenarios(out)?;
+            }
+            ["open", "parquet", path, key_field] => {
+                self.store = Some(ParquetLoader::create_store(path, key_field)?);
+                self.scenarios(out)?;
+            }
+            ["load", scenario, path] => self.load(scenario, Path::new(path))?,
+            ["scenarios"] => self.scenarios(out)?,
+            ["fields"] => self.fields(out)?,
+            ["members", field] => self.members(field, out)?,
+            ["timing"] => self.timing = !self.timing,
+            ["timing", "on"] => self.timing = true,
+            ["timing", "off"] => self.timing = false,
+            ["format"] => writeln!(out, "{:?}", self.format)?,
+            ["format", format] => self.format = format.parse()?,
+            _ => return Err(format!("unknown command '{}', type \\help for the list of commands", line).into()),
+        }
+        if let ["timing", ..] = args.as_slice() {
+            writeln!(out, "Timing is {}.", if self.timing { "on" } else { "off" })?;
+        }
+        Ok(Control::Continue)
+    }
+
+    fn store(&self) -> Result<&Store> {
+        self.store.as_ref().ok_or_else(|| "no store, open one with \\open".into())
+    }
+
+    /// Loads the file as a new scenario. The loaders check its rows with
+    /// [`Store::prepare_batch`], invalid rows are reported and nothing is loaded.
+    fn load(&mut self, scenario: &str, path: &Path) -> Result<()> {
+        let store = self.store.as_mut().ok_or("no store, open one with \\open")?;
+        if store.has_scenario(scenario) {
+            return Err(format!("scenario '{}' is already loaded", scenario).into());
+        }
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("csv") => CsvLoader::new().load(store, scenario, path)?,
+            Some("parquet") => ParquetLoader::new(store).load(scenario, path)?,
+            _ => return Err(format!("cannot load {}, expected a .csv or a .parquet file", path.display()).into()),
+        }
+        Ok(())
+    }
+
+    fn scenarios<W: Write>(&self, out: &mut W) -> Result<()> {
+        self.members(SCENARIO_FIELD_NAME, out)
+    }
+
+    fn fields<W: Write>(&self, out: &mut W) -> Result<()> {
+        let store = self.store()?;
+        let key_field = store.key_field().name();
+        let mut table = Table::new();
+        table.set_header(vec!["field", "type", "key"]);
+        for field in store.schema().fields() {
+            let key = if field.name() == key_field { "yes" } else { "" };
+            table.add_row(vec![field.name().to_string(), field.data_type().to_string(), key.to_string()]);
+        }
+        writeln!(out, "{}", table)?;
+        Ok(())
+    }
+
+    fn members<W: Write>(&self, field: &str, out: &mut W) -> Result<()> {
+        let store = self.store()?;
+        let dictionary = store.dictionary_provider.dicos.get(field)
+            .ok_or_else(|| format!("'{}' is not a Utf8 field", field))?;
+        for position in 0..dictionary.size() as u32 {
+            writeln!(out, "{}", dictionary.read(&position).unwrap())?;
+        }
+        Ok(())
+    }
+
+    /// Parses a query written in json, in sql or with the builder syntax, and validates it.
+    fn parse(&self, text: &str) -> Result<Query> {
+        let store = self.store()?;
+        let query = if text.starts_with('{') {
+            serde_json::from_str(text)?
+        } else if text.get(..6).is_some_and(|keyword| keyword.eq_ignore_ascii_case("select")) {
+            sql::parse_query(text, store)?
+        } else {
+            parse_builder(text)?
+        };
+        if let Err(errors) = query.validate(store) {
+            let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+            return Err(errors.join("\n").into());
+        }
+        Ok(query)
+    }
+
+    /// Prints the plan of a query, the query is executed when `analyze` is set.
+    fn explain<W: Write>(&self, text: &str, analyze: bool, out: &mut W) -> Result<()> {
+        let query = self.parse(text)?;
+        let engine = QueryEngine::new(self.store()?);
+        let plan = if analyze { engine.analyze(&query).1 } else { engine.explain(&query) };
+        writeln!(out, "{}", plan)?;
+        Ok(())
+    }
+
+    fn query<W: Write>(&self, text: &str, out: &mut W) -> Result<()> {
+        let store = self.store()?;
+        let start = Instant::now();
+        let query = self.parse(text)?;
+        let engine = QueryEngine::new(store);
+        let result = engine.execute(&query);
+        let elapsed = start.elapsed();
+
+        let writer = ResultWriter::new();
+        match self.format {
+            OutputFormat::Table => writeln!(out, "{}", result)?,
+            OutputFormat::Csv => writer.write_csv(&result, &mut *out)?,
+            OutputFormat::Json => {
+                writer.write_json(&result, &mut *out)?;
+                writeln!(out)?;
+            }
+            OutputFormat::JsonLines => writer.write_json_lines(&result, &mut *out)?,
+        }
+        if self.timing {
+            writeln!(out, "Time: {:.3} ms", elapsed.as_secs_f64() * 1000f64)?;
+        }
+        Ok(())
+    }
+}
+
+/// A csv loader with the type overrides of the `--type <field>=<type>` options.
+fn csv_loader(options: &[&str]) -> Result<CsvLoader> {
+    let mut loader = CsvLoader::new();
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        let value = match (*option, options.next()) {
+            ("--type", Some(value)) => value,
+            _ => return Err(format!("unknown option '{}', expected --type <field>=<type>", option).into()),
+        };
+        let (field, name) = value.split_once('=')
+            .ok_or_else(|| format!("invalid type override '{}', expected <field>=<type>", value))?;
+        let data_type = [DataType::UInt64, DataType::UInt32, DataType::Int64, DataType::Float64, DataType::Utf8].into_iter()
+            .find(|data_type| data_type.to_string().eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("unknown type '{}', expected UInt64, UInt32, Int64, Float64 or Utf8", name))?;
+        loader.add_type_override(field, data_type);
+    }
+    Ok(loader)
+}
+
+impl Default for Cli {
+    fn default() -> Self {
+        Cli::new()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Number(String),
+    Punct(char),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Arg {
+    Str(String),
+    Number(String),
+    Ident(String),
+    List(Vec<Arg>),
+    Call(String, Vec<Arg>),
+}
+
+/// Parses the calls of the [`Query`] builder, e.g.
+/// `Query::new().add_wildcard_coordinate("scenario").add_aggregated_measure("Price", "sum")`.
+/// The lists are written `["a", "b"]` or `vec!["a", "b"]` and `Query::new()` can be omitted.
+pub fn parse_builder(text: &str) -> std::result::Result<Query, String> {
+    let tokens = tokenize(text)?;
+    let mut parser = BuilderParser { tokens, position: 0 };
+    let mut query = Query::new();
+    if parser.peek() == Some(&Token::Ident("Query::new".to_string())) {
+        parser.position += 1;
+        parser.expect('(')?;
+        parser.expect(')')?;
+    }
+    while parser.peek().is_some() {
+        if parser.eat('.') {
+            continue;
+        }
+        if parser.eat(';') && parser.peek().is_none() {
+            break;
+        }
+        let (method, args) = match parser.arg()? {
+            Arg::Call(method, args) => (method, args),
+            arg => return Err(format!("expected a method call, found {:?}", arg)),
+        };
+        apply(&mut query, &method, &args)?;
+    }
+    Ok(query)
+}
+
+fn apply(query: &mut Query, method: &str, args: &[Arg]) -> std::result::Result<(), String> {
+    match (method, args) {
+        ("add_wildcard_coordinate", [Arg::Str(field)]) => {
+            query.add_wildcard_coordinate(field);
+        }
+        ("add_coordinates", [Arg::Str(field), Arg::List(values)]) => {
+            let values = values.iter().map(as_str).collect::<std::result::Result<Vec<&str>, String>>()?;
+            query.add_coordinates(field, values);
+        }
+        ("add_aggregated_measure", [Arg::Str(field), Arg::Str(agg)]) => {
+            query.add_aggregated_measure(field, agg);
+        }
+        ("add_order_by", [Arg::Call(function, order_args)]) => {
+            let order_by = match (function.as_str(), order_args.as_slice()) {
+                ("OrderBy::asc", [Arg::Str(field)]) => OrderBy::asc(field),
+                ("OrderBy::desc", [Arg::Str(field)]) => OrderBy::desc(field),
+                _ => return Err(format!("expected OrderBy::asc(\"field\") or OrderBy::desc(\"field\"), found {}", function)),
+            };
+            query.add_order_by(order_by);
+        }
+        ("add_having", [Arg::Call(function, having_args)]) => {
+            let (alias, comparison, value) = match having_args.as_slice() {
+                [Arg::Str(alias), Arg::Ident(comparison), Arg::Number(value)] => (alias, comparison, value),
+                _ => return Err(format!("expected {}(\"alias\", Comparison::Gt, 0), found {:?}", function, having_args)),
+            };
+            let comparison = match comparison.as_str() {
+                "Comparison::Eq" => Comparison::Eq,
+                "Comparison::NotEq" => Comparison::NotEq,
+                "Comparison::Lt" => Comparison::Lt,
+                "Comparison::LtEq" => Comparison::LtEq,
+                "Comparison::Gt" => Comparison::Gt,
+                "Comparison::GtEq" => Comparison::GtEq,
+                _ => return Err(format!("unknown comparison {}", comparison)),
+            };
+            let value: f64 = value.parse().map_err(|_| format!("'{}' is not a number", value))?;
+            let having = match function.as_str() {
+                "Having::new" => Having::new(alias, comparison, value),
+                "Having::delta" => Having::delta(alias, comparison, value),
+                _ => return Err(format!("expected Having::new or Having::delta, found {}", function)),
+            };
+            query.add_having(having);
+        }
+        ("set_top_n_per_group", [Arg::List(group_by), Arg::Number(n)]) => {
+            let group_by = group_by.iter().map(as_str).collect::<std::result::Result<Vec<&str>, String>>()?;
+            query.set_top_n_per_group(group_by, as_count(n)?);
+        }
+        ("set_limit", [Arg::Number(n)]) => {
+            query.set_limit(as_count(n)?);
+        }
+        ("set_offset", [Arg::Number(n)]) => {
+            query.set_offset(as_count(n)?);
+        }
+        _ => return Err(format!("unknown method or wrong arguments: {}({:?})", method, args)),
+    }
+    Ok(())
+}
+
+fn as_str(arg: &Arg) -> std::result::Result<&str, String> {
+    match arg {
+        Arg::Str(s) => Ok(s),
+        _ => Err(format!("expected a string, found {:?}", arg)),
+    }
+}
+
+fn as_count(n: &str) -> std::result::Result<usize, String> {
+    n.parse().map_err(|_| format!("expected a positive integer, found {}", n))
+}
+
+fn tokenize(text: &str) -> std::result::Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '"' {
+            chars.next();
+            let mut s = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => s.extend(chars.next()),
+                    Some(c) => s.push(c),
+                    None => return Err("unterminated string".to_string()),
+                }
+            }
+            tokens.push(Token::Str(s));
+        } else if c.is_ascii_digit() || c == '-' {
+            let mut n = String::new();
+            while let Some(&c) = chars.peek().filter(|c| c.is_ascii_digit() || **c == '.' || **c == '-') {
+                n.push(c);
+                chars.next();
+            }
+            tokens.push(Token::Number(n));
+        } else if c.is_alphabetic() || c == '_' {
+            let mut ident = String::new();
+            while let Some(&c) = chars.peek().filter(|c| c.is_alphanumeric() || **c == '_' || **c == ':') {
+                ident.push(c);
+                chars.next();
+            }
+            tokens.push(Token::Ident(ident));
+        } else if "()[],.!;".contains(c) {
+            tokens.push(Token::Punct(c));
+            chars.next();
+        } else {
+            return Err(format!("unexpected character '{}'", c));
+        }
+    }
+    Ok(tokens)
+}
+
+struct BuilderParser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl BuilderParser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn eat(&mut self, punct: char) -> bool {
+        if self.peek() == Some(&Token::Punct(punct)) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, punct: char) -> std::result::Result<(), String> {
+        if self.eat(punct) {
+            Ok(())
+        } else {
+            Err(format!("expected '{}', found {:?}", punct, self.peek()))
+        }
+    }
+
+    fn arg(&mut self) -> std::result::Result<Arg, String> {
+        let token = self.peek().cloned().ok_or("unexpected end of input")?;
+        self.position += 1;
+        match token {
+            Token::Str(s) => Ok(Arg::Str(s)),
+            Token::Number(n) => Ok(Arg::Number(n)),
+            Token::Punct('[') => Ok(Arg::List(self.args(']')?)),
+            Token::Ident(ident) if ident == "vec" => {
+                self.expect('!')?;
+                self.expect('[')?;
+                Ok(Arg::List(self.args(']')?))
+            }
+            Token::Ident(ident) => {
+                if self.eat('(') {
+                    Ok(Arg::Call(ident, self.args(')')?))
+                } else {
+                    Ok(Arg::Ident(ident))
+                }
+            }
+            Token::Punct(c) => Err(format!("unexpected '{}'", c)),
+        }
+    }
+
+    /// The comma separated arguments up to the closing character, which is consumed.
+    fn args(&mut self, close: char) -> std::result::Result<Vec<Arg>, String> {
+        let mut args = Vec::new();
+        while !self.eat(close) {
+            if !args.is_empty() {
+                self.expect(',')?;
+                // Trailing comma.
+                if self.eat(close) {
+                    break;
+                }
+            }
+            args.push(self.arg()?);
+        }
+        Ok(args)
+    }
+}
@@ -0,0 +1,340 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use arrow::array::{ArrayRef, Float64Builder, Int64Builder, StringBuilder, UInt32Builder, UInt64Builder};
+use arrow::csv::reader::infer_file_schema;
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::error::ArrowError;
+use arrow::record_batch::RecordBatch;
+
+use crate::datastore::{CHUNK_DEFAULT_SIZE, MAIN_SCENARIO_NAME, Store};
+
+pub const SCENARIO_PLACEHOLDER: &str = "{scenario}";
+pub const DEFAULT_FILE_PATTERN: &str = "data_{scenario}_scenario.csv";
+
+/// A row that could not be parsed. `line` is the line number in the file, the header being line 1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MalformedRow {
+    pub line: u64,
+    pub message: String,
+}
+
+#[derive(Debug)]
+pub enum CsvLoaderError {
+    Io(std::io::Error),
+    Csv(csv::Error),
+    Arrow(ArrowError),
+    Schema(String),
+    /// Nothing is loaded from a file containing malformed rows.
+    MalformedRows { path: PathBuf, rows: Vec<MalformedRow> },
+}
+
+impl fmt::Display for CsvLoaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CsvLoaderError::Io(e) => write!(f, "io error: {}", e),
+            CsvLoaderError::Csv(e) => write!(f, "csv error: {}", e),
+            CsvLoaderError::Arrow(e) => write!(f, "arrow error: {}", e),
+            CsvLoaderError::Schema(message) => write!(f, "schema error: {}", message),
+            CsvLoaderError::MalformedRows { path, rows } => {
+                write!(f, "{} malformed row(s) in {}", rows.len(), path.display())?;
+                for row in rows {
+                    write!(f, "\n  line {}: {}", row.line, row.message)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for CsvLoaderError {}
+
+impl From<std::io::Error> for CsvLoaderError {
+    fn from(e: std::io::Error) -> Self {
+        CsvLoaderError::Io(e)
+    }
+}
+
+impl From<csv::Error> for CsvLoaderError {
+    fn from(e: csv::Error) -> Self {
+        CsvLoaderError::Csv(e)
+    }
+}
+
+impl From<ArrowError> for CsvLoaderError {
+    fn from(e: ArrowError) -> Self {
+        CsvLoaderError::Arrow(e)
+    }
+}
+
+/// Loads csv files into a [`Store`]. The schema is inferred from the base file: integers become
+/// Int64, decimals Float64 and everything else Utf8. The inferred type of a field can be overridden
+/// e.g. to UInt32 to be able to aggregate it.
+///
+/// The scenario of a file is extracted from its name with a pattern containing `{scenario}`, by
+/// default `data_{scenario}_scenario.csv`.
+pub struct CsvLoader {
+    delimiter: u8,
+    file_pattern: String,
+    max_read_records: Option<usize>,
+    type_overrides: HashMap<String, DataType>,
+}
+
+impl CsvLoader {
+    pub fn new() -> CsvLoader {
+        CsvLoader {
+            delimiter: b',',
+            file_pattern: DEFAULT_FILE_PATTERN.to_string(),
+            max_read_records: None,
+            type_overrides: HashMap::new(),
+        }
+    }
+
+    pub fn set_delimiter(&mut self, delimiter: u8) -> &mut CsvLoader {
+        self.delimiter = delimiter;
+        self
+    }
+
+    pub fn set_file_pattern(&mut self, file_pattern: &str) -> &mut CsvLoader {
+        self.file_pattern = file_pattern.to_string();
+        self
+    }
+
+    /// Limits the number of records read to infer the schema. The whole file is read by default.
+    pub fn set_max_read_records(&mut self, max_read_records: usize) -> &mut CsvLoader {
+        self.max_read_records = Some(max_read_records);
+        self
+    }
+
+    pub fn add_type_override(&mut self, field: &str, data_type: DataType) -> &mut CsvLoader {
+        self.type_overrides.insert(field.to_string(), data_type);
+        self
+    }
+
+    pub fn infer_schema<P: AsRef<Path>>(&self, path: P) -> Result<Schema, CsvLoaderError> {
+        let mut file = File::open(path)?;
+        let (inferred, _) = infer_file_schema(&mut file, self.delimiter, self.max_read_records, true)?;
+        let mut fields = Vec::with_capacity(inferred.fields().len());
+        for field in inferred.fields() {
+            let data_type = match self.type_overrides.get(field.name()) {
+                Some(data_type) => data_type.clone(),
+                None => match field.data_type() {
+                    DataType::Int64 | DataType::Float64 => field.data_type().clone(),
+                    _ => DataType::Utf8,
+                },
+            };
+            if !Store::is_supported_type(&data_type) {
+                return Err(CsvLoaderError::Schema(format!("type {} of field '{}' is not supported", data_type, field.name())));
+            }
+            fields.push(Field::new(field.name(), data_type, false));
+        }
+        for field in self.type_overrides.keys() {
+            if !fields.iter().any(|f| f.name() == field) {
+                return Err(CsvLoaderError::Schema(format!("cannot override the type of unknown field '{}'", field)));
+            }
+        }
+        Ok(Schema::new(fields))
+    }
+
+    /// Lists the files of the directory matching the pattern. The base scenario comes first, the
+    /// other ones are sorted by name.
+    pub fn scenario_files<P: AsRef<Path>>(&self, directory: P) -> Result<Vec<(String, PathBuf)>, CsvLoaderError> {
+        let (prefix, suffix) = self.file_pattern.split_once(SCENARIO_PLACEHOLDER)
+            .ok_or_else(|| CsvLoaderError::Schema(format!("pattern '{}' does not contain {}", self.file_pattern, SCENARIO_PLACEHOLDER)))?;
+
+        let mut files = Vec::new();
+        for entry in fs::read_dir(directory)? {
+            let path = entry?.path();
+            let name = match path.file_name().and_then(|n| n.to_str()) {
+                Some(name) => name,
+                None => continue,
+            };
+            if name.len() > prefix.len() + suffix.len() && name.starts_with(prefix) && name.ends_with(suffix) {
+                let scenario = name[prefix.len()..name.len() - suffix.len()].to_string();
+                files.push((scenario, path));
+            }
+        }
+        files.sort_by(|a, b| (a.0 != MAIN_SCENARIO_NAME, &a.0).cmp(&(b.0 != MAIN_SCENARIO_NAME, &b.0)));
+        Ok(files)
+    }
+
+    /// Creates a store whose schema is inferred from the base file of the directory and loads all
+    /// the scenario files into it.
+    pub fn create_store<P: AsRef<Path>>(&self, directory: P, key_field: &str) -> Result<Store, CsvLoaderError> {
+        let files = self.scenario_files(directory)?;
+        let base_file = match files.first() {
+            Some((scenario, path)) if scenario == MAIN_SCENARIO_NAME => path,
+            _ => return Err(CsvLoaderError::Schema(format!("cannot find a file for the {} scenario", MAIN_SCENARIO_NAME))),
+        };
+
+        let schema = self.infer_schema(base_file)?;
+        let key_index = schema.index_of(key_field)?;
+        if *schema.field(key_index).data_type() != DataType::Int64 {
+            return Err(CsvLoaderError::Schema(format!("key field '{}' must be of type {}", key_field, DataType::Int64)));
+        }
+
+        let mut store = Store::new(Arc::new(schema), vec![key_index as u32], CHUNK_DEFAULT_SIZE as u32);
+        for (scenario, path) in files.iter() {
+            self.load(&mut store, scenario, path)?;
+        }
+        Ok(store)
+    }
+
+    /// Loads every file of the directory matching the pattern and returns the loaded scenarios.
+    pub fn load_directory<P: AsRef<Path>>(&self, store: &mut Store, directory: P) -> Result<Vec<String>, CsvLoaderError> {
+        let files = self.scenario_files(directory)?;
+        for (scenario, path) in files.iter() {
+            self.load(store, scenario, path)?;
+        }
+        Ok(files.into_iter().map(|(scenario, _)| scenario).collect())
+    }
+
+    /// Reads the file with the store schema. The columns are matched by name with the header.
+    pub fn read<P: AsRef<Path>>(&self, schema: &Schema, path: P) -> Result<RecordBatch, CsvLoaderError> {
+        let path = path.as_ref();
+        self.read_from(schema, File::open(path)?, path)
+    }
+
+    /// Same as [`CsvLoader::read`] for any reader. `path` only names the data in the errors.
+    pub fn read_from<R: io::Read>(&self, schema: &Schema, reader: R, path: &Path) -> Result<RecordBatch, CsvLoaderError> {
+        let mut reader = csv::ReaderBuilder::new()
+            .delimiter(self.delimiter)
+            .flexible(true)
+            .from_reader(reader);
+
+        let headers = reader.headers()?.clone();
+        let mut indices = Vec::with_capacity(schema.fields().len());
+        for field in schema.fields() {
+            let index = headers.iter().position(|h| h == field.name())
+                .ok_or_else(|| CsvLoaderError::Schema(format!("cannot find column '{}' in {}", field.name(), path.display())))?;
+            indices.push(index);
+        }
+
+        let mut builders: Vec<ColumnBuilder> = schema.fields().iter().map(|f| ColumnBuilder::new(f.data_type())).collect();
+        let mut malformed_rows = Vec::new();
+        for record in reader.records() {
+            let record = match record {
+                Ok(record) => record,
+                Err(e) => {
+                    let line = e.position().map(|p| p.line()).unwrap_or(0);
+                    malformed_rows.push(MalformedRow { line, message: e.to_string() });
+                    continue;
+                }
+            };
+            let line = record.position().map(|p| p.line()).unwrap_or(0);
+            if record.len() != headers.len() {
+                malformed_rows.push(MalformedRow {
+                    line,
+                    message: format!("expected {} fields but found {}", headers.len(), record.len()),
+                });
+                continue;
+            }
+
+            // Parse the whole row before appending anything so that a malformed row is skipped entirely.
+            let mut values = Vec::with_capacity(indices.len());
+            let mut error = None;
+            for (field, index) in schema.fields().iter().zip(indices.iter()) {
+                match Value::parse(field.data_type(), &record[*index]) {
+                    Ok(value) => values.push(value),
+                    Err(message) => {
+                        error = Some(format!("{} for field '{}'", message, field.name()));
+                        break;
+                    }
+                }
+            }
+            match error {
+                Some(message) => malformed_rows.push(MalformedRow { line, message }),
+                None => builders.iter_mut().zip(values).for_each(|(b, v)| b.append(v)),
+            }
+        }
+
+        if !malformed_rows.is_empty() {
+            return Err(CsvLoaderError::MalformedRows { path: path.to_path_buf(), rows: malformed_rows });
+        }
+        let columns: Vec<ArrayRef> = builders.into_iter().map(|b| b.finish()).collect();
+        Ok(RecordBatch::try_new(Arc::new(schema.clone()), columns)?)
+    }
+
+    /// Loads the file as a scenario. The rows are checked with [`Store::prepare_batch`] first, a
+    /// key missing from the base scenario is an error.
+    pub fn load<P: AsRef<Path>>(&self, store: &mut Store, scenario: &str, path: P) -> Result<(), CsvLoaderError> {
+        let batch = self.read(&store.schema(), path)?;
+        let batch = store.prepare_batch(scenario, &batch)?;
+        store.load(scenario, &batch);
+        Ok(())
+    }
+}
+
+impl Default for CsvLoader {
+    fn default() -> Self {
+        CsvLoader::new()
+    }
+}
+
+enum Value<'a> {
+    UInt64(u64),
+    UInt32(u32),
+    Int64(i64),
+    Float64(f64),
+    Utf8(&'a str),
+}
+
+impl<'a> Value<'a> {
+    fn parse(data_type: &DataType, s: &'a str) -> Result<Value<'a>, String> {
+        let parsed = match data_type {
+            DataType::UInt64 => s.parse().map(Value::UInt64).ok(),
+            DataType::UInt32 => s.parse().map(Value::UInt32).ok(),
+            DataType::Int64 => s.parse().map(Value::Int64).ok(),
+            DataType::Float64 => s.parse().map(Value::Float64).ok(),
+            _ => return Ok(Value::Utf8(s)),
+        };
+        parsed.ok_or_else(|| format!("cannot parse '{}' as {}", s, data_type))
+    }
+}
+
+enum ColumnBuilder {
+    UInt64(UInt64Builder),
+    UInt32(UInt32Builder),
+    Int64(Int64Builder),
+    Float64(Float64Builder),
+    Utf8(StringBuilder),
+}
+
+impl ColumnBuilder {
+    fn new(data_type: &DataType) -> ColumnBuilder {
+        let capacity = CHUNK_DEFAULT_SIZE;
+        match data_type {
+            DataType::UInt64 => ColumnBuilder::UInt64(UInt64Builder::new(capacity)),
+            DataType::UInt32 => ColumnBuilder::UInt32(UInt32Builder::new(capacity)),
+            DataType::Int64 => ColumnBuilder::Int64(Int64Builder::new(capacity)),
+            DataType::Float64 => ColumnBuilder::Float64(Float64Builder::new(capacity)),
+            _ => ColumnBuilder::Utf8(StringBuilder::new(capacity)),
+        }
+    }
+
+    fn append(&mut self, value: Value) {
+        match (self, value) {
+            (ColumnBuilder::UInt64(b), Value::UInt64(v)) => b.append_value(v).unwrap(),
+            (ColumnBuilder::UInt32(b), Value::UInt32(v)) => b.append_value(v).unwrap(),
+            (ColumnBuilder::Int64(b), Value::Int64(v)) => b.append_value(v).unwrap(),
+            (ColumnBuilder::Float64(b), Value::Float64(v)) => b.append_value(v).unwrap(),
+            (ColumnBuilder::Utf8(b), Value::Utf8(v)) => b.append_value(v).unwrap(),
+            _ => unreachable!(),
+        }
+    }
+
+    fn finish(self) -> ArrayRef {
+        match self {
+            ColumnBuilder::UInt64(mut b) => Arc::new(b.finish()),
+            ColumnBuilder::UInt32(mut b) => Arc::new(b.finish()),
+            ColumnBuilder::Int64(mut b) => Arc::new(b.finish()),
+            ColumnBuilder::Float64(mut b) => Arc::new(b.finish()),
+            ColumnBuilder::Utf8(mut b) => Arc::new(b.finish()),
+        }
+    }
+}
@@ -1,19 +1,23 @@
-use std::borrow::BorrowMut;
 use crate::chunk_array::{ChunkArray, ChunkArrayReader};
-use crate::row_mapping::{IdentityMapping, IntIntMapRowMapping, RowMapping};
-use arrow::array::{Array, ArrayBuilder, ArrayRef, Float64Builder, Int64Array, Int64Builder, PrimitiveArray, PrimitiveBuilder, StringArray, UInt32Array, UInt32Builder, UInt64Builder};
+use crate::row_mapping::{IdentityMapping, RowMapping, RowMappingFactory, RowMappingSharing};
+use arrow::array::{Array, ArrayRef, Float64Builder, Int64Array, Int64Builder, PrimitiveArray, PrimitiveBuilder, StringArray, UInt32Array, UInt32Builder, UInt64Builder};
 use arrow::datatypes::{ArrowPrimitiveType, DataType, Field, Float64Type, Int64Type, Schema, SchemaRef, UInt32Type, UInt64Type};
+use arrow::compute::{cast, concat, take};
+use arrow::error::ArrowError;
 use arrow::record_batch::RecordBatch;
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
 
 
 use std::sync::Arc;
+use roaring::RoaringBitmap;
 use crate::chunk_array::ChunkArrayReader::{BaseReader, ScenarioReader};
 
 
+use crate::aggregate_table::{AggregateTable, AggregateTableDefinition};
 use crate::dictionary_provider::{Dictionary, DictionaryProvider};
+use crate::query::{Query, ValidationError};
 
 pub const MAIN_SCENARIO_NAME: &str = "base";
 pub const SCENARIO_FIELD_NAME: &str = "scenario";
@@ -29,6 +33,27 @@ pub struct Store {
     pub row_mapping_by_field_by_scenario: HashMap<String, HashMap<String, Arc<dyn RowMapping>>>,
     pub dictionary_provider: DictionaryProvider,
     pub primary_index: HashMap<i64, u64>, // FIXME it assumes the key is a i64. Should be generic.
+    /// The base rows of each dictionary position, by dictionary encoded field.
+    pub bitmap_index_by_field: HashMap<String, Vec<RoaringBitmap>>,
+    /// The rows whose value differs from the base scenario.
+    pub overridden_rows_by_field_by_scenario: HashMap<String, HashMap<String, RoaringBitmap>>,
+    version: u64,
+    changes: Vec<StoreChange>,
+    aggregate_tables: Vec<AggregateTable>,
+}
+
+/// A load of the store, see [`Store::changes_since`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoreChange {
+    /// The version of the store after the load.
+    pub version: u64,
+    pub scenario: String,
+    /// The fields whose values may have changed: all of them for the base scenario, the ones the
+    /// scenario modifies before or after the load otherwise.
+    pub fields: HashSet<String>,
+    /// Whether the load added the scenario, or changed whether the queries on all the scenarios
+    /// list it: they only list the scenarios modifying some values.
+    pub listing_changed: bool,
 }
 
 impl Store {
@@ -61,12 +86,18 @@ impl Store {
             row_mapping_by_field_by_scenario,
             dictionary_provider: DictionaryProvider::new(),
             primary_index: HashMap::new(),
+            bitmap_index_by_field: HashMap::new(),
+            overridden_rows_by_field_by_scenario: HashMap::new(),
+            version: 0,
+            changes: Vec::new(),
+            aggregate_tables: Vec::new(),
         }
     }
 
     pub fn get_scenario_chunk_array(&self, scenario: &str, field: &str) -> ChunkArrayReader {
         let base_array = self.vector_by_field_by_scenario.get(MAIN_SCENARIO_NAME).unwrap().get(field);
-        let scenario_array = self.vector_by_field_by_scenario.get(scenario).unwrap().get(field);
+        // A scenario that does not change any value has no vectors.
+        let scenario_array = self.vector_by_field_by_scenario.get(scenario).and_then(|vectors| vectors.get(field));
         match scenario_array {
             None => {
                 BaseReader {
@@ -95,103 +126,176 @@ impl Store {
         ChunkArray::new(field, array_size)
     }
 
+    /// Loads a scenario. A scenario other than the base one can be loaded again, its new values
+    /// replace the previous ones. Loading the base scenario again adds rows, see [`Store::append`].
     pub fn load(&mut self, scenario: &str, batch: &RecordBatch) {
+        self.load_with_sharing(scenario, batch, &RowMappingSharing::PerField);
+    }
+
+    /// Adds the rows of a batch to a scenario, so that a large source can be loaded in several
+    /// batches. The rows of the base scenario are added after the loaded ones, their keys must not
+    /// be loaded yet. The values of another scenario are added to its previous ones, a row given
+    /// again takes its new values, and its fields map their rows per field as with
+    /// [`Store::load`]. A scenario that is not loaded yet is loaded.
+    pub fn append(&mut self, scenario: &str, batch: &RecordBatch) {
+        if scenario == MAIN_SCENARIO_NAME || !self.has_scenario(scenario) {
+            self.load(scenario, batch);
+        } else {
+            let batch = self.merge_scenario_values(scenario, batch);
+            self.load(scenario, &batch);
+        }
+    }
+
+    /// The previous values of a scenario followed by the values of the batch, for the fields
+    /// modified by the scenario or given by the batch. The values of a row given by the batch are
+    /// the ones of the batch.
+    fn merge_scenario_values(&self, scenario: &str, batch: &RecordBatch) -> RecordBatch {
+        let schema = batch.schema();
+        let key_field = self.key_field();
+        let key_index = schema
+            .index_of(key_field.name())
+            .unwrap_or_else(|_| panic!("cannot find key field '{}' in batch", key_field.name()));
+        let keys = batch.column(key_index).as_any().downcast_ref::<Int64Array>().unwrap();
+        let batch_rows: Vec<u32> = keys.values().iter()
+            .map(|key| *self.primary_index
+                .get(key)
+                .unwrap_or_else(|| panic!("Cannot find key {} in {} scenario", key, MAIN_SCENARIO_NAME)) as u32)
+            .collect();
+
+        let mut previous_rows = RoaringBitmap::new();
+        if let Some(rows_by_field) = self.overridden_rows_by_field_by_scenario.get(scenario) {
+            rows_by_field.values().for_each(|rows| previous_rows |= rows);
+        }
+        batch_rows.iter().for_each(|row| { previous_rows.remove(*row); });
+        let mut rows: Vec<u32> = previous_rows.iter().collect();
+        let previous_count = rows.len();
+        rows.extend(batch_rows);
+
+        let modified = self.vector_by_field_by_scenario.get(scenario);
+        let mut fields = Vec::new();
+        let mut columns = Vec::new();
+        for field in self.schema.fields() {
+            match schema.index_of(field.name()) {
+                Ok(index) => {
+                    let previous = self.read_rows(scenario, field.name(), &rows[..previous_count]);
+                    columns.push(concat(&[previous.as_ref(), batch.column(index).as_ref()]).unwrap());
+                }
+                // The rows of the batch keep their values in the scenario.
+                Err(_) if modified.is_some_and(|vectors| vectors.contains_key(field.name())) => {
+                    columns.push(self.read_rows(scenario, field.name(), &rows));
+                }
+                Err(_) => continue,
+            }
+            fields.push(field.clone());
+        }
+        RecordBatch::try_new(Arc::new(Schema::new(fields)), columns).unwrap()
+    }
+
+    /// Loads a scenario, the fields of a scenario other than the base one share their row
+    /// mappings as given by `sharing`. `sharing` is ignored for the base scenario.
+    pub fn load_with_sharing(&mut self, scenario: &str, batch: &RecordBatch, sharing: &RowMappingSharing) {
+        let added = !self.has_scenario(scenario);
+        let listed = self.vector_by_field_by_scenario.contains_key(scenario);
         let dic = self.dictionary_provider.dicos
             .entry(SCENARIO_FIELD_NAME.to_string())
             .or_insert(Dictionary::new());
         let _ = *dic.map(scenario.to_string());
 
+        let mut fields: HashSet<String>;
         if scenario == MAIN_SCENARIO_NAME {
             self.load_main_scenario(scenario, batch);
+            fields = self.schema.fields().iter().map(|f| f.name().to_string()).collect();
         } else {
-            let key_col = batch.column(self.key_indices[0] as usize);
-            for index in 0..batch.columns().len() {
-                let col = batch.column(index);
-                let schema = batch.schema();
-                let field = schema.field(index);
-
-                match field.data_type() {
-                    DataType::UInt64 => {
-                        self.build_scenario_array(col, key_col, scenario, field, UInt64Builder::new(self.array_size as usize));
-                    }
-                    DataType::UInt32 => {
-                        self.build_scenario_array(col, key_col, scenario, field, UInt32Builder::new(self.array_size as usize));
-                    }
-                    DataType::Int64 => {
-                        self.build_scenario_array(col, key_col, scenario, field, Int64Builder::new(self.array_size as usize));
-                    }
-                    DataType::Float64 => {
-                        self.build_scenario_array(col, key_col, scenario, field, Float64Builder::new(self.array_size as usize));
-                    }
-                    DataType::Utf8 => {
-                        let row_mapping = IntIntMapRowMapping::new();
-                        let arr = col.as_any().downcast_ref::<StringArray>().unwrap();
-                        let key_arr = key_col.as_any().downcast_ref::<Int64Array>().unwrap();
-
-                        let mut builder = UInt32Builder::new(self.array_size as usize);
-                        {
-                            let base_vector = self.vector_by_field_by_scenario.get(MAIN_SCENARIO_NAME).unwrap().get(field.name()).unwrap();
-                            let array_ref = base_vector.array.borrow();
-                            let base_arr = array_ref.as_ref().unwrap().as_any().downcast_ref::<UInt32Array>().unwrap();
-
-                            let dictionary = self.dictionary_provider.dicos
-                                .get_mut(field.name())
-                                .expect(format!("cannot find dictionary for field '{}'", field).as_str());
-
-                            let mut cursor: u32 = 0;
-                            for i in 0..arr.len() {
-                                let key = key_arr.value(i);
-                                let row = self.primary_index
-                                    .get(&key)
-                                    .expect(format!("Cannot find key {} in {} scenario", key, MAIN_SCENARIO_NAME).as_str());
-                                let row = *row as usize;
-                                let original_value = base_arr.value(row);
-                                let value = arr.value(i);
-                                let position = dictionary.get_position(&value.to_string());
-                                match position {
-                                    None => {
-                                        // should be mapped
-                                        let p = dictionary.map(value.to_string());
-                                        builder.append_value(*p).unwrap();
-                                        row_mapping.map(row as u32, cursor);
-                                        cursor += 1;
-                                    }
-                                    Some(p) => {
-                                        if *p != original_value {
-                                            // already in dictionary but current value is different
-                                            builder.append_value(*p).unwrap();
-                                            row_mapping.map(row as u32, cursor);
-                                            cursor += 1;
-                                        }
-                                    }
-                                }
-                            }
-                        }
+            fields = self.remove_scenario_values(scenario);
+            self.load_scenario(scenario, batch, sharing);
+            if let Some(vectors) = self.vector_by_field_by_scenario.get(scenario) {
+                fields.extend(vectors.keys().cloned());
+            }
+        }
+        self.version += 1;
+        self.changes.push(StoreChange {
+            version: self.version,
+            scenario: scenario.to_string(),
+            fields,
+            listing_changed: added || listed != self.vector_by_field_by_scenario.contains_key(scenario),
+        });
+        self.refresh_aggregate_tables(scenario);
+    }
 
-                        if !builder.is_empty() {
-                            self.vector_by_field_by_scenario
-                                .entry(scenario.to_string())
-                                .or_insert(HashMap::new())
-                                .entry(field.name().to_string())
-                                .or_insert_with(|| {
-                                    let chunk_array = Store::create_chunk_array(field.clone(), self.array_size);
-                                    chunk_array.set_array(Arc::new(builder.finish()));
-                                    Arc::new(chunk_array)
-                                });
-                            self.row_mapping_by_field_by_scenario
-                                .entry(scenario.to_string())
-                                .or_insert(HashMap::new())
-                                .entry(field.name().to_string())
-                                .or_insert_with(|| row_mapping);
-                        }
-                    }
-                    _ => { panic!("type not supported {}", field.data_type()) }
-                }
+    /// Adds an aggregate table, the queries it can answer are rolled up from it instead of
+    /// aggregating the rows, see [`AggregateTableDefinition::can_answer`]. The table is kept up to
+    /// date by the loads.
+    pub fn add_aggregate_table(&mut self, definition: AggregateTableDefinition) -> Result<(), Vec<ValidationError>> {
+        definition.validate(self)?;
+        if self.aggregate_tables.iter().any(|table| table.definition().name == definition.name) {
+            return Err(vec![ValidationError::new(
+                "name".to_string(),
+                format!("there is already an aggregate table named '{}'", definition.name))]);
+        }
+        let mut table = AggregateTable::new(definition);
+        if self.has_scenario(MAIN_SCENARIO_NAME) {
+            for scenario in self.vector_by_field_by_scenario.keys() {
+                table.refresh(self, scenario);
+            }
+        }
+        self.aggregate_tables.push(table);
+        Ok(())
+    }
+
+    pub fn aggregate_tables(&self) -> &[AggregateTable] {
+        &self.aggregate_tables
+    }
+
+    /// The smallest aggregate table the query can be rolled up from.
+    pub fn find_aggregate_table(&self, query: &Query) -> Option<&AggregateTable> {
+        self.aggregate_tables.iter()
+            .filter(|table| table.definition().can_answer(query))
+            .min_by_key(|table| table.size())
+    }
+
+    /// Aggregates again the scenarios of the aggregate tables the load of a scenario changed: all
+    /// of them for the base scenario as the other scenarios read its values.
+    fn refresh_aggregate_tables(&mut self, scenario: &str) {
+        if self.aggregate_tables.is_empty() || !self.has_scenario(MAIN_SCENARIO_NAME) {
+            return;
+        }
+        let scenarios: Vec<String> = if scenario == MAIN_SCENARIO_NAME {
+            self.vector_by_field_by_scenario.keys().cloned().collect()
+        } else {
+            vec![scenario.to_string()]
+        };
+        let mut tables = std::mem::take(&mut self.aggregate_tables);
+        for table in tables.iter_mut() {
+            for scenario in scenarios.iter() {
+                table.refresh(self, scenario);
             }
         }
+        self.aggregate_tables = tables;
+    }
+
+    /// Removes the values of a scenario and returns the fields it modified.
+    fn remove_scenario_values(&mut self, scenario: &str) -> HashSet<String> {
+        self.row_mapping_by_field_by_scenario.remove(scenario);
+        self.overridden_rows_by_field_by_scenario.remove(scenario);
+        self.vector_by_field_by_scenario.remove(scenario)
+            .map(|vectors| vectors.into_keys().collect())
+            .unwrap_or_default()
+    }
+
+    /// The number of changes of the store, every load changes it.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// The changes made after the given version, oldest first.
+    pub fn changes_since(&self, version: u64) -> &[StoreChange] {
+        let start = self.changes.partition_point(|change| change.version <= version);
+        &self.changes[start..]
     }
 
+    /// Adds the rows of the batch after the loaded ones.
     fn load_main_scenario(&mut self, scenario: &str, batch: &RecordBatch) {
+        let first_row = *self.row_count.borrow();
         let schema = batch.schema();
         for index in 0..batch.columns().len() {
             let col = batch.column(index);
@@ -203,7 +307,7 @@ impl Store {
 
             if index as u32 == self.key_indices[0] {
                 let arr = col.as_any().downcast_ref::<Int64Array>().unwrap(); // FIXME should not be hardcoded
-                let mut r: u64 = 0;
+                let mut r: u64 = first_row;
                 for b in arr.iter() {
                     self.primary_index.insert(b.unwrap(), r);
                     r += 1;
@@ -234,10 +338,16 @@ impl Store {
                         .entry(field.name().to_string())
                         .or_insert(Dictionary::new());
                     let mut builder = UInt32Builder::new(string_array.len());
-                    for element in string_array {
-                        builder.append_value(*dic.map(element.unwrap().to_string())).unwrap();
+                    let bitmaps = self.bitmap_index_by_field.entry(field.name().to_string()).or_default();
+                    for (row, element) in string_array.iter().enumerate() {
+                        let position = *dic.map(element.unwrap().to_string());
+                        builder.append_value(position).unwrap();
+                        if bitmaps.len() <= position as usize {
+                            bitmaps.resize(position as usize + 1, RoaringBitmap::new());
+                        }
+                        bitmaps[position as usize].insert(first_row as u32 + row as u32);
                     }
-                    self.get_chunk_array(scenario, field).set_array(Arc::new(builder.finish()));
+                    self.append_base_values(scenario, field, Arc::new(builder.finish()));
                 }
                 _ => { panic!("type not supported {}", field.data_type()) }
             }
@@ -255,62 +365,134 @@ impl Store {
             builder.append_value(element.unwrap()).unwrap();
         }
         let array = builder.finish();
-        self.get_chunk_array(scenario, field).borrow_mut().set_array(Arc::new(array));
+        self.append_base_values(scenario, field, Arc::new(array));
     }
 
-    fn build_scenario_array<T: ArrowPrimitiveType>(
-        &mut self,
-        col: &ArrayRef,
-        key_col: &ArrayRef,
-        scenario: &str,
-        field: &Field,
-        mut builder: PrimitiveBuilder<T>) {
-        let row_mapping = IntIntMapRowMapping::new();
-        let arr = col.as_any().downcast_ref::<PrimitiveArray<T>>().unwrap();
-        let key_arr = key_col.as_any().downcast_ref::<Int64Array>().unwrap();
-
-        // Create a block here to borrow vector_by_field_by_scenario as immutable.
-        {
-            let base_vector = self.vector_by_field_by_scenario.get(MAIN_SCENARIO_NAME).unwrap().get(field.name()).unwrap();
-            let array_ref = base_vector.array.borrow();
-            let base_arr = array_ref.as_ref().unwrap().as_any().downcast_ref::<PrimitiveArray<T>>().unwrap();
-
-            let mut cursor: u32 = 0;
-            for i in 0..arr.len() {
-                let key = key_arr.value(i);
-                let row = self.primary_index
-                    .get(&key)
-                    .expect(format!("Cannot find key {} in {} scenario", key, MAIN_SCENARIO_NAME).as_str());
-                let row = *row as usize;
-                let original_value = base_arr.value(row);
-                let value = arr.value(i);
-                if original_value != value {
-                    builder.append_value(value).unwrap();
-                    row_mapping.map(row as u32, cursor);
-                    cursor += 1;
+    fn append_base_values(&mut self, scenario: &str, field: &Field, array: ArrayRef) {
+        let chunk_array = self.get_chunk_array(scenario, field);
+        let previous = chunk_array.array.borrow().clone();
+        match previous {
+            Some(previous) => chunk_array.set_array(concat(&[previous.as_ref(), array.as_ref()]).unwrap()),
+            None => chunk_array.set_array(array),
+        }
+    }
+
+    /// Only the rows whose value differs from the base scenario are stored. The values of a field
+    /// are stored for all the rows of its mapping, see [`RowMappingSharing`].
+    fn load_scenario(&mut self, scenario: &str, batch: &RecordBatch, sharing: &RowMappingSharing) {
+        let schema = batch.schema();
+        // The key is looked up by name so that a batch can contain a subset of the fields.
+        let key_field = self.key_field();
+        let key_index = schema
+            .index_of(key_field.name())
+            .unwrap_or_else(|_| panic!("cannot find key field '{}' in batch", key_field.name()));
+        let keys = batch.column(key_index).as_any().downcast_ref::<Int64Array>().unwrap();
+        let rows: Vec<u32> = keys.values().iter()
+            .map(|key| *self.primary_index
+                .get(key)
+                .unwrap_or_else(|| panic!("Cannot find key {} in {} scenario", key, MAIN_SCENARIO_NAME)) as u32)
+            .collect();
+
+        // The modified fields with their values, dictionary encoded, and the batch indices of the
+        // modified rows.
+        let mut modified: Vec<(&Field, ArrayRef, Vec<u32>)> = Vec::new();
+        for (field, column) in schema.fields().iter().zip(batch.columns()) {
+            let column = match field.data_type() {
+                DataType::Utf8 => Arc::new(self.encode(field, column)) as ArrayRef,
+                DataType::UInt64 | DataType::UInt32 | DataType::Int64 | DataType::Float64 => Arc::clone(column),
+                data_type => panic!("type not supported {}", data_type),
+            };
+            let indices = {
+                let base_vector = self.vector_by_field_by_scenario.get(MAIN_SCENARIO_NAME).unwrap().get(field.name()).unwrap();
+                let base_array = base_vector.array.borrow();
+                let base_array = base_array.as_ref().unwrap();
+                match column.data_type() {
+                    DataType::UInt64 => Store::modified_indices::<UInt64Type>(&column, base_array, &rows),
+                    DataType::UInt32 => Store::modified_indices::<UInt32Type>(&column, base_array, &rows),
+                    DataType::Int64 => Store::modified_indices::<Int64Type>(&column, base_array, &rows),
+                    _ => Store::modified_indices::<Float64Type>(&column, base_array, &rows),
                 }
+            };
+            if !indices.is_empty() {
+                self.set_overridden_rows(scenario, field, indices.iter().map(|index| rows[*index as usize]).collect());
+                modified.push((field, column, indices));
             }
         }
 
-        // This block borrow vector_by_field_by_scenario as mutable.
-        if !builder.is_empty() {
-            self.vector_by_field_by_scenario
-                .entry(scenario.to_string())
-                .or_insert(HashMap::new())
-                .entry(field.name().to_string())
-                .or_insert_with(|| {
-                    let chunk_array = Store::create_chunk_array(field.clone(), self.array_size);
-                    chunk_array.set_array(Arc::new(builder.finish()));
-                    Arc::new(chunk_array)
-                });
-            self.row_mapping_by_field_by_scenario
-                .entry(scenario.to_string())
-                .or_insert(HashMap::new())
-                .entry(field.name().to_string())
-                .or_insert_with(|| row_mapping);
+        let names: Vec<&str> = modified.iter().map(|(field, _, _)| field.name().as_str()).collect();
+        let row_count = *self.row_count.borrow();
+        for group in sharing.groups(&names) {
+            let mut indices: Vec<u32> = group.iter().flat_map(|index| modified[*index].2.iter().copied()).collect();
+            if group.len() > 1 {
+                indices.sort_unstable();
+                indices.dedup();
+            }
+            let row_mapping = RowMappingFactory::create(indices.iter().map(|index| rows[*index as usize]).collect(), row_count);
+            let indices = UInt32Array::from(indices);
+            for index in group {
+                let (field, column, _) = &modified[index];
+                let chunk_array = Store::create_chunk_array((*field).clone(), self.array_size);
+                chunk_array.set_array(take(column.as_ref(), &indices, None).unwrap());
+                self.vector_by_field_by_scenario
+                    .entry(scenario.to_string())
+                    .or_default()
+                    .insert(field.name().to_string(), Arc::new(chunk_array));
+                self.row_mapping_by_field_by_scenario
+                    .entry(scenario.to_string())
+                    .or_default()
+                    .insert(field.name().to_string(), Arc::clone(&row_mapping));
+            }
         }
     }
 
+    /// Encodes the values of a dictionary encoded field, the new values are added to the
+    /// dictionary.
+    fn encode(&mut self, field: &Field, column: &ArrayRef) -> UInt32Array {
+        let dictionary = self.dictionary_provider.dicos
+            .get_mut(field.name())
+            .unwrap_or_else(|| panic!("cannot find dictionary for field '{}'", field));
+        let values = column.as_any().downcast_ref::<StringArray>().unwrap();
+        values.iter().map(|value| Some(*dictionary.map(value.unwrap().to_string()))).collect()
+    }
+
+    /// Returns the indices of the values that differ from the base values of their rows.
+    fn modified_indices<T: ArrowPrimitiveType>(column: &ArrayRef, base_array: &ArrayRef, rows: &[u32]) -> Vec<u32> {
+        let values = column.as_any().downcast_ref::<PrimitiveArray<T>>().unwrap();
+        let base_values = base_array.as_any().downcast_ref::<PrimitiveArray<T>>().unwrap();
+        (0..values.len())
+            .filter(|index| values.value(*index) != base_values.value(rows[*index] as usize))
+            .map(|index| index as u32)
+            .collect()
+    }
+
+    fn set_overridden_rows(&mut self, scenario: &str, field: &Field, rows: RoaringBitmap) {
+        self.overridden_rows_by_field_by_scenario
+            .entry(scenario.to_string())
+            .or_default()
+            .entry(field.name().to_string())
+            .or_insert(rows);
+    }
+
+    /// Returns the base rows whose value of a dictionary encoded field is one of the given
+    /// dictionary positions.
+    pub fn get_matching_rows(&self, field: &str, positions: &HashSet<u32>) -> RoaringBitmap {
+        let mut rows = RoaringBitmap::new();
+        if let Some(bitmaps) = self.bitmap_index_by_field.get(field) {
+            for position in positions {
+                if let Some(bitmap) = bitmaps.get(*position as usize) {
+                    rows |= bitmap;
+                }
+            }
+        }
+        rows
+    }
+
+    /// Returns the rows of a field whose value in the scenario differs from the base one, `None`
+    /// if the scenario does not modify the field.
+    pub fn get_overridden_rows(&self, scenario: &str, field: &str) -> Option<&RoaringBitmap> {
+        self.overridden_rows_by_field_by_scenario.get(scenario).and_then(|rows| rows.get(field))
+    }
+
     fn get_chunk_array(&mut self, scenario: &str, field: &Field) -> &Arc<ChunkArray> {
         self.vector_by_field_by_scenario
             .get(scenario)
@@ -322,4 +504,98 @@ impl Store {
     pub fn schema(&self) -> Arc<Schema> {
         Arc::clone(&self.schema)
     }
+
+    pub fn key_field(&self) -> &Field {
+        self.schema.field(self.key_indices[0] as usize)
+    }
+
+    /// Whether the store can hold a field of the type.
+    pub fn is_supported_type(data_type: &DataType) -> bool {
+        matches!(data_type, DataType::UInt64 | DataType::UInt32 | DataType::Int64 | DataType::Float64 | DataType::Utf8)
+    }
+
+    pub fn has_scenario(&self, scenario: &str) -> bool {
+        self.dictionary_provider.dicos.get(SCENARIO_FIELD_NAME)
+            .and_then(|dictionary| dictionary.get_position(&scenario.to_string()))
+            .is_some()
+    }
+
+    /// Checks that the batch can be given to [`Store::load`] without making it panic, and casts
+    /// its columns to the types of the store, in the order of the store schema. The base scenario
+    /// needs every field whereas a scenario can be restricted to the key and the fields it modifies.
+    pub fn prepare_batch(&self, scenario: &str, batch: &RecordBatch) -> Result<RecordBatch, ArrowError> {
+        let invalid = |message: String| Err(ArrowError::InvalidArgumentError(message));
+        if scenario != MAIN_SCENARIO_NAME && !self.has_scenario(MAIN_SCENARIO_NAME) {
+            return invalid(format!("the {} scenario must be loaded before '{}'", MAIN_SCENARIO_NAME, scenario));
+        }
+        let batch_schema = batch.schema();
+        for field in batch_schema.fields() {
+            if self.schema.index_of(field.name()).is_err() {
+                return invalid(format!("field '{}' does not exist in the store", field.name()));
+            }
+        }
+
+        let key_field = self.key_field();
+        let mut fields = Vec::with_capacity(batch.num_columns());
+        let mut columns = Vec::with_capacity(batch.num_columns());
+        for field in self.schema.fields() {
+            match batch_schema.index_of(field.name()) {
+                Ok(index) => {
+                    let column = cast(batch.column(index), field.data_type())?;
+                    if column.null_count() > 0 {
+                        return invalid(format!("field '{}' contains null values", field.name()));
+                    }
+                    fields.push(field.clone());
+                    columns.push(column);
+                }
+                Err(_) if field == key_field => return invalid(format!("key field '{}' is required", field.name())),
+                Err(_) if scenario == MAIN_SCENARIO_NAME => {
+                    return invalid(format!("field '{}' is required to load {}", field.name(), MAIN_SCENARIO_NAME));
+                }
+                Err(_) => {}
+            }
+        }
+
+        if scenario != MAIN_SCENARIO_NAME {
+            let key_index = fields.iter().position(|f| f == key_field).unwrap();
+            let keys = columns[key_index].as_any().downcast_ref::<Int64Array>().unwrap();
+            if let Some(key) = keys.values().iter().find(|k| !self.primary_index.contains_key(k)) {
+                return invalid(format!("key {} does not exist in the {} scenario", key, MAIN_SCENARIO_NAME));
+            }
+        }
+        RecordBatch::try_new(Arc::new(Schema::new(fields)), columns)
+    }
+
+    /// Materializes the effective view of a scenario i.e. the base values patched with the
+    /// values of the scenario. Dictionary encoded fields are decoded back to strings.
+    pub fn get_scenario_batch(&self, scenario: &str) -> RecordBatch {
+        let rows: Vec<u32> = (0..*self.row_count.borrow() as u32).collect();
+        let columns: Vec<ArrayRef> = self.schema.fields().iter()
+            .map(|field| self.read_rows(scenario, field.name(), &rows))
+            .collect();
+        RecordBatch::try_new(self.schema(), columns).unwrap()
+    }
+
+    /// Reads the values of a field of the effective view of a scenario, at the given rows.
+    pub fn read_rows(&self, scenario: &str, field: &str, rows: &[u32]) -> ArrayRef {
+        let reader = self.get_scenario_chunk_array(scenario, field);
+        match reader.data_type() {
+            DataType::UInt64 => Arc::new(Store::read_column::<UInt64Type>(&reader, rows)),
+            DataType::UInt32 => Arc::new(Store::read_column::<UInt32Type>(&reader, rows)),
+            DataType::Int64 => Arc::new(Store::read_column::<Int64Type>(&reader, rows)),
+            DataType::Float64 => Arc::new(Store::read_column::<Float64Type>(&reader, rows)),
+            DataType::Utf8 => {
+                let dictionary = self.get_dictionary(field);
+                let array: StringArray = rows.iter()
+                    .map(|row| dictionary.read(&reader.read::<UInt32Type>(*row)))
+                    .collect();
+                Arc::new(array)
+            }
+            data_type => { panic!("type not supported {}", data_type) }
+        }
+    }
+
+    fn read_column<T: ArrowPrimitiveType>(reader: &ChunkArrayReader, rows: &[u32]) -> PrimitiveArray<T> {
+        PrimitiveArray::<T>::from_iter_values(rows.iter().map(|row| reader.read::<T>(*row)))
+    }
 }
@@ -1,3 +1,5 @@
+use std::borrow::Borrow;
+use ahash::RandomState;
 use std::collections::HashMap;
 use std::hash::Hash;
 
@@ -18,10 +20,11 @@ impl DictionaryProvider {
     }
 }
 
+/// Dictionaries are looked up for every aggregated row, they hash with aHash rather than SipHash.
 #[derive(Debug)]
 pub struct Dictionary<T> {
-    map: HashMap<T, u32>,
-    reverse_map: HashMap<u32, T>,
+    map: HashMap<T, u32, RandomState>,
+    reverse_map: HashMap<u32, T, RandomState>,
 }
 
 impl<T> Dictionary<T>
@@ -30,23 +33,25 @@ impl<T> Dictionary<T>
 {
     pub(crate) fn new() -> Dictionary<T> {
         Dictionary {
-            map: HashMap::new(),
-            reverse_map: HashMap::new(),
+            map: HashMap::default(),
+            reverse_map: HashMap::default(),
         }
     }
 
     pub fn map(&mut self, value: T) -> &u32 {
-        let size = self.map.len();
-        let pos = self.map.entry(value.clone()).or_insert(size as u32);
-        self.reverse_map.insert(*pos, value);
-        pos
+        let size = self.map.len() as u32;
+        let reverse_map = &mut self.reverse_map;
+        self.map.entry(value).or_insert_with_key(|value| {
+            reverse_map.insert(size, value.clone());
+            size
+        })
     }
 
     pub fn read(&self, position: &u32) -> Option<&T> {
         self.reverse_map.get(position)
     }
 
-    pub fn get_position(&self, value: &T) -> Option<&u32> {
+    pub fn get_position<Q: ?Sized + Eq + Hash>(&self, value: &Q) -> Option<&u32> where T: Borrow<Q> {
         self.map.get(value)
     }
 
@@ -0,0 +1,201 @@
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use arrow::datatypes::Schema;
+use arrow::ipc::writer::IpcWriteOptions;
+use arrow::record_batch::RecordBatch;
+use arrow_flight::flight_service_server::{FlightService, FlightServiceServer};
+use arrow_flight::utils::{flight_data_from_arrow_batch, flight_data_to_arrow_batch};
+use arrow_flight::{Action, ActionType, Criteria, Empty, FlightData, FlightDescriptor, FlightInfo,
+                   HandshakeRequest, HandshakeResponse, PutResult, SchemaAsIpc, SchemaResult, Ticket};
+use futures::{Stream, StreamExt};
+use serde_json::json;
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::{Request, Response, Status, Streaming};
+
+use crate::datastore::Store;
+use crate::query::Query;
+use crate::query_engine::QueryEngine;
+use crate::sql;
+use crate::store_handle::StoreHandle;
+
+pub const DEFAULT_FLIGHT_ADDRESS: &str = "127.0.0.1:50051";
+
+/// The maximum number of rows of the record batches streamed by DoGet.
+pub const FLIGHT_BATCH_SIZE: usize = 8192;
+
+type FlightStream<T> = Pin<Box<dyn Stream<Item=Result<T, Status>> + Send + 'static>>;
+
+/// Serves a [`Store`] over Arrow Flight.
+///
+/// - DoGet: the ticket is a json [`Query`] or, when it does not start with `{`, a sql query, see
+///   [`sql::parse_query`]. The result is streamed as record batches of at most
+///   [`FLIGHT_BATCH_SIZE`] rows.
+/// - DoPut: loads the batches as a new scenario. The scenario name is the first element of the
+///   path of the descriptor, or its command. A scenario can be restricted to the key and the
+///   fields it modifies. The only [`PutResult`] has a json metadata `{"scenario":..., "rows":...}`.
+///
+/// The other methods are not implemented. The requests are executed one at a time by the thread
+/// owning the store, see [`StoreHandle`].
+pub struct FlightServer {
+    store: StoreHandle,
+}
+
+// tonic::Status is large but it is what the service returns anyway.
+#[allow(clippy::result_large_err)]
+impl FlightServer {
+    /// Starts the thread owning the store. The store is created on this thread by `create_store`.
+    pub fn new<F: FnOnce() -> Store + Send + 'static>(create_store: F) -> FlightServer {
+        FlightServer::with_handle(StoreHandle::spawn(create_store))
+    }
+
+    /// Serves a store already shared with other services.
+    pub fn with_handle(store: StoreHandle) -> FlightServer {
+        FlightServer { store }
+    }
+
+    pub async fn serve(self, address: SocketAddr) -> Result<(), tonic::transport::Error> {
+        tonic::transport::Server::builder()
+            .add_service(FlightServiceServer::new(self))
+            .serve(address)
+            .await
+    }
+
+    /// Serves the connections of an already bound listener, e.g. on port 0 to let the system pick a
+    /// free port.
+    pub async fn serve_with_listener(self, listener: TcpListener) -> Result<(), tonic::transport::Error> {
+        tonic::transport::Server::builder()
+            .add_service(FlightServiceServer::new(self))
+            .serve_with_incoming(TcpListenerStream::new(listener))
+            .await
+    }
+
+    fn execute(store: &Store, ticket: &[u8]) -> Result<RecordBatch, Status> {
+        let text = std::str::from_utf8(ticket)
+            .map_err(|e| Status::invalid_argument(format!("the ticket is not valid utf-8: {}", e)))?;
+        let query: Query = if text.trim_start().starts_with('{') {
+            serde_json::from_str(text).map_err(|e| Status::invalid_argument(format!("cannot parse the query: {}", e)))?
+        } else {
+            sql::parse_query(text, store).map_err(|e| Status::invalid_argument(e.to_string()))?
+        };
+        if let Err(errors) = query.validate(store) {
+            let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+            return Err(Status::invalid_argument(errors.join(", ")));
+        }
+        let engine = QueryEngine::new(store);
+        Ok(engine.execute(&query).to_record_batch())
+    }
+
+    fn load(store: &mut Store, scenario: &str, batch: &RecordBatch) -> Result<usize, Status> {
+        if store.has_scenario(scenario) {
+            return Err(Status::already_exists(format!("scenario '{}' is already loaded", scenario)));
+        }
+        let batch = store.prepare_batch(scenario, batch).map_err(|e| Status::invalid_argument(e.to_string()))?;
+        store.load(scenario, &batch);
+        Ok(batch.num_rows())
+    }
+
+    /// Runs `f` on the store, waiting for it on a blocking thread to keep serving the other requests.
+    async fn run<T, F>(&self, f: F) -> Result<T, Status>
+        where T: Send + 'static,
+              F: FnOnce(&mut Store) -> Result<T, Status> + Send + 'static,
+    {
+        let store = self.store.clone();
+        tokio::task::spawn_blocking(move || store.run(f))
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?
+            .map_err(|e| Status::internal(e.to_string()))?
+    }
+
+    fn scenario_name(descriptor: Option<&FlightDescriptor>) -> Result<String, Status> {
+        let descriptor = descriptor
+            .ok_or_else(|| Status::invalid_argument("the first message must have a descriptor naming the scenario"))?;
+        match descriptor.path.first() {
+            Some(scenario) => Ok(scenario.clone()),
+            None if !descriptor.cmd.is_empty() => String::from_utf8(descriptor.cmd.clone())
+                .map_err(|e| Status::invalid_argument(format!("the scenario name is not valid utf-8: {}", e))),
+            None => Err(Status::invalid_argument("the descriptor does not name a scenario")),
+        }
+    }
+}
+
+#[tonic::async_trait]
+#[allow(clippy::result_large_err)]
+impl FlightService for FlightServer {
+    type HandshakeStream = FlightStream<HandshakeResponse>;
+    type ListFlightsStream = FlightStream<FlightInfo>;
+    type DoGetStream = FlightStream<FlightData>;
+    type DoPutStream = FlightStream<PutResult>;
+    type DoActionStream = FlightStream<arrow_flight::Result>;
+    type ListActionsStream = FlightStream<ActionType>;
+    type DoExchangeStream = FlightStream<FlightData>;
+
+    async fn handshake(&self, _: Request<Streaming<HandshakeRequest>>) -> Result<Response<Self::HandshakeStream>, Status> {
+        Err(Status::unimplemented("handshake"))
+    }
+
+    async fn list_flights(&self, _: Request<Criteria>) -> Result<Response<Self::ListFlightsStream>, Status> {
+        Err(Status::unimplemented("list_flights"))
+    }
+
+    async fn get_flight_info(&self, _: Request<FlightDescriptor>) -> Result<Response<FlightInfo>, Status> {
+        Err(Status::unimplemented("get_flight_info"))
+    }
+
+    async fn get_schema(&self, _: Request<FlightDescriptor>) -> Result<Response<SchemaResult>, Status> {
+        Err(Status::unimplemented("get_schema"))
+    }
+
+    async fn do_get(&self, request: Request<Ticket>) -> Result<Response<Self::DoGetStream>, Status> {
+        let ticket = request.into_inner().ticket;
+        let batch = self.run(move |store| FlightServer::execute(store, &ticket)).await?;
+
+        // The result only has Utf8 and primitive columns, there is no dictionary to send.
+        let options = IpcWriteOptions::default();
+        let mut messages = vec![SchemaAsIpc::new(&batch.schema(), &options).into()];
+        let mut offset = 0;
+        while offset < batch.num_rows() {
+            let length = FLIGHT_BATCH_SIZE.min(batch.num_rows() - offset);
+            let (_, data) = flight_data_from_arrow_batch(&batch.slice(offset, length), &options);
+            messages.push(data);
+            offset += length;
+        }
+        Ok(Response::new(Box::pin(futures::stream::iter(messages.into_iter().map(Ok)))))
+    }
+
+    async fn do_put(&self, request: Request<Streaming<FlightData>>) -> Result<Response<Self::DoPutStream>, Status> {
+        let mut stream = request.into_inner();
+        let first = stream.next().await
+            .ok_or_else(|| Status::invalid_argument("the stream is empty"))??;
+        let scenario = FlightServer::scenario_name(first.flight_descriptor.as_ref())?;
+        let schema = Arc::new(Schema::try_from(&first)
+            .map_err(|e| Status::invalid_argument(format!("the first message must be a schema: {}", e)))?);
+
+        let mut batches = Vec::new();
+        while let Some(data) = stream.next().await {
+            let batch = flight_data_to_arrow_batch(&data?, Arc::clone(&schema), &[])
+                .map_err(|e| Status::invalid_argument(e.to_string()))?;
+            batches.push(batch);
+        }
+        let batch = RecordBatch::concat(&schema, &batches).map_err(|e| Status::invalid_argument(e.to_string()))?;
+
+        let name = scenario.clone();
+        let rows = self.run(move |store| FlightServer::load(store, &name, &batch)).await?;
+        let result = PutResult { app_metadata: json!({ "scenario": scenario, "rows": rows }).to_string().into_bytes() };
+        Ok(Response::new(Box::pin(futures::stream::iter(vec![Ok(result)]))))
+    }
+
+    async fn do_action(&self, _: Request<Action>) -> Result<Response<Self::DoActionStream>, Status> {
+        Err(Status::unimplemented("do_action"))
+    }
+
+    async fn list_actions(&self, _: Request<Empty>) -> Result<Response<Self::ListActionsStream>, Status> {
+        Err(Status::unimplemented("list_actions"))
+    }
+
+    async fn do_exchange(&self, _: Request<Streaming<FlightData>>) -> Result<Response<Self::DoExchangeStream>, Status> {
+        Err(Status::unimplemented("do_exchange"))
+    }
+}
@@ -0,0 +1,104 @@
+use arrow::array::{Array, ArrayRef};
+use serde::{Deserialize, Serialize};
+
+use crate::point_dictionary::PointDictionary;
+use crate::point_list_aggregates_result::AggregateValue;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Comparison {
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+}
+
+impl Comparison {
+    pub fn evaluate(&self, left: f64, right: f64) -> bool {
+        match self {
+            Comparison::Eq => left == right,
+            Comparison::NotEq => left != right,
+            Comparison::Lt => left < right,
+            Comparison::LtEq => left <= right,
+            Comparison::Gt => left > right,
+            Comparison::GtEq => left >= right,
+        }
+    }
+}
+
+/// A predicate on an aggregated value, identified by the alias of its measure e.g. `sum(price)`.
+/// The points that do not satisfy all the predicates of a query are removed from the result.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Having {
+    pub alias: String,
+    pub comparison: Comparison,
+    pub value: f64,
+    /// Compares the difference between the value of the point and the value of the same point in
+    /// the base scenario instead of the value itself.
+    #[serde(default)]
+    pub delta: bool,
+}
+
+impl Having {
+    pub fn new(alias: &str, comparison: Comparison, value: f64) -> Having {
+        Having { alias: alias.to_string(), comparison, value, delta: false }
+    }
+
+    /// A predicate on the delta with the base scenario. It requires the scenario coordinate and the
+    /// base scenario to be part of the query. A point missing from base has a base value of 0.
+    pub fn delta(alias: &str, comparison: Comparison, value: f64) -> Having {
+        Having { alias: alias.to_string(), comparison, value, delta: true }
+    }
+}
+
+/// Keeps the rows satisfying all the predicates. `scenario_index` is the index of the scenario
+/// coordinate in the points and `base_scenario` the position of the base scenario in its dictionary.
+pub(crate) fn filter(rows: Vec<u32>,
+                     having: &[Having],
+                     point_dictionary: &PointDictionary,
+                     aggregate_names: &[String],
+                     aggregates: &[ArrayRef],
+                     scenario_index: Option<usize>,
+                     base_scenario: Option<u32>) -> Vec<u32> {
+    let predicates: Vec<(&Having, &ArrayRef)> = having.iter().map(|h| {
+        let index = aggregate_names.iter()
+            .position(|n| *n == h.alias)
+            .unwrap_or_else(|| panic!("cannot filter on '{}', it is not a measure of the query", h.alias));
+        (h, &aggregates[index])
+    }).collect();
+    let needs_base = having.iter().any(|h| h.delta);
+    let (scenario_index, base_scenario) = match (scenario_index, base_scenario) {
+        (Some(i), Some(b)) => (i, b),
+        _ if needs_base => panic!("a delta predicate requires the scenario coordinate and the base scenario in the query"),
+        _ => (usize::MAX, u32::MAX),
+    };
+
+    let mut base_point = Vec::with_capacity(point_dictionary.len() as usize);
+    rows.into_iter().filter(|row| {
+        let base_row = if needs_base {
+            base_point.clear();
+            base_point.extend_from_slice(point_dictionary.read(row).unwrap());
+            base_point[scenario_index] = base_scenario;
+            point_dictionary.get_position(&base_point).copied()
+        } else {
+            None
+        };
+
+        predicates.iter().all(|(h, array)| {
+            let r = *row as usize;
+            if array.is_null(r) {
+                return false;
+            }
+            let mut value = AggregateValue::read(array.as_ref(), r).as_f64();
+            if h.delta {
+                value -= match base_row {
+                    Some(b) if !array.is_null(b as usize) => AggregateValue::read(array.as_ref(), b as usize).as_f64(),
+                    _ => 0f64,
+                };
+            }
+            h.comparison.evaluate(value, h.value)
+        })
+    }).collect()
+}
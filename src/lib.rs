@@ -1,11 +1,29 @@
 mod chunk_array;
 pub mod datastore;
 mod dictionary_provider;
-mod row_mapping;
-mod point_dictionary;
+pub mod row_mapping;
+pub mod point_dictionary;
 mod aggregator;
 pub mod point_list_aggregates_result;
 pub mod query;
 pub mod query_engine;
-mod bitmap_row_iterable_provider;
+pub mod bitmap_row_iterable_provider;
 mod row_iterable_provider;
+pub mod parquet_loader;
+pub mod parquet_exporter;
+pub mod csv_loader;
+pub mod result_writer;
+pub mod order_by;
+pub mod having;
+pub mod sql;
+pub mod server;
+pub mod cli;
+pub mod flight_server;
+pub mod pg_server;
+pub mod store_handle;
+pub mod table_provider;
+pub mod query_cache;
+pub mod aggregate_table;
+pub mod query_plan;
+#[cfg(feature = "python")]
+pub mod python;
@@ -0,0 +1,184 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+use arrow::array::{Array, ArrayRef, Float64Array, UInt64Array};
+use arrow::datatypes::DataType;
+use serde::{Deserialize, Serialize};
+
+use crate::dictionary_provider::Dictionary;
+use crate::point_dictionary::PointDictionary;
+
+/// Sorts the points of a result on a coordinate (by name) or on an aggregate (by alias).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OrderBy {
+    pub field: String,
+    #[serde(default)]
+    pub descending: bool,
+    #[serde(default)]
+    pub nulls_first: bool,
+}
+
+impl OrderBy {
+    /// Ascending order, nulls last.
+    pub fn asc(field: &str) -> OrderBy {
+        OrderBy { field: field.to_string(), descending: false, nulls_first: false }
+    }
+
+    /// Descending order, nulls first.
+    pub fn desc(field: &str) -> OrderBy {
+        OrderBy { field: field.to_string(), descending: true, nulls_first: true }
+    }
+
+    pub fn nulls_first(mut self) -> OrderBy {
+        self.nulls_first = true;
+        self
+    }
+
+    pub fn nulls_last(mut self) -> OrderBy {
+        self.nulls_first = false;
+        self
+    }
+}
+
+/// Keeps the `n` first points of each group according to the order of the query. A group is made
+/// of the points sharing the same coordinates on `group_by`, e.g. the top 5 products by revenue
+/// per scenario.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TopN {
+    pub group_by: Vec<String>,
+    pub n: usize,
+}
+
+enum SortKey<'a> {
+    Coordinate(usize, &'a Dictionary<String>),
+    Aggregate(&'a ArrayRef),
+}
+
+/// Compares two rows (i.e. points) of a result.
+pub(crate) struct RowComparator<'a> {
+    point_dictionary: &'a PointDictionary,
+    keys: Vec<(SortKey<'a>, &'a OrderBy)>,
+}
+
+impl<'a> RowComparator<'a> {
+    pub(crate) fn new(order_by: &'a [OrderBy],
+                      point_dictionary: &'a PointDictionary,
+                      point_names: &[String],
+                      dictionaries: &[&'a Dictionary<String>],
+                      aggregate_names: &[String],
+                      aggregates: &'a [ArrayRef]) -> RowComparator<'a> {
+        let keys = order_by.iter().map(|o| {
+            let key = if let Some(index) = point_names.iter().position(|n| *n == o.field) {
+                SortKey::Coordinate(index, dictionaries[index])
+            } else if let Some(index) = aggregate_names.iter().position(|n| *n == o.field) {
+                SortKey::Aggregate(&aggregates[index])
+            } else {
+                panic!("cannot order by '{}', it is neither a coordinate nor an aggregate of the query", o.field)
+            };
+            (key, o)
+        }).collect();
+        RowComparator { point_dictionary, keys }
+    }
+
+    pub(crate) fn compare(&self, left: u32, right: u32) -> Ordering {
+        for (key, order_by) in self.keys.iter() {
+            let ordering = match key {
+                SortKey::Coordinate(index, dictionary) => {
+                    let l = dictionary.read(&self.point_dictionary.read(&left).unwrap()[*index]);
+                    let r = dictionary.read(&self.point_dictionary.read(&right).unwrap()[*index]);
+                    RowComparator::apply_direction(l.cmp(&r), order_by)
+                }
+                SortKey::Aggregate(array) => {
+                    match (array.is_null(left as usize), array.is_null(right as usize)) {
+                        (true, true) => Ordering::Equal,
+                        (true, false) => if order_by.nulls_first { Ordering::Less } else { Ordering::Greater },
+                        (false, true) => if order_by.nulls_first { Ordering::Greater } else { Ordering::Less },
+                        (false, false) => RowComparator::apply_direction(
+                            RowComparator::compare_values(array.as_ref(), left as usize, right as usize),
+                            order_by),
+                    }
+                }
+            };
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+        Ordering::Equal
+    }
+
+    fn apply_direction(ordering: Ordering, order_by: &OrderBy) -> Ordering {
+        if order_by.descending { ordering.reverse() } else { ordering }
+    }
+
+    fn compare_values(array: &dyn Array, left: usize, right: usize) -> Ordering {
+        match array.data_type() {
+            DataType::UInt64 => {
+                let array = array.as_any().downcast_ref::<UInt64Array>().unwrap();
+                array.value(left).cmp(&array.value(right))
+            }
+            DataType::Float64 => {
+                let array = array.as_any().downcast_ref::<Float64Array>().unwrap();
+                array.value(left).total_cmp(&array.value(right))
+            }
+            _ => panic!("cannot order by {} values", array.data_type()),
+        }
+    }
+}
+
+/// An entry of the bounded heap of [`top_n`]. The greatest entry is the worst one, it is the first
+/// to be evicted. Ties are broken with the row to keep the selection deterministic.
+struct HeapEntry<'a, 'b> {
+    row: u32,
+    comparator: &'b RowComparator<'a>,
+}
+
+impl Ord for HeapEntry<'_, '_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.comparator.compare(self.row, other.row).then(self.row.cmp(&other.row))
+    }
+}
+
+impl PartialOrd for HeapEntry<'_, '_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for HeapEntry<'_, '_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for HeapEntry<'_, '_> {}
+
+/// Keeps the `top_n.n` best rows of each group with a heap bounded to `n` entries per group. The
+/// selected rows are returned in their original order.
+pub(crate) fn top_n(rows: &[u32],
+                    top_n: &TopN,
+                    comparator: &RowComparator,
+                    point_dictionary: &PointDictionary,
+                    point_names: &[String]) -> Vec<u32> {
+    let group_indices: Vec<usize> = top_n.group_by.iter().map(|field| {
+        point_names.iter()
+            .position(|n| n == field)
+            .unwrap_or_else(|| panic!("cannot group by '{}', it is not a coordinate of the query", field))
+    }).collect();
+
+    let mut heaps: HashMap<Vec<u32>, BinaryHeap<HeapEntry>> = HashMap::new();
+    for row in rows {
+        let point = point_dictionary.read(row).unwrap();
+        let group: Vec<u32> = group_indices.iter().map(|i| point[*i]).collect();
+        let heap = heaps.entry(group).or_insert_with(|| BinaryHeap::with_capacity(top_n.n + 1));
+        heap.push(HeapEntry { row: *row, comparator });
+        if heap.len() > top_n.n {
+            heap.pop();
+        }
+    }
+
+    let mut selected: Vec<u32> = heaps.into_values()
+        .flat_map(|heap| heap.into_iter().map(|e| e.row))
+        .collect();
+    selected.sort_unstable();
+    selected
+}
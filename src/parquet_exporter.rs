@@ -0,0 +1,52 @@
+use std::fs::File;
+use std::path::Path;
+
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::ArrowWriter;
+use parquet::errors::Result;
+use parquet::file::properties::WriterProperties;
+
+use crate::datastore::Store;
+use crate::point_list_aggregates_result::PointListAggregateResult;
+
+/// Writes the content of a [`Store`] or the result of a query to parquet files.
+pub struct ParquetExporter {
+    properties: WriterProperties,
+}
+
+impl ParquetExporter {
+    pub fn new() -> ParquetExporter {
+        // The dictionary encoder of parquet 9 reads the values through unaligned pointers.
+        let properties = WriterProperties::builder()
+            .set_dictionary_enabled(false)
+            .build();
+        ParquetExporter { properties }
+    }
+
+    pub fn with_properties(properties: WriterProperties) -> ParquetExporter {
+        ParquetExporter { properties }
+    }
+
+    /// Writes the effective view of the scenario: base values overridden by the scenario ones.
+    pub fn write_scenario<P: AsRef<Path>>(&self, store: &Store, scenario: &str, path: P) -> Result<()> {
+        self.write(&store.get_scenario_batch(scenario), path)
+    }
+
+    pub fn write_result<P: AsRef<Path>>(&self, result: &PointListAggregateResult, path: P) -> Result<()> {
+        self.write(&result.to_record_batch(), path)
+    }
+
+    fn write<P: AsRef<Path>>(&self, batch: &RecordBatch, path: P) -> Result<()> {
+        let file = File::create(path)?;
+        let mut writer = ArrowWriter::try_new(file, batch.schema(), Some(self.properties.clone()))?;
+        writer.write(batch)?;
+        writer.close()?;
+        Ok(())
+    }
+}
+
+impl Default for ParquetExporter {
+    fn default() -> Self {
+        ParquetExporter::new()
+    }
+}
@@ -0,0 +1,143 @@
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+
+use arrow::array::ArrayRef;
+use arrow::compute::cast;
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::{ArrowReader, ParquetFileArrowReader};
+use parquet::errors::{ParquetError, Result};
+use parquet::file::reader::{FileReader, SerializedFileReader};
+
+use crate::datastore::{CHUNK_DEFAULT_SIZE, MAIN_SCENARIO_NAME, Store};
+
+/// Loads parquet files into a [`Store`]. The file is streamed into the store in batches of the size
+/// of its largest row group, see [`Store::append`]. The columns are matched by name against the
+/// store schema, cast to the type expected by the store and checked with [`Store::prepare_batch`]
+/// before each batch is loaded: an invalid batch stops the load, the batches before it stay loaded.
+pub struct ParquetLoader<'a> {
+    store: &'a mut Store,
+}
+
+impl<'a> ParquetLoader<'a> {
+    pub fn new(store: &'a mut Store) -> ParquetLoader<'a> {
+        ParquetLoader { store }
+    }
+
+    /// Creates a store with the schema of the file and loads the file as the base scenario. The
+    /// columns must have a type supported by the store, see [`Store::is_supported_type`], and no
+    /// null value.
+    pub fn create_store<P: AsRef<Path>>(path: P, key_field: &str) -> Result<Store> {
+        let path = path.as_ref();
+        let file_reader = SerializedFileReader::new(File::open(path)?)?;
+        let file_schema = ParquetFileArrowReader::new(Arc::new(file_reader)).get_schema()?;
+        let mut fields = Vec::with_capacity(file_schema.fields().len());
+        for field in file_schema.fields() {
+            if !Store::is_supported_type(field.data_type()) {
+                return Err(ParquetError::General(format!("type {} of field '{}' is not supported", field.data_type(), field.name())));
+            }
+            // The nulls are rejected when the file is loaded.
+            fields.push(Field::new(field.name(), field.data_type().clone(), false));
+        }
+        let schema = Schema::new(fields);
+        let key_index = schema.index_of(key_field)
+            .map_err(|_| ParquetError::General(format!("cannot find key field '{}' in {}", key_field, path.display())))?;
+        if *schema.field(key_index).data_type() != DataType::Int64 {
+            return Err(ParquetError::General(format!("key field '{}' must be of type {}", key_field, DataType::Int64)));
+        }
+
+        let mut store = Store::new(Arc::new(schema), vec![key_index as u32], CHUNK_DEFAULT_SIZE as u32);
+        ParquetLoader::new(&mut store).load(MAIN_SCENARIO_NAME, path)?;
+        Ok(store)
+    }
+
+    /// Loads every field of the store schema from the file.
+    pub fn load<P: AsRef<Path>>(&mut self, scenario: &str, path: P) -> Result<()> {
+        let schema = self.store.schema();
+        let columns: Vec<&str> = schema.fields().iter().map(|f| f.name().as_str()).collect();
+        self.load_columns(scenario, path, &columns)
+    }
+
+    /// Loads only the given fields from the file. The base scenario needs every field of the store
+    /// whereas a scenario can be restricted to the key and the fields it modifies. The first batch
+    /// replaces the values of a scenario loaded before, the next ones are appended.
+    pub fn load_columns<P: AsRef<Path>>(&mut self, scenario: &str, path: P, columns: &[&str]) -> Result<()> {
+        let schema = self.project_schema(scenario, columns)?;
+        let mut loaded = false;
+        ParquetLoader::read(path, &schema, |batch| {
+            let batch = self.store.prepare_batch(scenario, &batch)?;
+            if loaded {
+                self.store.append(scenario, &batch);
+            } else {
+                self.store.load(scenario, &batch);
+                loaded = true;
+            }
+            Ok(())
+        })?;
+        if !loaded {
+            let batch = self.store.prepare_batch(scenario, &RecordBatch::new_empty(schema))?;
+            self.store.load(scenario, &batch);
+        }
+        Ok(())
+    }
+
+    fn project_schema(&self, scenario: &str, columns: &[&str]) -> Result<SchemaRef> {
+        let store_schema = self.store.schema();
+        let key_field = self.store.key_field();
+        if !columns.contains(&key_field.name().as_str()) {
+            return Err(ParquetError::General(format!("key field '{}' must be loaded", key_field.name())));
+        }
+
+        let mut fields = Vec::with_capacity(columns.len());
+        for field in store_schema.fields() {
+            if columns.contains(&field.name().as_str()) {
+                fields.push(field.clone());
+            } else if scenario == MAIN_SCENARIO_NAME {
+                return Err(ParquetError::General(format!("field '{}' is required to load {}", field.name(), MAIN_SCENARIO_NAME)));
+            }
+        }
+        for column in columns {
+            if store_schema.index_of(column).is_err() {
+                return Err(ParquetError::General(format!("field '{}' does not exist in the store", column)));
+            }
+        }
+        Ok(Arc::new(Schema::new(fields)))
+    }
+
+    /// Gives the batches of the file to `load` one at a time, with the fields of the schema.
+    fn read<P, F>(path: P, schema: &SchemaRef, mut load: F) -> Result<()>
+        where P: AsRef<Path>, F: FnMut(RecordBatch) -> Result<()> {
+        let file_reader = SerializedFileReader::new(File::open(path)?)?;
+        let row_group_size = file_reader.metadata().row_groups().iter()
+            .map(|rg| rg.num_rows() as usize)
+            .max()
+            .unwrap_or(0);
+        if row_group_size == 0 {
+            return Ok(());
+        }
+
+        let mut arrow_reader = ParquetFileArrowReader::new(Arc::new(file_reader));
+        let file_schema = arrow_reader.get_schema()?;
+        let mut indices = Vec::with_capacity(schema.fields().len());
+        for field in schema.fields() {
+            let index = file_schema.index_of(field.name())
+                .map_err(|_| ParquetError::General(format!("cannot find column '{}' in file", field.name())))?;
+            indices.push(index);
+        }
+        let mut sorted_indices = indices.clone();
+        sorted_indices.sort_unstable();
+
+        for batch in arrow_reader.get_record_reader_by_columns(sorted_indices.clone(), row_group_size)? {
+            // The reader returns the columns in the file order, put them back in the schema order.
+            let batch = batch?;
+            let mut columns: Vec<ArrayRef> = Vec::with_capacity(indices.len());
+            for (field, index) in schema.fields().iter().zip(indices.iter()) {
+                let position = sorted_indices.binary_search(index).unwrap();
+                columns.push(cast(batch.column(position), field.data_type())?);
+            }
+            load(RecordBatch::try_new(Arc::clone(schema), columns)?)?;
+        }
+        Ok(())
+    }
+}
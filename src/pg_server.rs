@@ -0,0 +1,964 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufReader, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+
+use arrow::array::{Array, ArrayRef, BooleanArray, Float64Array, Int32Array, Int64Array, StringArray, UInt32Array, UInt64Array};
+use arrow::compute::{filter_record_batch, lexsort_to_indices, take, SortColumn, SortOptions};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::record_batch::RecordBatch;
+use sqlparser::ast::{BinaryOperator, Expr, GroupByExpr, ObjectName, OrderByExpr, SelectItem, SetExpr, Statement, TableFactor, Value};
+use sqlparser::dialect::PostgreSqlDialect;
+use sqlparser::parser::Parser;
+
+use crate::datastore::{SCENARIO_FIELD_NAME, Store};
+use crate::point_list_aggregates_result::array_value_to_string;
+use crate::query::Query;
+use crate::query_engine::QueryEngine;
+use crate::sql::{self, SqlError, TABLE_NAME};
+use crate::store_handle::{StoreHandle, StoreUnavailable};
+
+pub const DEFAULT_PG_ADDRESS: &str = "127.0.0.1:5432";
+
+/// The read only view listing the loaded scenarios.
+pub const SCENARIOS_TABLE_NAME: &str = "scenarios";
+
+/// The parameters reported to the clients on startup, also answered by `SHOW`.
+const PARAMETERS: [(&str, &str); 9] = [
+    ("server_version", "14.0"),
+    ("server_encoding", "UTF8"),
+    ("client_encoding", "UTF8"),
+    ("DateStyle", "ISO, MDY"),
+    ("TimeZone", "UTC"),
+    ("integer_datetimes", "on"),
+    ("standard_conforming_strings", "on"),
+    ("transaction_isolation", "read committed"),
+    ("search_path", "public"),
+];
+
+const PROTOCOL_VERSION: i32 = 196608;
+const SSL_REQUEST: i32 = 80877103;
+const GSSENC_REQUEST: i32 = 80877104;
+const CANCEL_REQUEST: i32 = 80877102;
+
+const BOOL_OID: i32 = 16;
+const INT8_OID: i32 = 20;
+const INT4_OID: i32 = 23;
+const TEXT_OID: i32 = 25;
+const FLOAT8_OID: i32 = 701;
+const VARCHAR_OID: i32 = 1043;
+
+const SYNTAX_ERROR: &str = "42601";
+const FEATURE_NOT_SUPPORTED: &str = "0A000";
+const INVALID_QUERY: &str = "42000";
+const UNDEFINED_OBJECT: &str = "42704";
+const UNDEFINED_STATEMENT: &str = "26000";
+const UNDEFINED_CURSOR: &str = "34000";
+const DUPLICATE_STATEMENT: &str = "42P05";
+const OUT_OF_RANGE: &str = "22003";
+const PROTOCOL_VIOLATION: &str = "08P01";
+const INTERNAL_ERROR: &str = "XX000";
+
+/// An error sent to the client, `code` is the postgres SQLSTATE.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PgError {
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl PgError {
+    fn new<S: Into<String>>(code: &'static str, message: S) -> PgError {
+        PgError { code, message: message.into() }
+    }
+}
+
+impl fmt::Display for PgError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.message, self.code)
+    }
+}
+
+impl Error for PgError {}
+
+impl From<StoreUnavailable> for PgError {
+    fn from(e: StoreUnavailable) -> Self {
+        PgError::new(INTERNAL_ERROR, e.to_string())
+    }
+}
+
+impl From<SqlError> for PgError {
+    fn from(e: SqlError) -> Self {
+        let code = match e {
+            SqlError::Parser(_) => SYNTAX_ERROR,
+            SqlError::Unsupported(_) => FEATURE_NOT_SUPPORTED,
+            SqlError::Invalid(_) => INVALID_QUERY,
+        };
+        PgError::new(code, e.to_string())
+    }
+}
+
+type Result<T> = std::result::Result<T, PgError>;
+
+/// Serves a [`Store`] over the postgres wire protocol (version 3) so that sql clients and BI tools
+/// can query it. There is no authentication and no TLS.
+///
+/// - Simple and extended query flows. The parameters of prepared statements are inlined in the
+///   sql text: numbers as numbers and the other values as string literals. Results can be sent in
+///   text or binary format.
+/// - `SELECT` on the `store` table, see [`sql::parse_query`], and on a minimal catalog:
+///   `information_schema.tables`, `information_schema.columns` and the [`SCENARIOS_TABLE_NAME`]
+///   view, with `=` filters, `ORDER BY` and `LIMIT`. `SELECT` without `FROM` of constants and of
+///   `version()`, `current_database()`, `current_schema()` and `current_user`.
+/// - `SHOW` of the startup parameters. `SET` and transaction statements are accepted and ignored,
+///   the store is read only.
+///
+/// Result columns map to `text`, `int8` (integers, unsigned ones included), `int4`, `float8` and
+/// `bool`. Each connection has its own thread, the queries are executed one at a time by the
+/// thread owning the store.
+pub struct PgServer {
+    store: StoreHandle,
+}
+
+impl PgServer {
+    /// Starts the thread owning the store. The store is created on this thread by `create_store`.
+    pub fn new<F: FnOnce() -> Store + Send + 'static>(create_store: F) -> PgServer {
+        PgServer::with_handle(StoreHandle::spawn(create_store))
+    }
+
+    /// Serves a store already shared with other services.
+    pub fn with_handle(store: StoreHandle) -> PgServer {
+        PgServer { store }
+    }
+
+    pub fn serve(&self, address: &str) -> io::Result<()> {
+        self.serve_listener(TcpListener::bind(address)?)
+    }
+
+    /// Serves the connections of an already bound listener, e.g. on port 0 to let the system pick a
+    /// free port.
+    pub fn serve_listener(&self, listener: TcpListener) -> io::Result<()> {
+        for (index, stream) in listener.incoming().enumerate() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    eprintln!("cannot accept a connection: {}", e);
+                    continue;
+                }
+            };
+            let store = self.store.clone();
+            thread::spawn(move || {
+                if let Err(e) = Connection::new(stream, store, index as i32).and_then(|c| c.run()) {
+                    eprintln!("connection closed: {}", e);
+                }
+            });
+        }
+        Ok(())
+    }
+}
+
+struct PreparedStatement {
+    sql: String,
+    parameter_types: Vec<i32>,
+}
+
+struct Portal {
+    sql: String,
+    result_formats: Vec<i16>,
+}
+
+/// What a connection knows about itself, answered by the catalog.
+#[derive(Clone)]
+struct Session {
+    database: String,
+    user: String,
+}
+
+struct Connection {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    out: Vec<u8>,
+    store: StoreHandle,
+    session: Session,
+    process_id: i32,
+    statements: HashMap<String, PreparedStatement>,
+    portals: HashMap<String, Portal>,
+    /// Set by an error in the extended query flow, the messages are ignored until the next Sync.
+    failed: bool,
+}
+
+impl Connection {
+    fn new(stream: TcpStream, store: StoreHandle, process_id: i32) -> io::Result<Connection> {
+        Ok(Connection {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            out: Vec::new(),
+            store,
+            session: Session { database: String::new(), user: String::new() },
+            process_id,
+            statements: HashMap::new(),
+            portals: HashMap::new(),
+            failed: false,
+        })
+    }
+
+    fn run(mut self) -> io::Result<()> {
+        if !self.startup()? {
+            return Ok(());
+        }
+        loop {
+            let mut tag = [0u8; 1];
+            match self.reader.read_exact(&mut tag) {
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(()),
+                result => result?,
+            }
+            let body = self.read_body()?;
+            if self.failed && tag[0] != b'S' && tag[0] != b'X' {
+                continue;
+            }
+
+            let mut payload = Payload::new(&body);
+            let result = match tag[0] {
+                b'Q' => self.simple_query(&mut payload),
+                b'P' => self.parse(&mut payload),
+                b'B' => self.bind(&mut payload),
+                b'D' => self.describe(&mut payload),
+                b'E' => self.execute(&mut payload),
+                b'C' => self.close(&mut payload),
+                b'S' => {
+                    self.failed = false;
+                    self.ready_for_query();
+                    Ok(())
+                }
+                b'H' => Ok(()),
+                b'X' => return Ok(()),
+                other => Err(PgError::new(PROTOCOL_VIOLATION, format!("unsupported message type '{}'", other as char))),
+            };
+            if let Err(e) = result {
+                self.send_error(&e);
+                if tag[0] == b'Q' {
+                    self.ready_for_query();
+                } else {
+                    self.failed = true;
+                }
+            }
+            if matches!(tag[0], b'Q' | b'S' | b'H') {
+                self.flush()?;
+            }
+        }
+    }
+
+    /// Negotiates the connection, returns false when the client does not go on with a session.
+    fn startup(&mut self) -> io::Result<bool> {
+        let body = loop {
+            let body = self.read_body()?;
+            let mut payload = Payload::new(&body);
+            match payload.i32() {
+                Ok(SSL_REQUEST) | Ok(GSSENC_REQUEST) => {
+                    self.writer.write_all(b"N")?;
+                }
+                Ok(PROTOCOL_VERSION) => break body,
+                Ok(CANCEL_REQUEST) => return Ok(false),
+                _ => {
+                    self.send_error(&PgError::new(FEATURE_NOT_SUPPORTED, "unsupported protocol, expected version 3.0"));
+                    self.flush()?;
+                    return Ok(false);
+                }
+            }
+        };
+
+        let mut payload = Payload::new(&body[4..]);
+        while let Ok(name) = payload.cstr() {
+            if name.is_empty() {
+                break;
+            }
+            let value = payload.cstr().unwrap_or_default();
+            match name.as_str() {
+                "user" => self.session.user = value,
+                "database" => self.session.database = value,
+                _ => {}
+            }
+        }
+        if self.session.database.is_empty() {
+            self.session.database = self.session.user.clone();
+        }
+
+        self.send(b'R', &0i32.to_be_bytes());
+        for (name, value) in PARAMETERS.iter() {
+            let mut body = Vec::new();
+            put_cstr(&mut body, name);
+            put_cstr(&mut body, value);
+            self.send(b'S', &body);
+        }
+        let mut body = self.process_id.to_be_bytes().to_vec();
+        body.extend_from_slice(&0i32.to_be_bytes());
+        self.send(b'K', &body);
+        self.ready_for_query();
+        self.flush()?;
+        Ok(true)
+    }
+
+    fn read_body(&mut self) -> io::Result<Vec<u8>> {
+        let mut length = [0u8; 4];
+        self.reader.read_exact(&mut length)?;
+        let length = i32::from_be_bytes(length);
+        if length < 4 {
+            return Err(io::Error::new(ErrorKind::InvalidData, format!("invalid message length {}", length)));
+        }
+        let mut body = vec![0u8; length as usize - 4];
+        self.reader.read_exact(&mut body)?;
+        Ok(body)
+    }
+
+    fn simple_query(&mut self, payload: &mut Payload) -> Result<()> {
+        let sql = payload.cstr()?;
+        let session = self.session.clone();
+        let results = self.store.run(move |store| {
+            let statements = match parse_sql(&sql) {
+                Ok(statements) if statements.is_empty() => return vec![Ok(Plan::Empty)],
+                Ok(statements) => statements,
+                Err(e) => return vec![Err(e)],
+            };
+            let mut results = Vec::with_capacity(statements.len());
+            for statement in statements {
+                let result = plan_statement(store, &session, statement).and_then(|plan| plan.execute(store));
+                let failed = result.is_err();
+                results.push(result);
+                if failed {
+                    break;
+                }
+            }
+            results
+        })?;
+        for result in results {
+            match result {
+                Ok(plan) => self.send_result(plan, &[], true)?,
+                Err(e) => {
+                    self.send_error(&e);
+                    break;
+                }
+            }
+        }
+        self.ready_for_query();
+        Ok(())
+    }
+
+    fn parse(&mut self, payload: &mut Payload) -> Result<()> {
+        let name = payload.cstr()?;
+        let sql = payload.cstr()?;
+        let parameter_count = payload.i16()?;
+        let mut parameter_types = Vec::with_capacity(parameter_count.max(0) as usize);
+        for _ in 0..parameter_count {
+            parameter_types.push(payload.i32()?);
+        }
+        if !name.is_empty() && self.statements.contains_key(&name) {
+            return Err(PgError::new(DUPLICATE_STATEMENT, format!("prepared statement \"{}\" already exists", name)));
+        }
+        let count = placeholders(&sql).iter().map(|p| p.2).max().unwrap_or(0);
+        parameter_types.resize(parameter_types.len().max(count), 0);
+        self.statements.insert(name, PreparedStatement { sql, parameter_types });
+        self.send(b'1', &[]);
+        Ok(())
+    }
+
+    fn bind(&mut self, payload: &mut Payload) -> Result<()> {
+        let portal = payload.cstr()?;
+        let name = payload.cstr()?;
+        let statement = self.statements.get(&name)
+            .ok_or_else(|| PgError::new(UNDEFINED_STATEMENT, format!("prepared statement \"{}\" does not exist", name)))?;
+
+        let formats = payload.formats()?;
+        let count = payload.i16()?.max(0) as usize;
+        let mut literals = Vec::with_capacity(count);
+        for index in 0..count {
+            let length = payload.i32()?;
+            let literal = if length < 0 {
+                "NULL".to_string()
+            } else {
+                let value = payload.take(length as usize)?;
+                let parameter_type = statement.parameter_types.get(index).copied().unwrap_or(0);
+                to_literal(value, parameter_type, format(&formats, index) == 1)?
+            };
+            literals.push(literal);
+        }
+        let result_formats = payload.formats()?;
+        let sql = substitute(&statement.sql, &literals)?;
+        self.portals.insert(portal, Portal { sql, result_formats });
+        self.send(b'2', &[]);
+        Ok(())
+    }
+
+    fn describe(&mut self, payload: &mut Payload) -> Result<()> {
+        let kind = payload.u8()?;
+        let name = payload.cstr()?;
+        let (sql, formats) = match kind {
+            b'S' => {
+                let statement = self.statements.get(&name)
+                    .ok_or_else(|| PgError::new(UNDEFINED_STATEMENT, format!("prepared statement \"{}\" does not exist", name)))?;
+                let mut body = (statement.parameter_types.len() as i16).to_be_bytes().to_vec();
+                for parameter_type in statement.parameter_types.iter() {
+                    let parameter_type = if *parameter_type == 0 { TEXT_OID } else { *parameter_type };
+                    body.extend_from_slice(&parameter_type.to_be_bytes());
+                }
+                // The parameters do not change the columns of the result, any value can be used.
+                let sql = substitute(&statement.sql, &vec!["0".to_string(); statement.parameter_types.len()])?;
+                self.send(b't', &body);
+                (sql, Vec::new())
+            }
+            b'P' => {
+                let portal = self.portals.get(&name)
+                    .ok_or_else(|| PgError::new(UNDEFINED_CURSOR, format!("portal \"{}\" does not exist", name)))?;
+                (portal.sql.clone(), portal.result_formats.clone())
+            }
+            other => return Err(PgError::new(PROTOCOL_VIOLATION, format!("invalid describe kind '{}'", other as char))),
+        };
+
+        let session = self.session.clone();
+        let schema = self.store.run(move |store| plan_one(store, &session, &sql).map(|plan| plan.schema(store)))??;
+        match schema {
+            Some(schema) => self.send_row_description(&schema, &formats),
+            None => self.send(b'n', &[]),
+        }
+        Ok(())
+    }
+
+    /// Sends every row, the row limit of the message is ignored.
+    fn execute(&mut self, payload: &mut Payload) -> Result<()> {
+        let name = payload.cstr()?;
+        let portal = self.portals.get(&name)
+            .ok_or_else(|| PgError::new(UNDEFINED_CURSOR, format!("portal \"{}\" does not exist", name)))?;
+        let sql = portal.sql.clone();
+        let formats = portal.result_formats.clone();
+        let session = self.session.clone();
+        let plan = self.store.run(move |store| plan_one(store, &session, &sql).and_then(|plan| plan.execute(store)))??;
+        self.send_result(plan, &formats, false)
+    }
+
+    fn close(&mut self, payload: &mut Payload) -> Result<()> {
+        let kind = payload.u8()?;
+        let name = payload.cstr()?;
+        match kind {
+            b'S' => self.statements.remove(&name).map(|_| ()),
+            _ => self.portals.remove(&name).map(|_| ()),
+        };
+        self.send(b'3', &[]);
+        Ok(())
+    }
+
+    fn send_result(&mut self, plan: Plan, formats: &[i16], describe: bool) -> Result<()> {
+        match plan {
+            Plan::Empty => self.send(b'I', &[]),
+            Plan::Command(tag) => self.send_command_complete(&tag),
+            Plan::Query(_) => unreachable!("the plan is executed"),
+            Plan::Rows(batch) => {
+                if describe {
+                    self.send_row_description(&batch.schema(), formats);
+                }
+                for row in 0..batch.num_rows() {
+                    let mut body = (batch.num_columns() as i16).to_be_bytes().to_vec();
+                    for (index, column) in batch.columns().iter().enumerate() {
+                        if column.is_null(row) {
+                            body.extend_from_slice(&(-1i32).to_be_bytes());
+                        } else {
+                            let value = encode(column.as_ref(), row, format(formats, index) == 1)?;
+                            body.extend_from_slice(&(value.len() as i32).to_be_bytes());
+                            body.extend_from_slice(&value);
+                        }
+                    }
+                    self.send(b'D', &body);
+                }
+                self.send_command_complete(&format!("SELECT {}", batch.num_rows()));
+            }
+        }
+        Ok(())
+    }
+
+    fn send_row_description(&mut self, schema: &Schema, formats: &[i16]) {
+        let mut body = (schema.fields().len() as i16).to_be_bytes().to_vec();
+        for (index, field) in schema.fields().iter().enumerate() {
+            let (oid, length) = pg_type(field.data_type());
+            put_cstr(&mut body, field.name());
+            body.extend_from_slice(&0i32.to_be_bytes());
+            body.extend_from_slice(&0i16.to_be_bytes());
+            body.extend_from_slice(&oid.to_be_bytes());
+            body.extend_from_slice(&length.to_be_bytes());
+            body.extend_from_slice(&(-1i32).to_be_bytes());
+            body.extend_from_slice(&format(formats, index).to_be_bytes());
+        }
+        self.send(b'T', &body);
+    }
+
+    fn send_command_complete(&mut self, tag: &str) {
+        let mut body = Vec::new();
+        put_cstr(&mut body, tag);
+        self.send(b'C', &body);
+    }
+
+    fn send_error(&mut self, error: &PgError) {
+        let mut body = Vec::new();
+        for (field, value) in [(b'S', "ERROR"), (b'V', "ERROR"), (b'C', error.code), (b'M', error.message.as_str())] {
+            body.push(field);
+            put_cstr(&mut body, value);
+        }
+        body.push(0);
+        self.send(b'E', &body);
+    }
+
+    fn ready_for_query(&mut self) {
+        self.send(b'Z', b"I");
+    }
+
+    fn send(&mut self, tag: u8, body: &[u8]) {
+        self.out.push(tag);
+        self.out.extend_from_slice(&(body.len() as i32 + 4).to_be_bytes());
+        self.out.extend_from_slice(body);
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.write_all(&self.out)?;
+        self.out.clear();
+        self.writer.flush()
+    }
+}
+
+/// Reads the fields of a message.
+struct Payload<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Payload<'a> {
+    fn new(data: &'a [u8]) -> Payload<'a> {
+        Payload { data, position: 0 }
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8]> {
+        if self.position + length > self.data.len() {
+            return Err(PgError::new(PROTOCOL_VIOLATION, "truncated message"));
+        }
+        let bytes = &self.data[self.position..self.position + length];
+        self.position += length;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn i16(&mut self) -> Result<i16> {
+        Ok(i16::from_be_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn i32(&mut self) -> Result<i32> {
+        Ok(i32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn cstr(&mut self) -> Result<String> {
+        let length = self.data[self.position..].iter().position(|b| *b == 0)
+            .ok_or_else(|| PgError::new(PROTOCOL_VIOLATION, "unterminated string"))?;
+        let bytes = self.take(length + 1)?;
+        String::from_utf8(bytes[..length].to_vec()).map_err(|e| PgError::new(PROTOCOL_VIOLATION, e.to_string()))
+    }
+
+    fn formats(&mut self) -> Result<Vec<i16>> {
+        let count = self.i16()?.max(0);
+        (0..count).map(|_| self.i16()).collect()
+    }
+}
+
+fn put_cstr(buffer: &mut Vec<u8>, value: &str) {
+    buffer.extend_from_slice(value.as_bytes());
+    buffer.push(0);
+}
+
+/// The format of a column or of a parameter: no code means text, a single code applies to all.
+fn format(formats: &[i16], index: usize) -> i16 {
+    match formats.len() {
+        0 => 0,
+        1 => formats[0],
+        _ => formats.get(index).copied().unwrap_or(0),
+    }
+}
+
+/// The type oid and length of the postgres type of a column.
+fn pg_type(data_type: &DataType) -> (i32, i16) {
+    match data_type {
+        DataType::Boolean => (BOOL_OID, 1),
+        DataType::Int64 | DataType::UInt32 | DataType::UInt64 => (INT8_OID, 8),
+        DataType::Int32 => (INT4_OID, 4),
+        DataType::Float64 => (FLOAT8_OID, 8),
+        _ => (TEXT_OID, -1),
+    }
+}
+
+fn pg_type_name(data_type: &DataType) -> &'static str {
+    match pg_type(data_type).0 {
+        BOOL_OID => "boolean",
+        INT8_OID => "bigint",
+        INT4_OID => "integer",
+        FLOAT8_OID => "double precision",
+        _ => "text",
+    }
+}
+
+fn encode(column: &dyn Array, row: usize, binary: bool) -> Result<Vec<u8>> {
+    macro_rules! value {
+        ($array_type:ty) => { column.as_any().downcast_ref::<$array_type>().unwrap().value(row) };
+    }
+    let bytes = match (column.data_type(), binary) {
+        (DataType::Boolean, true) => vec![value!(BooleanArray) as u8],
+        (DataType::Boolean, false) => if value!(BooleanArray) { b"t".to_vec() } else { b"f".to_vec() },
+        (DataType::Int64, true) => value!(Int64Array).to_be_bytes().to_vec(),
+        (DataType::UInt32, true) => (value!(UInt32Array) as i64).to_be_bytes().to_vec(),
+        (DataType::UInt64, true) => i64::try_from(value!(UInt64Array))
+            .map_err(|_| PgError::new(OUT_OF_RANGE, format!("{} is out of range for type bigint", value!(UInt64Array))))?
+            .to_be_bytes().to_vec(),
+        (DataType::Int32, true) => value!(Int32Array).to_be_bytes().to_vec(),
+        (DataType::Float64, true) => value!(Float64Array).to_be_bytes().to_vec(),
+        _ => array_value_to_string(column, row)
+            .map_err(|e| PgError::new(INTERNAL_ERROR, e.to_string()))?
+            .into_bytes(),
+    };
+    Ok(bytes)
+}
+
+/// The sql literal of a parameter value.
+fn to_literal(value: &[u8], parameter_type: i32, binary: bool) -> Result<String> {
+    let invalid = || PgError::new(PROTOCOL_VIOLATION, format!("invalid binary value for a parameter of type {}", parameter_type));
+    let text = if binary {
+        match parameter_type {
+            BOOL_OID => return Ok(if value.first() == Some(&1) { "TRUE" } else { "FALSE" }.to_string()),
+            INT8_OID => i64::from_be_bytes(value.try_into().map_err(|_| invalid())?).to_string(),
+            INT4_OID => i32::from_be_bytes(value.try_into().map_err(|_| invalid())?).to_string(),
+            FLOAT8_OID => f64::from_be_bytes(value.try_into().map_err(|_| invalid())?).to_string(),
+            0 | TEXT_OID | VARCHAR_OID => String::from_utf8(value.to_vec()).map_err(|_| invalid())?,
+            _ => return Err(PgError::new(FEATURE_NOT_SUPPORTED, format!("binary parameters of type {}", parameter_type))),
+        }
+    } else {
+        String::from_utf8(value.to_vec()).map_err(|e| PgError::new(PROTOCOL_VIOLATION, e.to_string()))?
+    };
+    let numeric = !text.is_empty()
+        && text.chars().all(|c| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E'))
+        && text.parse::<f64>().is_ok();
+    if numeric {
+        Ok(text)
+    } else {
+        Ok(format!("'{}'", text.replace('\'', "''")))
+    }
+}
+
+/// The `$n` placeholders outside of string literals and quoted identifiers, as their byte range
+/// and their 1-based index.
+fn placeholders(sql: &str) -> Vec<(usize, usize, usize)> {
+    let bytes = sql.as_bytes();
+    let mut placeholders = Vec::new();
+    let mut quote = None;
+    let mut position = 0;
+    while position < bytes.len() {
+        let byte = bytes[position];
+        match quote {
+            Some(q) if byte == q => quote = None,
+            Some(_) => {}
+            None if byte == b'\'' || byte == b'"' => quote = Some(byte),
+            None if byte == b'$' => {
+                let end = position + 1 + bytes[position + 1..].iter().take_while(|b| b.is_ascii_digit()).count();
+                if let Ok(index) = sql[position + 1..end].parse::<usize>() {
+                    placeholders.push((position, end, index));
+                    position = end;
+                    continue;
+                }
+            }
+            None => {}
+        }
+        position += 1;
+    }
+    placeholders
+}
+
+fn substitute(sql: &str, literals: &[String]) -> Result<String> {
+    let mut result = String::with_capacity(sql.len());
+    let mut last = 0;
+    for (start, end, index) in placeholders(sql) {
+        let literal = index.checked_sub(1).and_then(|i| literals.get(i))
+            .ok_or_else(|| PgError::new(PROTOCOL_VIOLATION, format!("there is no parameter ${}", index)))?;
+        result.push_str(&sql[last..start]);
+        result.push_str(literal);
+        last = end;
+    }
+    result.push_str(&sql[last..]);
+    Ok(result)
+}
+
+/// A planned statement.
+enum Plan {
+    Empty,
+    Command(String),
+    /// A query on the store, not executed yet.
+    Query(Query),
+    Rows(RecordBatch),
+}
+
+impl Plan {
+    /// The columns of the result, none for the statements without rows.
+    fn schema(&self, store: &Store) -> Option<SchemaRef> {
+        match self {
+            Plan::Empty | Plan::Command(_) => None,
+            Plan::Rows(batch) => Some(batch.schema()),
+            Plan::Query(query) => {
+                let schema = store.schema();
+                let mut fields: Vec<Field> = query.coordinates.keys().map(|c| Field::new(c, DataType::Utf8, false)).collect();
+                for measure in query.measures.iter() {
+                    let data_type = match schema.field_with_name(&measure.field).map(|f| f.data_type()) {
+                        Ok(DataType::UInt32) => DataType::UInt64,
+                        Ok(data_type) => data_type.clone(),
+                        Err(_) => DataType::Utf8,
+                    };
+                    fields.push(Field::new(&measure.alias(), data_type, false));
+                }
+                Some(Arc::new(Schema::new(fields)))
+            }
+        }
+    }
+
+    fn execute(self, store: &Store) -> Result<Plan> {
+        match self {
+            Plan::Query(query) => Ok(Plan::Rows(QueryEngine::new(store).execute(&query).to_record_batch())),
+            plan => Ok(plan),
+        }
+    }
+}
+
+fn parse_sql(sql: &str) -> Result<Vec<Statement>> {
+    Parser::parse_sql(&PostgreSqlDialect {}, sql).map_err(|e| PgError::new(SYNTAX_ERROR, e.to_string()))
+}
+
+fn plan_one(store: &Store, session: &Session, sql: &str) -> Result<Plan> {
+    let mut statements = parse_sql(sql)?;
+    match statements.len() {
+        0 => Ok(Plan::Empty),
+        1 => plan_statement(store, session, statements.remove(0)),
+        _ => Err(PgError::new(SYNTAX_ERROR, "cannot insert multiple commands into a prepared statement")),
+    }
+}
+
+fn plan_statement(store: &Store, session: &Session, statement: Statement) -> Result<Plan> {
+    let tag = match &statement {
+        Statement::Query(_) => return plan_select(store, session, statement),
+        Statement::ShowVariable { variable } => return show(variable.iter().map(|i| i.value.as_str()).collect::<Vec<_>>().join(" ")),
+        Statement::SetVariable { .. } | Statement::SetTimeZone { .. } | Statement::SetNames { .. }
+        | Statement::SetNamesDefault { .. } | Statement::SetTransaction { .. } => "SET",
+        Statement::StartTransaction { .. } => "BEGIN",
+        Statement::Commit { .. } => "COMMIT",
+        Statement::Rollback { .. } => "ROLLBACK",
+        Statement::Discard { .. } => "DISCARD ALL",
+        Statement::Deallocate { .. } => "DEALLOCATE",
+        statement => return Err(PgError::new(FEATURE_NOT_SUPPORTED, format!("'{}', the store is read only and only answers SELECT", statement))),
+    };
+    Ok(Plan::Command(tag.to_string()))
+}
+
+fn show(name: String) -> Result<Plan> {
+    let (_, value) = PARAMETERS.iter().find(|(parameter, _)| parameter.eq_ignore_ascii_case(&name))
+        .ok_or_else(|| PgError::new(UNDEFINED_OBJECT, format!("unrecognized configuration parameter \"{}\"", name)))?;
+    let schema = Arc::new(Schema::new(vec![Field::new(&name, DataType::Utf8, false)]));
+    let batch = RecordBatch::try_new(schema, vec![Arc::new(StringArray::from(vec![*value]))]).unwrap();
+    Ok(Plan::Rows(batch))
+}
+
+fn plan_select(store: &Store, session: &Session, statement: Statement) -> Result<Plan> {
+    if let Statement::Query(query) = &statement {
+        if let SetExpr::Select(select) = query.body.as_ref() {
+            if select.from.is_empty() {
+                return plan_constants(session, &select.projection);
+            }
+            if let [table] = select.from.as_slice() {
+                if let TableFactor::Table { name, .. } = &table.relation {
+                    if let Some(batch) = catalog_table(store, session, name) {
+                        let group_by_empty = matches!(&select.group_by, GroupByExpr::Expressions(e, _) if e.is_empty());
+                        if !table.joins.is_empty() || !group_by_empty || select.having.is_some() || select.distinct.is_some() {
+                            return Err(PgError::new(FEATURE_NOT_SUPPORTED, "the catalog only supports filters, ORDER BY and LIMIT"));
+                        }
+                        let batch = filter_catalog(batch, select.selection.as_ref())?;
+                        let order_by = query.order_by.as_ref().map(|o| o.exprs.as_slice()).unwrap_or(&[]);
+                        let batch = sort_catalog(batch, order_by)?;
+                        let batch = project_catalog(batch, &select.projection)?;
+                        let limit = match &query.limit {
+                            Some(Expr::Value(Value::Number(n, _))) => n.parse::<usize>().ok(),
+                            _ => None,
+                        };
+                        let length = limit.unwrap_or(batch.num_rows()).min(batch.num_rows());
+                        return Ok(Plan::Rows(batch.slice(0, length)));
+                    }
+                }
+            }
+        }
+    }
+
+    let query = sql::parse_query(&statement.to_string(), store)?;
+    if let Err(errors) = query.validate(store) {
+        let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        return Err(PgError::new(INVALID_QUERY, errors.join(", ")));
+    }
+    Ok(Plan::Query(query))
+}
+
+/// `SELECT 1`, `SELECT version()`... as sent by the clients to check the connection.
+fn plan_constants(session: &Session, projection: &[SelectItem]) -> Result<Plan> {
+    let mut fields = Vec::with_capacity(projection.len());
+    let mut columns: Vec<ArrayRef> = Vec::with_capacity(projection.len());
+    for item in projection {
+        let (expr, alias) = match item {
+            SelectItem::UnnamedExpr(expr) => (expr, None),
+            SelectItem::ExprWithAlias { expr, alias } => (expr, Some(alias.value.clone())),
+            item => return Err(PgError::new(FEATURE_NOT_SUPPORTED, format!("'{}' without a FROM clause", item))),
+        };
+        let function = match expr {
+            Expr::Function(function) => Some(function.name.to_string().to_lowercase()),
+            Expr::Identifier(ident) => Some(ident.value.to_lowercase()),
+            _ => None,
+        };
+        let column: ArrayRef = match (expr, function.as_deref()) {
+            (_, Some("version")) => Arc::new(StringArray::from(vec![format!("PostgreSQL {} (rustchristmasdb)", PARAMETERS[0].1)])),
+            (_, Some("current_database")) => Arc::new(StringArray::from(vec![session.database.clone()])),
+            (_, Some("current_schema")) => Arc::new(StringArray::from(vec!["public"])),
+            (_, Some("current_user")) | (_, Some("session_user")) | (_, Some("user")) => Arc::new(StringArray::from(vec![session.user.clone()])),
+            (Expr::Value(Value::Number(n, _)), _) => match (n.parse::<i32>(), n.parse::<i64>(), n.parse::<f64>()) {
+                (Ok(v), _, _) => Arc::new(Int32Array::from(vec![v])),
+                (_, Ok(v), _) => Arc::new(Int64Array::from(vec![v])),
+                (_, _, Ok(v)) => Arc::new(Float64Array::from(vec![v])),
+                _ => return Err(PgError::new(SYNTAX_ERROR, format!("invalid number {}", n))),
+            },
+            (Expr::Value(Value::SingleQuotedString(s)), _) => Arc::new(StringArray::from(vec![s.clone()])),
+            (Expr::Value(Value::Boolean(b)), _) => Arc::new(BooleanArray::from(vec![*b])),
+            _ => return Err(PgError::new(FEATURE_NOT_SUPPORTED, format!("'{}' without a FROM clause", expr))),
+        };
+        let name = alias.or(function).unwrap_or_else(|| "?column?".to_string());
+        fields.push(Field::new(&name, column.data_type().clone(), false));
+        columns.push(column);
+    }
+    Ok(Plan::Rows(RecordBatch::try_new(Arc::new(Schema::new(fields)), columns).unwrap()))
+}
+
+/// The tables of the catalog, as batches.
+fn catalog_table(store: &Store, session: &Session, name: &ObjectName) -> Option<RecordBatch> {
+    let name: Vec<String> = name.0.iter().map(|i| i.value.to_lowercase()).collect();
+    let string_column = |name: &str, values: Vec<String>| (Field::new(name, DataType::Utf8, false), Arc::new(StringArray::from(values)) as ArrayRef);
+    let columns = match name.join(".").as_str() {
+        "information_schema.tables" => {
+            let tables = [(TABLE_NAME, "BASE TABLE"), (SCENARIOS_TABLE_NAME, "VIEW")];
+            vec![
+                string_column("table_catalog", vec![session.database.clone(); tables.len()]),
+                string_column("table_schema", vec!["public".to_string(); tables.len()]),
+                string_column("table_name", tables.iter().map(|t| t.0.to_string()).collect()),
+                string_column("table_type", tables.iter().map(|t| t.1.to_string()).collect()),
+            ]
+        }
+        "information_schema.columns" => {
+            let mut columns = vec![(TABLE_NAME, SCENARIO_FIELD_NAME.to_string(), "text")];
+            for field in store.schema().fields() {
+                columns.push((TABLE_NAME, field.name().clone(), pg_type_name(field.data_type())));
+            }
+            columns.push((SCENARIOS_TABLE_NAME, SCENARIO_FIELD_NAME.to_string(), "text"));
+            let positions: Vec<i32> = columns.iter().enumerate()
+                .map(|(index, column)| columns[..index].iter().filter(|c| c.0 == column.0).count() as i32 + 1)
+                .collect();
+            vec![
+                string_column("table_catalog", vec![session.database.clone(); columns.len()]),
+                string_column("table_schema", vec!["public".to_string(); columns.len()]),
+                string_column("table_name", columns.iter().map(|c| c.0.to_string()).collect()),
+                string_column("column_name", columns.iter().map(|c| c.1.clone()).collect()),
+                (Field::new("ordinal_position", DataType::Int32, false), Arc::new(Int32Array::from(positions)) as ArrayRef),
+                string_column("data_type", columns.iter().map(|c| c.2.to_string()).collect()),
+                string_column("is_nullable", vec!["NO".to_string(); columns.len()]),
+            ]
+        }
+        "scenarios" | "public.scenarios" => {
+            let scenarios = match store.dictionary_provider.dicos.get(SCENARIO_FIELD_NAME) {
+                Some(dictionary) => (0..dictionary.size() as u32).filter_map(|p| dictionary.read(&p).cloned()).collect(),
+                None => Vec::new(),
+            };
+            vec![string_column(SCENARIO_FIELD_NAME, scenarios)]
+        }
+        _ => return None,
+    };
+    let (fields, columns): (Vec<Field>, Vec<ArrayRef>) = columns.into_iter().unzip();
+    Some(RecordBatch::try_new(Arc::new(Schema::new(fields)), columns).unwrap())
+}
+
+fn catalog_column(batch: &RecordBatch, expr: &Expr) -> Result<usize> {
+    let name = match expr {
+        Expr::Identifier(ident) => &ident.value,
+        Expr::CompoundIdentifier(idents) => &idents.last().unwrap().value,
+        _ => return Err(PgError::new(FEATURE_NOT_SUPPORTED, format!("'{}', expected a column of the catalog", expr))),
+    };
+    batch.schema().index_of(name).map_err(|_| PgError::new(INVALID_QUERY, format!("column \"{}\" does not exist", name)))
+}
+
+/// Keeps the rows matching a conjunction of `column = literal`.
+fn filter_catalog(batch: RecordBatch, selection: Option<&Expr>) -> Result<RecordBatch> {
+    let expr = match selection {
+        Some(expr) => expr,
+        None => return Ok(batch),
+    };
+    match expr {
+        Expr::Nested(expr) => filter_catalog(batch, Some(expr)),
+        Expr::BinaryOp { left, op: BinaryOperator::And, right } => {
+            let batch = filter_catalog(batch, Some(left))?;
+            filter_catalog(batch, Some(right))
+        }
+        Expr::BinaryOp { left, op: BinaryOperator::Eq, right } => {
+            let index = catalog_column(&batch, left)?;
+            let value = match right.as_ref() {
+                Expr::Value(Value::SingleQuotedString(s)) | Expr::Value(Value::Number(s, _)) => s,
+                expr => return Err(PgError::new(FEATURE_NOT_SUPPORTED, format!("'{}', expected a literal", expr))),
+            };
+            let column = batch.column(index);
+            let mask: BooleanArray = (0..batch.num_rows())
+                .map(|row| Some(array_value_to_string(column.as_ref(), row).map(|v| &v == value).unwrap_or(false)))
+                .collect();
+            filter_record_batch(&batch, &mask).map_err(|e| PgError::new(INTERNAL_ERROR, e.to_string()))
+        }
+        expr => Err(PgError::new(FEATURE_NOT_SUPPORTED, format!("'{}', the catalog can only be filtered with AND and =", expr))),
+    }
+}
+
+fn sort_catalog(batch: RecordBatch, order_by: &[OrderByExpr]) -> Result<RecordBatch> {
+    if order_by.is_empty() || batch.num_rows() == 0 {
+        return Ok(batch);
+    }
+    let mut sort_columns = Vec::with_capacity(order_by.len());
+    for expr in order_by {
+        let descending = expr.asc == Some(false);
+        sort_columns.push(SortColumn {
+            values: Arc::clone(batch.column(catalog_column(&batch, &expr.expr)?)),
+            options: Some(SortOptions { descending, nulls_first: expr.nulls_first.unwrap_or(descending) }),
+        });
+    }
+    let internal = |e: arrow::error::ArrowError| PgError::new(INTERNAL_ERROR, e.to_string());
+    let indices = lexsort_to_indices(&sort_columns, None).map_err(internal)?;
+    let columns = batch.columns().iter().map(|c| take(c.as_ref(), &indices, None)).collect::<std::result::Result<Vec<_>, _>>().map_err(internal)?;
+    RecordBatch::try_new(batch.schema(), columns).map_err(internal)
+}
+
+fn project_catalog(batch: RecordBatch, projection: &[SelectItem]) -> Result<RecordBatch> {
+    let mut fields = Vec::new();
+    let mut columns = Vec::new();
+    for item in projection {
+        let (expr, alias) = match item {
+            SelectItem::Wildcard(_) | SelectItem::QualifiedWildcard(_, _) => {
+                fields.extend(batch.schema().fields().iter().cloned());
+                columns.extend(batch.columns().iter().cloned());
+                continue;
+            }
+            SelectItem::UnnamedExpr(expr) => (expr, None),
+            SelectItem::ExprWithAlias { expr, alias } => (expr, Some(alias.value.as_str())),
+        };
+        let index = catalog_column(&batch, expr)?;
+        let field = batch.schema().field(index).clone();
+        fields.push(Field::new(alias.unwrap_or(field.name()), field.data_type().clone(), false));
+        columns.push(Arc::clone(batch.column(index)));
+    }
+    RecordBatch::try_new(Arc::new(Schema::new(fields)), columns).map_err(|e| PgError::new(INTERNAL_ERROR, e.to_string()))
+}
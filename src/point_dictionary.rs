@@ -1,36 +1,111 @@
-use crate::dictionary_provider::Dictionary;
+use std::collections::HashMap;
 
-#[derive(Debug)]
+use ahash::RandomState;
+
+/// Maps the points of a query, the dictionary positions of its coordinates, to consecutive
+/// positions. When the cardinalities of the coordinates are known, the coordinates of a point are
+/// bit-packed into an integer key, the points too wide to be packed are keyed by a `Vec`.
+#[derive(Debug, Clone)]
 pub struct PointDictionary {
-    dic: Dictionary<Vec<u32>>,
+    keys: PointKeys,
+    /// The number of bits of each coordinate in a packed key.
+    bits: Vec<u32>,
+    /// The points by position, one after the other.
+    points: Vec<u32>,
     point_length: u32,
 }
 
+#[derive(Debug, Clone)]
+enum PointKeys {
+    Packed64(HashMap<u64, u32, RandomState>),
+    Packed128(HashMap<u128, u32, RandomState>),
+    Wide(HashMap<Vec<u32>, u32, RandomState>),
+}
+
 impl PointDictionary {
-    pub fn new(point_length: u32) -> PointDictionary {
+    /// Creates a dictionary of points whose i-th coordinate is lower than `cardinalities[i]`.
+    pub fn with_cardinalities(cardinalities: &[u32]) -> PointDictionary {
+        let bits: Vec<u32> = cardinalities.iter()
+            .map(|cardinality| u32::BITS - cardinality.saturating_sub(1).leading_zeros())
+            .collect();
+        let keys = match bits.iter().sum::<u32>() {
+            width if width <= u64::BITS => PointKeys::Packed64(HashMap::default()),
+            width if width <= u128::BITS => PointKeys::Packed128(HashMap::default()),
+            _ => PointKeys::Wide(HashMap::default()),
+        };
         PointDictionary {
-            dic: Dictionary::new(),
-            point_length,
+            keys,
+            bits,
+            points: Vec::new(),
+            point_length: cardinalities.len() as u32,
         }
     }
 
-    pub fn map(&mut self, point: &[u32]) -> &u32 {
-        self.dic.map(Vec::from(point))
+    /// Returns the position of the point, the point is only copied when it is new.
+    pub fn map(&mut self, point: &[u32]) -> u32 {
+        let size = self.size() as u32;
+        let position = match &mut self.keys {
+            PointKeys::Packed64(map) => {
+                let key = pack::<u64>(&self.bits, point).unwrap_or_else(|| panic!("cannot pack the point {:?}", point));
+                *map.entry(key).or_insert(size)
+            }
+            PointKeys::Packed128(map) => {
+                let key = pack::<u128>(&self.bits, point).unwrap_or_else(|| panic!("cannot pack the point {:?}", point));
+                *map.entry(key).or_insert(size)
+            }
+            PointKeys::Wide(map) => match map.get(point) {
+                Some(position) => *position,
+                None => *map.entry(Vec::from(point)).or_insert(size),
+            },
+        };
+        if position == size {
+            self.points.extend_from_slice(point);
+        }
+        position
     }
 
-    pub fn read(&self, position: &u32) -> Option<&Vec<u32>> {
-        self.dic.read(position)
+    pub fn read(&self, position: &u32) -> Option<&[u32]> {
+        let start = *position as usize * self.point_length as usize;
+        self.points.get(start..start + self.point_length as usize)
     }
 
     pub fn get_position(&self, point: &[u32]) -> Option<&u32> {
-        self.dic.get_position(&Vec::from(point))
+        match &self.keys {
+            PointKeys::Packed64(map) => map.get(&pack::<u64>(&self.bits, point)?),
+            PointKeys::Packed128(map) => map.get(&pack::<u128>(&self.bits, point)?),
+            PointKeys::Wide(map) => map.get(point),
+        }
     }
 
     pub fn size(&self) -> usize {
-        self.dic.size()
+        match &self.keys {
+            PointKeys::Packed64(map) => map.len(),
+            PointKeys::Packed128(map) => map.len(),
+            PointKeys::Wide(map) => map.len(),
+        }
     }
 
+    /// The number of coordinates of the points, see [`PointDictionary::size`] for the number of
+    /// points.
+    #[allow(clippy::len_without_is_empty)]
     pub fn len(&self) -> u32 {
         self.point_length
     }
 }
+
+/// Packs the coordinates of a point, `None` if the point does not have a coordinate per
+/// cardinality or if a coordinate does not fit in its bits.
+fn pack<K>(bits: &[u32], point: &[u32]) -> Option<K>
+    where K: From<u32> + std::ops::Shl<u32, Output=K> + std::ops::BitOr<Output=K> {
+    if point.len() != bits.len() {
+        return None;
+    }
+    let mut key = K::from(0);
+    for (bits, coordinate) in bits.iter().zip(point) {
+        if *bits < u32::BITS && coordinate >> bits != 0 {
+            return None;
+        }
+        key = (key << *bits) | K::from(*coordinate);
+    }
+    Some(key)
+}
@@ -1,52 +1,49 @@
 use std::any::Any;
-use std::collections::HashMap;
 use std::fmt;
+use std::sync::Arc;
 
 
 
 use arrow::array;
 
-use arrow::array::{Array, Float64Array, UInt32Array};
-use arrow::datatypes::{DataType};
+use arrow::array::{Array, ArrayRef, Float64Array, PrimitiveArray, StringArray, UInt32Array, UInt64Array};
+use arrow::datatypes::{ArrowPrimitiveType, DataType, Field, Schema, SchemaRef};
 use arrow::error::ArrowError;
+use arrow::record_batch::RecordBatch;
 
 use comfy_table::{Table, Cell};
 
 use crate::dictionary_provider::Dictionary;
-use crate::aggregator::Aggregator;
 use crate::{make_string, assert_row_value};
 use crate::point_dictionary::PointDictionary;
+use crate::datastore::Store;
 
 pub struct PointListAggregateResult<'a> {
     point_dictionary: PointDictionary,
     point_names: Vec<String>,
-    aggregators: Vec<Box<dyn Aggregator>>,
+    aggregate_fields: Vec<Field>,
+    aggregates: Vec<ArrayRef>,
     aggregate_names: Vec<String>,
     dictionaries: Vec<&'a Dictionary<String>>,
 }
 
 impl<'a> PointListAggregateResult<'a> {
-    pub fn new(point_dictionary: PointDictionary,
-               point_names: Vec<String>,
-               dictionaries: Vec<&'a Dictionary<String>>,
-               aggregators_by_scenario: HashMap<String, Vec<Box<dyn Aggregator>>>) -> PointListAggregateResult<'a> {
-        let mut aggregators_vec = Vec::new();
-        for (_, aggregators) in aggregators_by_scenario.into_iter() {
-            for aggregator in aggregators.into_iter() {
-                aggregators_vec.push(aggregator);
-            }
-            break;
-        }
-
-        let aggregate_names = aggregators_vec.iter()
-            .map(|a| a.get_field().name().to_string())
+    /// Creates a result from the aggregated columns. Each column must contain one value per point.
+    pub(crate) fn from_columns(point_dictionary: PointDictionary,
+                               point_names: Vec<String>,
+                               dictionaries: Vec<&'a Dictionary<String>>,
+                               aggregate_fields: Vec<Field>,
+                               aggregates: Vec<ArrayRef>) -> PointListAggregateResult<'a> {
+        let aggregate_names = aggregate_fields.iter()
+            .map(|f| f.name().to_string())
             .collect();
 
         PointListAggregateResult {
             point_dictionary,
             point_names,
             dictionaries,
-            aggregators: aggregators_vec,
+            aggregate_fields,
+            aggregates,
             aggregate_names,
         }
     }
@@ -65,8 +62,8 @@ impl<'a> PointListAggregateResult<'a> {
         match position {
             None => { panic!("point {:?} does not exist", coordinates) }
             Some(row) => {
-                for i in 0..self.aggregators.len() {
-                    let array = self.aggregators[i].get_destination();
+                for i in 0..self.aggregates.len() {
+                    let array = self.aggregates[i].as_ref();
                     let r = *row as usize;
                     match array.data_type() {
                         DataType::UInt32 => assert_row_value!(UInt32Array, u32, array, r, expected_value),
@@ -82,6 +79,110 @@ impl<'a> PointListAggregateResult<'a> {
         self.point_dictionary.size()
     }
 
+    pub fn point_names(&self) -> &[String] {
+        &self.point_names
+    }
+
+    pub fn aggregate_names(&self) -> &[String] {
+        &self.aggregate_names
+    }
+
+    /// Returns the row of the point, `None` if one of the coordinates is unknown or if the point
+    /// does not exist. Coordinates are given in the order of [`PointListAggregateResult::point_names`].
+    pub fn get_row(&self, coordinates: &[&str]) -> Option<usize> {
+        if coordinates.len() != self.point_names.len() {
+            return None;
+        }
+        let mut buffer = Vec::with_capacity(coordinates.len());
+        for (dictionary, coordinate) in self.dictionaries.iter().zip(coordinates.iter()) {
+            buffer.push(*dictionary.get_position(&coordinate.to_string())?);
+        }
+        self.point_dictionary.get_position(&buffer[..]).map(|row| *row as usize)
+    }
+
+    pub fn get_coordinates(&self, row: usize) -> Vec<&str> {
+        let point = self.point_dictionary.read(&(row as u32)).unwrap();
+        point.iter()
+            .zip(self.dictionaries.iter())
+            .map(|(position, dictionary)| dictionary.read(position).unwrap().as_str())
+            .collect()
+    }
+
+    pub fn get_value(&self, row: usize, aggregate_index: usize) -> AggregateValue {
+        AggregateValue::read(self.aggregates[aggregate_index].as_ref(), row)
+    }
+
+    /// Returns all the aggregated values of the point.
+    pub fn get_values(&self, coordinates: &[&str]) -> Option<Vec<AggregateValue>> {
+        let row = self.get_row(coordinates)?;
+        Some((0..self.aggregates.len()).map(|i| self.get_value(row, i)).collect())
+    }
+
+    /// Returns the value of an aggregate for a point. `None` if the point or the aggregate do not
+    /// exist or if the aggregate is not of type `T`.
+    pub fn get_aggregate<T: ArrowPrimitiveType>(&self, coordinates: &[&str], alias: &str) -> Option<T::Native> {
+        let row = self.get_row(coordinates)?;
+        self.get_typed_column::<T>(alias).map(|values| values[row])
+    }
+
+    /// Returns the column of an aggregate, trimmed to the number of points.
+    pub fn get_column(&self, alias: &str) -> Option<ArrayRef> {
+        let index = self.aggregate_names.iter().position(|name| name == alias)?;
+        Some(Arc::clone(&self.aggregates[index]))
+    }
+
+    /// Same as [`PointListAggregateResult::get_column`] but returns the raw values. `None` if the
+    /// aggregate is not of type `T`.
+    pub fn get_typed_column<T: ArrowPrimitiveType>(&self, alias: &str) -> Option<&[T::Native]> {
+        let index = self.aggregate_names.iter().position(|name| name == alias)?;
+        let array = self.aggregates[index].as_any().downcast_ref::<PrimitiveArray<T>>()?;
+        Some(array.values())
+    }
+
+    /// Iterates over the points as (coordinates, aggregated values), in the order of the point dictionary.
+    pub fn iter(&self) -> impl Iterator<Item=(Vec<&str>, Vec<AggregateValue>)> + '_ {
+        (0..self.size()).map(move |row| {
+            let values = (0..self.aggregates.len()).map(|i| self.get_value(row, i)).collect();
+            (self.get_coordinates(row), values)
+        })
+    }
+
+    /// The schema of [`PointListAggregateResult::to_record_batch`]: one Utf8 column per coordinate
+    /// (coordinates are always dictionary encoded strings) followed by one column per aggregate.
+    pub fn schema(&self) -> SchemaRef {
+        let mut fields = Vec::with_capacity(self.point_names.len() + self.aggregates.len());
+        for name in self.point_names.iter() {
+            fields.push(Field::new(name, DataType::Utf8, false));
+        }
+        fields.extend(self.aggregate_fields.iter().cloned());
+        Arc::new(Schema::new(fields))
+    }
+
+    /// Copies the result without its references to the store.
+    pub fn detach(&self) -> DetachedResult {
+        DetachedResult {
+            point_dictionary: self.point_dictionary.clone(),
+            point_names: self.point_names.clone(),
+            aggregate_fields: self.aggregate_fields.clone(),
+            aggregates: self.aggregates.clone(),
+        }
+    }
+
+    /// Converts the result into a batch with one row per point, in the order of the point dictionary.
+    pub fn to_record_batch(&self) -> RecordBatch {
+        let size = self.size();
+        let mut columns: Vec<ArrayRef> = Vec::with_capacity(self.point_dictionary.len() as usize + self.aggregates.len());
+        for index in 0..self.point_names.len() {
+            let dictionary = self.dictionaries[index];
+            let array: StringArray = (0..size)
+                .map(|row| dictionary.read(&self.point_dictionary.read(&(row as u32)).unwrap()[index]))
+                .collect();
+            columns.push(Arc::new(array));
+        }
+        columns.extend(self.aggregates.iter().cloned());
+        RecordBatch::try_new(self.schema(), columns).unwrap()
+    }
+
     ///! Convert a series of record batches into a table
     fn create_table(&self) -> Table {
         let mut table = Table::new();
@@ -108,9 +209,8 @@ impl<'a> PointListAggregateResult<'a> {
                 cells.push(Cell::new(o));
             }
 
-            for aggregator in self.aggregators.iter() {
-                let array = aggregator.get_destination();
-                cells.push(Cell::new(array_value_to_string(array, row).unwrap()));
+            for array in self.aggregates.iter() {
+                cells.push(Cell::new(array_value_to_string(array.as_ref(), row).unwrap()));
             }
             table.add_row(cells);
         }
@@ -119,6 +219,68 @@ impl<'a> PointListAggregateResult<'a> {
     }
 }
 
+/// A result without the dictionaries of the store, it can be kept while the store changes, see
+/// [`crate::query_engine::QueryEngine::refresh`].
+#[derive(Debug, Clone)]
+pub struct DetachedResult {
+    pub(crate) point_dictionary: PointDictionary,
+    pub(crate) point_names: Vec<String>,
+    pub(crate) aggregate_fields: Vec<Field>,
+    pub(crate) aggregates: Vec<ArrayRef>,
+}
+
+impl DetachedResult {
+    /// Creates the result again with the dictionaries of the store. The dictionary positions
+    /// never change, any later version of the store the result was computed from fits.
+    pub fn attach<'a>(&self, store: &'a Store) -> PointListAggregateResult<'a> {
+        let dictionaries = self.point_names.iter().map(|name| store.get_dictionary(name)).collect();
+        PointListAggregateResult::from_columns(self.point_dictionary.clone(),
+                                               self.point_names.clone(),
+                                               dictionaries,
+                                               self.aggregate_fields.clone(),
+                                               self.aggregates.clone())
+    }
+
+    /// An estimate of the memory used by the result, in bytes.
+    pub fn memory_size(&self) -> usize {
+        let points = self.point_dictionary.size() * (self.point_dictionary.len() as usize + 2) * std::mem::size_of::<u32>();
+        points + self.aggregates.iter().map(|a| a.get_array_memory_size()).sum::<usize>()
+    }
+}
+
+/// A value produced by an aggregator.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AggregateValue {
+    UInt64(u64),
+    Float64(f64),
+}
+
+impl AggregateValue {
+    pub(crate) fn read(array: &dyn Array, row: usize) -> AggregateValue {
+        match array.data_type() {
+            DataType::UInt64 => AggregateValue::UInt64(array.as_any().downcast_ref::<UInt64Array>().unwrap().value(row)),
+            DataType::Float64 => AggregateValue::Float64(array.as_any().downcast_ref::<Float64Array>().unwrap().value(row)),
+            _ => panic!("{} not supported", array.data_type()),
+        }
+    }
+
+    pub fn as_f64(&self) -> f64 {
+        match self {
+            AggregateValue::UInt64(v) => *v as f64,
+            AggregateValue::Float64(v) => *v,
+        }
+    }
+}
+
+impl fmt::Display for AggregateValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AggregateValue::UInt64(v) => write!(f, "{}", v),
+            AggregateValue::Float64(v) => write!(f, "{}", v),
+        }
+    }
+}
+
 pub fn array_value_to_string(column: &dyn Array, row: usize) -> Result<String, ArrowError> {
     if column.is_null(row) {
         return Ok("".to_string());
@@ -168,6 +330,12 @@ macro_rules! assert_row_value {
     }};
 }
 
+impl From<&PointListAggregateResult<'_>> for RecordBatch {
+    fn from(result: &PointListAggregateResult<'_>) -> Self {
+        result.to_record_batch()
+    }
+}
+
 impl fmt::Display for PointListAggregateResult<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         // f.debug_list()
@@ -0,0 +1,190 @@
+//! The `rustchristmasdb` python module, built with `maturin build`, see pyproject.toml. maturin
+//! builds the library as a cdylib, the crate itself is only an rlib.
+//!
+//! ```python
+//! import pandas as pd
+//! from rustchristmasdb import Store
+//!
+//! store = Store.from_table(pd.read_csv("base.csv"), "id")
+//! store.load("s1", pd.DataFrame({"id": [1], "price": [6.0]}))
+//! table = store.query("SELECT scenario, category, sum(price) FROM store GROUP BY scenario, category")
+//! ```
+//!
+//! The record batches cross the language boundary through the Arrow C data interface, without
+//! copying their buffers. The store then copies the values it keeps when it loads the batches one
+//! at a time, cast to its types, see [`Store::prepare_batch`] and [`Store::append`].
+
+use std::sync::Arc;
+
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::pyarrow::PyArrowConvert;
+use arrow::record_batch::RecordBatch;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList, PyType};
+
+use crate::datastore::{CHUNK_DEFAULT_SIZE, MAIN_SCENARIO_NAME, SCENARIO_FIELD_NAME, Store};
+use crate::query::Query;
+use crate::query_engine::QueryEngine;
+use crate::sql;
+
+#[pymodule]
+fn rustchristmasdb(_py: Python, module: &PyModule) -> PyResult<()> {
+    module.add_class::<PyStore>()?;
+    Ok(())
+}
+
+/// A store, see [`Store`]. The data is a `pyarrow.Table`, a `pyarrow.RecordBatch` or a
+/// `pandas.DataFrame`, the query results are `pyarrow.Table`s. The store is not thread safe, it
+/// can only be used by the python thread which created it.
+#[pyclass(name = "Store", module = "rustchristmasdb", unsendable)]
+pub struct PyStore {
+    store: Store,
+}
+
+#[pymethods]
+impl PyStore {
+    /// Creates an empty store. `schema` is a `pyarrow.Schema`, `key_field` must be an int64 field.
+    #[new]
+    fn new(schema: &PyAny, key_field: &str) -> PyResult<PyStore> {
+        let schema = Schema::from_pyarrow(schema)?;
+        PyStore::create(&schema, key_field)
+    }
+
+    /// Creates a store with the schema of `data` and loads it as the base scenario.
+    #[staticmethod]
+    fn from_table(py: Python, data: &PyAny, key_field: &str) -> PyResult<PyStore> {
+        let (schema, batches) = to_batches(py, data)?;
+        let mut store = PyStore::create(&schema, key_field)?;
+        store.load_batches(MAIN_SCENARIO_NAME, &schema, &batches)?;
+        Ok(store)
+    }
+
+    /// Loads a scenario and returns its number of rows. A scenario other than the base one can be
+    /// restricted to the key and the fields it modifies, loading it again replaces its values.
+    fn load(&mut self, scenario: &str, data: &PyAny) -> PyResult<usize> {
+        let (schema, batches) = to_batches(data.py(), data)?;
+        self.load_batches(scenario, &schema, &batches)
+    }
+
+    /// Executes a query: a json query, as a string or a dict, or a sql query.
+    fn query(&self, py: Python, query: &PyAny) -> PyResult<PyObject> {
+        let query: Query = match query.extract::<&str>() {
+            Ok(text) if !text.trim_start().starts_with('{') => {
+                sql::parse_query(text, &self.store).map_err(|e| PyValueError::new_err(e.to_string()))?
+            }
+            Ok(text) => parse_json(text)?,
+            Err(_) => {
+                let text: &str = py.import("json")?.call_method1("dumps", (query,))?.extract()?;
+                parse_json(text)?
+            }
+        };
+        if let Err(errors) = query.validate(&self.store) {
+            let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+            return Err(PyValueError::new_err(errors.join(", ")));
+        }
+
+        let batch = QueryEngine::new(&self.store).execute(&query).to_record_batch();
+        let batches = PyList::new(py, vec![batch.to_pyarrow(py)?]);
+        let table = py.import("pyarrow")?.getattr("Table")?.call_method1("from_batches", (batches,))?;
+        Ok(table.into())
+    }
+
+    /// The loaded scenarios, in loading order.
+    #[getter]
+    fn scenarios(&self) -> Vec<String> {
+        let dictionary = self.store.get_dictionary(SCENARIO_FIELD_NAME);
+        (0..dictionary.size() as u32)
+            .filter_map(|position| dictionary.read(&position).cloned())
+            .collect()
+    }
+
+    /// The `pyarrow.Schema` of the store.
+    #[getter]
+    fn schema(&self, py: Python) -> PyResult<PyObject> {
+        self.store.schema().to_pyarrow(py)
+    }
+}
+
+impl PyStore {
+    /// Creates a store with the types of the store closest to the ones of the schema, e.g.
+    /// int32 becomes int64 and the strings become utf8. The values are cast when loaded.
+    pub fn create(schema: &Schema, key_field: &str) -> PyResult<PyStore> {
+        let key_index = schema.index_of(key_field)
+            .map_err(|_| PyValueError::new_err(format!("cannot find key field '{}'", key_field)))?;
+        let fields = schema.fields().iter()
+            .map(|field| match store_type(field.data_type()) {
+                Some(data_type) => Ok(Field::new(field.name(), data_type, false)),
+                None => Err(PyValueError::new_err(format!("field '{}' has the unsupported type {}", field.name(), field.data_type()))),
+            })
+            .collect::<PyResult<Vec<Field>>>()?;
+        if *fields[key_index].data_type() != DataType::Int64 {
+            return Err(PyValueError::new_err(format!("key field '{}' must be of type {}", key_field, DataType::Int64)));
+        }
+        let store = Store::new(Arc::new(Schema::new(fields)), vec![key_index as u32], CHUNK_DEFAULT_SIZE as u32);
+        Ok(PyStore { store })
+    }
+
+    /// Loads the batches of a scenario one at a time and returns their number of rows. All the
+    /// batches are checked before the first one is loaded. The base scenario can only be loaded
+    /// once, loading it again would add its rows a second time.
+    pub fn load_batches(&mut self, scenario: &str, schema: &SchemaRef, batches: &[RecordBatch]) -> PyResult<usize> {
+        if scenario == MAIN_SCENARIO_NAME && self.store.has_scenario(scenario) {
+            return Err(PyValueError::new_err(format!("the {} scenario is already loaded", scenario)));
+        }
+        let empty = [RecordBatch::new_empty(Arc::clone(schema))];
+        let batches = if batches.is_empty() { &empty[..] } else { batches };
+        let batches = batches.iter()
+            .map(|batch| self.store.prepare_batch(scenario, batch).map_err(|e| PyValueError::new_err(e.to_string())))
+            .collect::<PyResult<Vec<RecordBatch>>>()?;
+        self.store.load(scenario, &batches[0]);
+        for batch in batches[1..].iter() {
+            self.store.append(scenario, batch);
+        }
+        Ok(batches.iter().map(|batch| batch.num_rows()).sum())
+    }
+
+    /// The store, to use it from Rust.
+    pub fn store(&self) -> &Store {
+        &self.store
+    }
+}
+
+/// The type the store uses for values of the given type, if any.
+fn store_type(data_type: &DataType) -> Option<DataType> {
+    match data_type {
+        DataType::Int8 | DataType::Int16 | DataType::Int32 | DataType::Int64 => Some(DataType::Int64),
+        DataType::UInt8 | DataType::UInt16 | DataType::UInt32 => Some(DataType::UInt32),
+        DataType::UInt64 => Some(DataType::UInt64),
+        DataType::Float32 | DataType::Float64 => Some(DataType::Float64),
+        DataType::Utf8 | DataType::LargeUtf8 => Some(DataType::Utf8),
+        DataType::Dictionary(_, value_type) if matches!(value_type.as_ref(), DataType::Utf8 | DataType::LargeUtf8) => Some(DataType::Utf8),
+        _ => None,
+    }
+}
+
+/// Converts a `pyarrow.Table`, a `pyarrow.RecordBatch` or a `pandas.DataFrame` into its schema
+/// and its record batches. The index of a data frame is dropped.
+pub fn to_batches(py: Python, data: &PyAny) -> PyResult<(SchemaRef, Vec<RecordBatch>)> {
+    let pyarrow = py.import("pyarrow")?;
+    if pyarrow.getattr("RecordBatch")?.downcast::<PyType>()?.is_instance(data)? {
+        let batch = RecordBatch::from_pyarrow(data)?;
+        return Ok((batch.schema(), vec![batch]));
+    }
+    let table = if pyarrow.getattr("Table")?.downcast::<PyType>()?.is_instance(data)? {
+        data
+    } else {
+        let kwargs = PyDict::new(py);
+        kwargs.set_item("preserve_index", false)?;
+        pyarrow.getattr("Table")?.call_method("from_pandas", (data,), Some(kwargs))?
+    };
+    let schema = Arc::new(Schema::from_pyarrow(table.getattr("schema")?)?);
+    let batches = table.call_method0("to_batches")?.iter()?
+        .map(|batch| RecordBatch::from_pyarrow(batch?))
+        .collect::<PyResult<Vec<RecordBatch>>>()?;
+    Ok((schema, batches))
+}
+
+fn parse_json(text: &str) -> PyResult<Query> {
+    serde_json::from_str(text).map_err(|e| PyValueError::new_err(format!("cannot parse the query: {}", e)))
+}
@@ -1,45 +1,268 @@
+use std::collections::HashSet;
+use std::fmt;
 
-
+use arrow::datatypes::DataType;
 use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+use crate::datastore::{MAIN_SCENARIO_NAME, SCENARIO_FIELD_NAME, Store, StoreChange};
+use crate::having::Having;
+use crate::order_by::{OrderBy, TopN};
+
+/// The aggregation functions understood by the query engine.
+pub const SUPPORTED_AGGREGATION_FUNCTIONS: [&str; 1] = ["sum"];
 
-pub struct Query<'a> {
+/// A query on a [`Store`]. It can be built with the methods below or deserialized from json:
+///
+/// ```json
+/// {
+///   "coordinates": { "scenario": null, "category": ["milk", "condiment"] },
+///   "measures": [{ "field": "price", "aggregation_function": "sum" }],
+///   "having": [{ "alias": "sum(price)", "comparison": "gt", "value": 4.0, "delta": false }],
+///   "order_by": [{ "field": "sum(price)", "descending": true, "nulls_first": true }],
+///   "top_n": { "group_by": ["scenario"], "n": 3 },
+///   "limit": 10,
+///   "offset": 0
+/// }
+/// ```
+///
+/// A coordinate set to `null` is a wildcard, a list of values is a filter. The order of the
+/// coordinates is the order of the columns of the result. Only `coordinates` and `measures` are
+/// required. `comparison` is one of `eq`, `not_eq`, `lt`, `lt_eq`, `gt` and `gt_eq`, `delta`,
+/// `descending` and `nulls_first` default to false.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Query {
     pub coordinates: IndexMap<String, Option<Vec<String>>>, // Use IndexMap to preserve the order.
-    pub measures: Vec<AggregatedMeasure<'a>>,
+    pub measures: Vec<AggregatedMeasure>,
+    #[serde(default)]
+    pub having: Vec<Having>,
+    #[serde(default)]
+    pub order_by: Vec<OrderBy>,
+    #[serde(default)]
+    pub top_n: Option<TopN>,
+    #[serde(default)]
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub offset: usize,
 }
 
-impl<'a> Query<'a> {
-    pub fn new() -> Query<'a> {
-        Query { coordinates: IndexMap::new(), measures: Vec::new() }
+impl Query {
+    pub fn new() -> Query {
+        Query {
+            coordinates: IndexMap::new(),
+            measures: Vec::new(),
+            having: Vec::new(),
+            order_by: Vec::new(),
+            top_n: None,
+            limit: None,
+            offset: 0,
+        }
     }
 
-    pub fn add_wildcard_coordinate(&mut self, field: &str) -> &mut Query<'a> {
+    pub fn add_wildcard_coordinate(&mut self, field: &str) -> &mut Query {
         self.coordinates.insert(field.to_string(), None);
         self
     }
 
-    pub fn add_coordinates(&mut self, field: &str, coordinates: Vec<&str>) -> &mut Query<'a> {
+    pub fn add_coordinates(&mut self, field: &str, coordinates: Vec<&str>) -> &mut Query {
         let v: Vec<String> = coordinates.iter().map(|c| c.to_string()).collect();
         self.coordinates.insert(field.to_string(), Some(v));
         self
     }
 
-    pub fn add_aggregated_measure(&mut self, field: &'a str, agg: &'a str) -> &mut Query<'a> {
+    pub fn add_aggregated_measure(&mut self, field: &str, agg: &str) -> &mut Query {
         self.measures.push(AggregatedMeasure::new(field, agg));
         self
     }
+
+    /// Filters the points on their aggregated values. All the predicates must be satisfied.
+    pub fn add_having(&mut self, having: Having) -> &mut Query {
+        self.having.push(having);
+        self
+    }
+
+    /// Sorts the points on a coordinate or on the alias of a measure e.g. `sum(price)`. Can be
+    /// called several times, the first call gives the primary order.
+    pub fn add_order_by(&mut self, order_by: OrderBy) -> &mut Query {
+        self.order_by.push(order_by);
+        self
+    }
+
+    /// Keeps the `n` first points, according to the order of the query, of each group of points
+    /// sharing the same `group_by` coordinates.
+    pub fn set_top_n_per_group(&mut self, group_by: Vec<&str>, n: usize) -> &mut Query {
+        self.top_n = Some(TopN { group_by: group_by.iter().map(|f| f.to_string()).collect(), n });
+        self
+    }
+
+    pub fn set_limit(&mut self, limit: usize) -> &mut Query {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn set_offset(&mut self, offset: usize) -> &mut Query {
+        self.offset = offset;
+        self
+    }
+
+    /// Checks the query against the schema of the store. The query engine panics on the queries
+    /// that do not pass this validation. Filter values missing from the store are not errors,
+    /// they match no row.
+    pub fn validate(&self, store: &Store) -> Result<(), Vec<ValidationError>> {
+        let mut errors = Vec::new();
+        let schema = store.schema();
+
+        for field in self.coordinates.keys() {
+            if field == SCENARIO_FIELD_NAME {
+                continue;
+            }
+            match schema.field_with_name(field).map(|f| f.data_type()) {
+                Ok(DataType::Utf8) => {}
+                Ok(data_type) => errors.push(ValidationError::new(
+                    format!("coordinates.{}", field),
+                    format!("{} fields cannot be coordinates, only Utf8 fields can", data_type))),
+                Err(_) => errors.push(ValidationError::new(format!("coordinates.{}", field), "unknown field".to_string())),
+            }
+        }
+
+        let aliases: Vec<String> = self.measures.iter().map(|m| m.alias()).collect();
+        for (index, measure) in self.measures.iter().enumerate() {
+            match schema.field_with_name(&measure.field).map(|f| f.data_type()) {
+                Ok(DataType::UInt32) | Ok(DataType::Float64) => {}
+                Ok(data_type) => errors.push(ValidationError::new(
+                    format!("measures[{}].field", index),
+                    format!("'{}' cannot be aggregated, {} is not supported", measure.field, data_type))),
+                Err(_) => errors.push(ValidationError::new(
+                    format!("measures[{}].field", index),
+                    format!("unknown field '{}'", measure.field))),
+            }
+            if !SUPPORTED_AGGREGATION_FUNCTIONS.contains(&measure.aggregation_function.as_str()) {
+                errors.push(ValidationError::new(
+                    format!("measures[{}].aggregation_function", index),
+                    format!("unknown function '{}', expected one of {:?}", measure.aggregation_function, SUPPORTED_AGGREGATION_FUNCTIONS)));
+            }
+            if aliases[..index].contains(&aliases[index]) {
+                errors.push(ValidationError::new(
+                    format!("measures[{}]", index),
+                    format!("'{}' is already a measure of the query", aliases[index])));
+            }
+        }
+
+        let base_queried = match self.coordinates.get(SCENARIO_FIELD_NAME) {
+            None => false,
+            Some(None) => true,
+            Some(Some(scenarios)) => scenarios.iter().any(|s| s == MAIN_SCENARIO_NAME),
+        };
+        for (index, having) in self.having.iter().enumerate() {
+            if !aliases.contains(&having.alias) {
+                errors.push(ValidationError::new(
+                    format!("having[{}].alias", index),
+                    format!("'{}' is not a measure of the query", having.alias)));
+            }
+            if having.delta && !base_queried {
+                errors.push(ValidationError::new(
+                    format!("having[{}].delta", index),
+                    "a delta requires the scenario coordinate and the base scenario in the query".to_string()));
+            }
+        }
+
+        for (index, order_by) in self.order_by.iter().enumerate() {
+            if !self.coordinates.contains_key(&order_by.field) && !aliases.contains(&order_by.field) {
+                errors.push(ValidationError::new(
+                    format!("order_by[{}].field", index),
+                    format!("'{}' is neither a coordinate nor a measure of the query", order_by.field)));
+            }
+        }
+
+        if let Some(top_n) = &self.top_n {
+            for (index, field) in top_n.group_by.iter().enumerate() {
+                if !self.coordinates.contains_key(field) {
+                    errors.push(ValidationError::new(
+                        format!("top_n.group_by[{}]", index),
+                        format!("'{}' is not a coordinate of the query", field)));
+                }
+            }
+        }
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+
+    /// The scenarios and the fields the result of the query is computed from.
+    pub fn dependencies(&self) -> QueryDependencies {
+        let scenarios = match self.coordinates.get(SCENARIO_FIELD_NAME) {
+            None => Some(HashSet::from([MAIN_SCENARIO_NAME.to_string()])),
+            Some(None) => None,
+            Some(Some(scenarios)) => Some(scenarios.iter().cloned().collect()),
+        };
+        let fields = self.coordinates.keys()
+            .filter(|field| *field != SCENARIO_FIELD_NAME)
+            .chain(self.measures.iter().map(|m| &m.field))
+            .cloned()
+            .collect();
+        QueryDependencies { scenarios, fields }
+    }
+}
+
+/// The scenarios and the fields a query reads, see [`Query::dependencies`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryDependencies {
+    /// `None` when the query reads all the scenarios.
+    pub scenarios: Option<HashSet<String>>,
+    pub fields: HashSet<String>,
 }
 
-pub struct AggregatedMeasure<'a> {
-    pub field: &'a str,
-    pub aggregation_function: &'a str,
+impl QueryDependencies {
+    /// Whether the change may modify the result of the query. The scenarios read the values of the
+    /// base scenario they do not modify, a change of the base scenario modifies every result.
+    pub fn is_affected_by(&self, change: &StoreChange) -> bool {
+        if change.scenario == MAIN_SCENARIO_NAME {
+            return true;
+        }
+        let read = self.scenarios.as_ref().is_none_or(|scenarios| scenarios.contains(&change.scenario));
+        read && (change.listing_changed || !change.fields.is_disjoint(&self.fields))
+    }
 }
 
-impl<'a> AggregatedMeasure<'a> {
-    pub fn new(field: &'a str, aggregation_function: &'a str) -> Self {
-        AggregatedMeasure { field, aggregation_function }
+impl Default for Query {
+    fn default() -> Self {
+        Query::new()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AggregatedMeasure {
+    pub field: String,
+    pub aggregation_function: String,
+}
+
+impl AggregatedMeasure {
+    pub fn new(field: &str, aggregation_function: &str) -> Self {
+        AggregatedMeasure { field: field.to_string(), aggregation_function: aggregation_function.to_string() }
     }
 
     pub fn alias(&self) -> String {
         format!("{}({})", self.aggregation_function, self.field)
     }
-}
\ No newline at end of file
+}
+
+/// An error of [`Query::validate`]. `field` is the path of the faulty value in the json shape of
+/// the query, e.g. `measures[1].field`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationError {
+    pub field: String,
+    pub message: String,
+}
+
+impl ValidationError {
+    pub(crate) fn new(field: String, message: String) -> ValidationError {
+        ValidationError { field, message }
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+impl std::error::Error for ValidationError {}
@@ -0,0 +1,226 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::Deserialize;
+
+use crate::datastore::{MAIN_SCENARIO_NAME, SCENARIO_FIELD_NAME, Store};
+use crate::point_list_aggregates_result::{DetachedResult, PointListAggregateResult};
+use crate::query::{Query, QueryDependencies};
+use crate::query_engine::QueryEngine;
+
+pub const DEFAULT_MAX_ENTRIES: usize = 256;
+pub const DEFAULT_MAX_BYTES: usize = 64 * 1024 * 1024;
+
+/// The hit and miss counts of a [`QueryCache`] and what it holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// The results removed to respect the size limits.
+    pub evictions: u64,
+    /// The results removed because the store changed.
+    pub invalidations: u64,
+    /// The results patched with the new values of the scenarios loaded again.
+    pub refreshes: u64,
+    pub entries: usize,
+    pub bytes: usize,
+}
+
+/// The size limits of a [`QueryCache`], e.g. `{ "max_entries": 100, "max_bytes": 1048576 }`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct QueryCacheConfig {
+    #[serde(default = "default_max_entries")]
+    pub max_entries: usize,
+    #[serde(default = "default_max_bytes")]
+    pub max_bytes: usize,
+}
+
+fn default_max_entries() -> usize {
+    DEFAULT_MAX_ENTRIES
+}
+
+fn default_max_bytes() -> usize {
+    DEFAULT_MAX_BYTES
+}
+
+impl Default for QueryCacheConfig {
+    fn default() -> Self {
+        QueryCacheConfig { max_entries: DEFAULT_MAX_ENTRIES, max_bytes: DEFAULT_MAX_BYTES }
+    }
+}
+
+struct CacheEntry {
+    query: Query,
+    dependencies: QueryDependencies,
+    result: DetachedResult,
+    /// The scenarios loaded again since the result was computed, see [`QueryEngine::refresh`].
+    stale_scenarios: Vec<String>,
+    bytes: usize,
+    last_use: u64,
+}
+
+/// A least recently used cache in front of [`QueryEngine::execute`]. The results are keyed by
+/// the normalized query, they are valid for the [`Store::version`] of the cache. When the store
+/// changes, the results the changes may modify, see [`QueryDependencies`], are dropped, except the
+/// ones only depending on scenarios loaded again: they are refreshed when used. The cache keeps at
+/// most `max_entries` results and `max_bytes` of aggregates, a result larger than `max_bytes` is
+/// not cached.
+pub struct QueryCache {
+    max_entries: usize,
+    max_bytes: usize,
+    entries: HashMap<String, CacheEntry>,
+    /// The keys of the entries by last use, the least recently used first.
+    keys_by_use: BTreeMap<u64, String>,
+    uses: u64,
+    version: u64,
+    stats: CacheStats,
+}
+
+impl QueryCache {
+    pub fn new() -> QueryCache {
+        QueryCache::with_config(&QueryCacheConfig::default())
+    }
+
+    pub fn with_config(config: &QueryCacheConfig) -> QueryCache {
+        QueryCache {
+            max_entries: config.max_entries,
+            max_bytes: config.max_bytes,
+            entries: HashMap::new(),
+            keys_by_use: BTreeMap::new(),
+            uses: 0,
+            version: 0,
+            stats: CacheStats::default(),
+        }
+    }
+
+    pub fn set_max_entries(&mut self, max_entries: usize) -> &mut QueryCache {
+        self.max_entries = max_entries;
+        self.evict();
+        self
+    }
+
+    pub fn set_max_bytes(&mut self, max_bytes: usize) -> &mut QueryCache {
+        self.max_bytes = max_bytes;
+        self.evict();
+        self
+    }
+
+    /// Returns the cached result of the query, executes it on a miss. The query must be valid,
+    /// see [`Query::validate`].
+    pub fn execute<'a>(&mut self, store: &'a Store, query: &Query) -> PointListAggregateResult<'a> {
+        self.apply_changes(store);
+
+        let key = normalize(query);
+        self.uses += 1;
+        if let Some(entry) = self.entries.get_mut(&key) {
+            self.keys_by_use.remove(&entry.last_use);
+            entry.last_use = self.uses;
+            self.keys_by_use.insert(self.uses, key);
+            self.stats.hits += 1;
+            if entry.stale_scenarios.is_empty() {
+                return entry.result.attach(store);
+            }
+            let result = QueryEngine::new(store).refresh(&entry.result, &entry.query, &entry.stale_scenarios);
+            entry.result = result.detach();
+            entry.stale_scenarios.clear();
+            self.stats.refreshes += 1;
+            let bytes = entry.result.memory_size();
+            self.stats.bytes = self.stats.bytes + bytes - entry.bytes;
+            entry.bytes = bytes;
+            self.evict();
+            return result;
+        }
+
+        self.stats.misses += 1;
+        let result = QueryEngine::new(store).execute(query);
+        let detached = result.detach();
+        let bytes = detached.memory_size();
+        if self.max_entries > 0 && bytes <= self.max_bytes {
+            self.keys_by_use.insert(self.uses, key.clone());
+            self.entries.insert(key, CacheEntry {
+                query: query.clone(),
+                dependencies: query.dependencies(),
+                result: detached,
+                stale_scenarios: Vec::new(),
+                bytes,
+                last_use: self.uses,
+            });
+            self.stats.bytes += bytes;
+            self.evict();
+        }
+        result
+    }
+
+    /// Drops the results modified by the changes of the store since the version of the cache,
+    /// or marks them for a refresh when they only depend on scenarios loaded again.
+    fn apply_changes(&mut self, store: &Store) {
+        if store.version() == self.version {
+            return;
+        }
+        // Another store, or the changes of a store the cache has not seen.
+        let changes = if store.version() > self.version { store.changes_since(self.version) } else { &[] };
+        let known = changes.first().is_some_and(|change| change.version == self.version + 1);
+        let mut dropped = Vec::new();
+        for (key, entry) in self.entries.iter_mut() {
+            let mut refreshable = known;
+            for change in changes.iter().filter(|change| entry.dependencies.is_affected_by(change)) {
+                refreshable &= change.scenario != MAIN_SCENARIO_NAME && !change.listing_changed;
+                if !entry.stale_scenarios.contains(&change.scenario) {
+                    entry.stale_scenarios.push(change.scenario.clone());
+                }
+            }
+            if !refreshable {
+                dropped.push(key.clone());
+            }
+        }
+        for key in dropped {
+            let entry = self.entries.remove(&key).unwrap();
+            self.keys_by_use.remove(&entry.last_use);
+            self.stats.bytes -= entry.bytes;
+            self.stats.invalidations += 1;
+        }
+        self.version = store.version();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats { entries: self.entries.len(), ..self.stats }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.keys_by_use.clear();
+        self.stats.bytes = 0;
+    }
+
+    /// Removes the least recently used results until the cache fits its limits.
+    fn evict(&mut self) {
+        while self.entries.len() > self.max_entries || self.stats.bytes > self.max_bytes {
+            let (_, key) = match self.keys_by_use.pop_first() {
+                Some(oldest) => oldest,
+                None => return,
+            };
+            if let Some(entry) = self.entries.remove(&key) {
+                self.stats.bytes -= entry.bytes;
+                self.stats.evictions += 1;
+            }
+        }
+    }
+}
+
+impl Default for QueryCache {
+    fn default() -> Self {
+        QueryCache::new()
+    }
+}
+
+/// The json of the query with the values of its filters sorted: two queries filtering the same
+/// values have the same key. The order of the scenarios is kept, it is the order of the points.
+fn normalize(query: &Query) -> String {
+    let mut query = query.clone();
+    for (field, values) in query.coordinates.iter_mut() {
+        if let (false, Some(values)) = (field == SCENARIO_FIELD_NAME, values) {
+            values.sort();
+            values.dedup();
+        }
+    }
+    serde_json::to_string(&query).unwrap()
+}
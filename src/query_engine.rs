@@ -1,15 +1,27 @@
 use std::collections::{HashMap, HashSet};
 
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use arrow::datatypes::UInt32Type;
+use arrow::array::{ArrayRef, UInt32Array};
+use arrow::compute::{concat, take};
+use arrow::datatypes::{Field, UInt32Type};
+use crate::aggregate_table::AggregateTable;
 use crate::aggregator::{Aggregator, AggregatorFactory};
+use crate::chunk_array::ChunkArrayReader;
 use crate::datastore::{MAIN_SCENARIO_NAME, SCENARIO_FIELD_NAME, Store};
+use crate::dictionary_provider::Dictionary;
+use crate::having;
+use crate::order_by::{RowComparator, top_n};
 use crate::point_dictionary::PointDictionary;
-use crate::point_list_aggregates_result::PointListAggregateResult;
+use crate::point_list_aggregates_result::{DetachedResult, PointListAggregateResult};
 use crate::query::Query;
+use crate::query_plan::{QueryPlan, RowProvider, ScenarioPlan};
 use crate::row_iterable_provider::RowIterableProviderFactory;
 
+/// The number of rows whose points are computed and whose values are aggregated at once.
+const AGGREGATION_BATCH_SIZE: usize = 1024;
+
 pub struct QueryEngine<'a> {
     store: &'a Store,
 }
@@ -19,16 +31,127 @@ impl<'a> QueryEngine<'a> {
         QueryEngine { store }
     }
 
-    pub fn execute(&self, query: &'a Query) -> PointListAggregateResult {
+    /// Executes the query, rolls it up from an aggregate table of the store when one has its
+    /// coordinates and its measures, see [`Store::find_aggregate_table`].
+    pub fn execute(&self, query: &Query) -> PointListAggregateResult<'a> {
+        self.analyze(query).0
+    }
+
+    /// Executes the query and returns how it was executed, with the actual point count and
+    /// timings.
+    pub fn analyze(&self, query: &Query) -> (PointListAggregateResult<'a>, QueryPlan) {
+        match self.store.find_aggregate_table(query) {
+            Some(table) => self.roll_up(table, query),
+            None => self.scan_with_plan(query),
+        }
+    }
+
+    /// Returns how the query would be executed: the rows are filtered but neither grouped nor
+    /// aggregated.
+    pub fn explain(&self, query: &Query) -> QueryPlan {
+        let start = Instant::now();
+        let queried_scenarios = self.compute_queried_scenarios(query);
+        let accepted_values_by_field = self.compute_accepted_values(query);
+        let dictionary = self.store.get_dictionary(SCENARIO_FIELD_NAME);
+        let scenarios: Vec<&str> = queried_scenarios.iter().map(|position| dictionary.read(position).unwrap().as_str()).collect();
+        let (row_provider, candidate_rows): (RowProvider, Vec<u64>) = match self.store.find_aggregate_table(query) {
+            Some(table) => (
+                RowProvider::AggregateTable(table.definition().name.clone()),
+                scenarios.iter().map(|scenario| table.scenario_size(scenario, &accepted_values_by_field) as u64).collect(),
+            ),
+            None => {
+                let row_provider = if accepted_values_by_field.is_empty() { RowProvider::Range } else { RowProvider::Bitmap };
+                let provider = RowIterableProviderFactory::create(self.store, accepted_values_by_field.clone());
+                (row_provider, scenarios.iter().map(|scenario| provider.get(scenario).len()).collect())
+            }
+        };
+
+        // At most one point per candidate row and per combination of the queried values.
+        let combinations = query.coordinates.iter()
+            .map(|(field, values)| match (field.as_str(), values) {
+                (SCENARIO_FIELD_NAME, _) => queried_scenarios.len() as u64,
+                (_, Some(_)) => accepted_values_by_field.get(field).map_or(0, |values| values.len() as u64),
+                (_, None) => self.store.get_dictionary(field).size() as u64,
+            })
+            .fold(1u64, |product, size| product.saturating_mul(size));
+        QueryPlan {
+            row_provider,
+            point_count: combinations.min(candidate_rows.iter().sum()),
+            scenarios: self.scenario_plans(query, &scenarios, candidate_rows),
+            filtering: start.elapsed(),
+            grouping: None,
+            aggregating: None,
+        }
+    }
+
+    /// The fields each scenario modifies among the fields of the query, and its candidate rows.
+    fn scenario_plans(&self, query: &Query, scenarios: &[&str], candidate_rows: Vec<u64>) -> Vec<ScenarioPlan> {
+        let mut fields: Vec<String> = query.dependencies().fields.into_iter().collect();
+        fields.sort();
+        scenarios.iter().zip(candidate_rows).map(|(scenario, candidate_rows)| {
+            let vectors = self.store.vector_by_field_by_scenario.get(*scenario).filter(|_| *scenario != MAIN_SCENARIO_NAME);
+            ScenarioPlan {
+                scenario: scenario.to_string(),
+                simulated_fields: fields.iter().filter(|field| vectors.is_some_and(|v| v.contains_key(*field))).cloned().collect(),
+                candidate_rows,
+            }
+        }).collect()
+    }
+
+    fn roll_up(&self, table: &AggregateTable, query: &Query) -> (PointListAggregateResult<'a>, QueryPlan) {
+        let start = Instant::now();
+        let queried_scenarios = self.compute_queried_scenarios(query);
+        if queried_scenarios.is_empty() {
+            // Without scenario the result has no aggregate column, as the scan gives it.
+            return self.scan_with_plan(query);
+        }
+        let accepted_values_by_field = self.compute_accepted_values(query);
+        let dictionary = self.store.get_dictionary(SCENARIO_FIELD_NAME);
+        let scenarios: Vec<(u32, &str)> = queried_scenarios.iter()
+            .map(|position| (*position, dictionary.read(position).unwrap().as_str()))
+            .collect();
+        let point_names: Vec<String> = query.coordinates.keys().map(|k| k.to_string()).collect();
+        let cardinalities: Vec<u32> = point_names.iter().map(|name| self.store.get_dictionary(name).size() as u32).collect();
+        let (point_dictionary, aggregate_fields, aggregates) =
+            table.roll_up(query, &accepted_values_by_field, &scenarios, &point_names, &cardinalities);
+
+        let names: Vec<&str> = scenarios.iter().map(|(_, scenario)| *scenario).collect();
+        let candidate_rows = names.iter().map(|scenario| table.scenario_size(scenario, &accepted_values_by_field) as u64).collect();
+        let plan = QueryPlan {
+            row_provider: RowProvider::AggregateTable(table.definition().name.clone()),
+            scenarios: self.scenario_plans(query, &names, candidate_rows),
+            point_count: point_dictionary.size() as u64,
+            filtering: Duration::ZERO,
+            grouping: Some(Duration::ZERO),
+            aggregating: Some(start.elapsed()),
+        };
+        (self.select(query, &queried_scenarios, &cardinalities, point_dictionary, point_names, aggregate_fields, aggregates), plan)
+    }
+
+    /// Executes the query on the rows of the store, without the aggregate tables.
+    pub(crate) fn scan(&self, query: &Query) -> PointListAggregateResult<'a> {
+        self.scan_with_plan(query).0
+    }
+
+    /// Executes the query on the rows of the store. The filtering time is the time spent finding
+    /// and iterating the rows, the grouping time the one spent mapping their points.
+    fn scan_with_plan(&self, query: &Query) -> (PointListAggregateResult<'a>, QueryPlan) {
+        let start = Instant::now();
         let accepted_values_by_field = self.compute_accepted_values(query);
+        let row_provider = if accepted_values_by_field.is_empty() { RowProvider::Range } else { RowProvider::Bitmap };
         let queried_scenarios = self.compute_queried_scenarios(query);
         let mut aggregators_by_scenario = self.compute_aggregators(query, queried_scenarios.clone());
 
         let point_size = query.coordinates.len();
-        let mut point_dictionary = PointDictionary::new(point_size as u32);
         let point_names: Vec<String> = query.coordinates.keys().map(|k| k.to_string()).collect();
+        let cardinalities: Vec<u32> = point_names.iter().map(|name| self.store.get_dictionary(name).size() as u32).collect();
+        let mut point_dictionary = PointDictionary::with_cardinalities(&cardinalities);
         let scenario_index = point_names.iter().position(|r| *r == SCENARIO_FIELD_NAME).unwrap_or(usize::MAX);
         let provider = RowIterableProviderFactory::create(self.store, accepted_values_by_field);
+        let mut scenarios = Vec::with_capacity(queried_scenarios.len());
+        let mut candidate_rows = Vec::with_capacity(queried_scenarios.len());
+        let mut grouping = Duration::ZERO;
+        let mut aggregating = Duration::ZERO;
         for i in queried_scenarios.iter() {
             let dictionary = self.store.get_dictionary(SCENARIO_FIELD_NAME);
             let scenario = dictionary.read(&i).unwrap();
@@ -43,35 +166,167 @@ impl<'a> QueryEngine<'a> {
             }
             let aggregators = aggregators_by_scenario.get_mut(scenario).unwrap();
 
-            provider.get(scenario.as_str()).for_each(|row| {
-                let mut point: Vec<u32> = Vec::with_capacity(point_size);
-                point.resize(point_size, 0);
-                for point_index in 0..point_size {
-                    if point_index != scenario_index {
-                        point[point_index] = columns[point_index].as_ref().unwrap().read::<UInt32Type>(row);
-                    } else {
-                        point[point_index] = *i;
-                    }
-                }
-
-                let destination_row = point_dictionary.map(point.as_slice());
-                // And then aggregate
-                for aggregator in aggregators.into_iter() {
-                    aggregator.ensure_capacity(*destination_row as usize);
-                    aggregator.as_mut().aggregate(row, *destination_row);
+            let mut batch = RowBatch::new(point_size);
+            let rows = provider.get(scenario.as_str());
+            scenarios.push(scenario.as_str());
+            candidate_rows.push(rows.len());
+            rows.for_each(|row| {
+                batch.rows.push(row);
+                if batch.rows.len() == AGGREGATION_BATCH_SIZE {
+                    batch.aggregate(&columns, *i, &mut point_dictionary, aggregators);
                 }
             });
+            batch.aggregate(&columns, *i, &mut point_dictionary, aggregators);
+            grouping += batch.grouping;
+            aggregating += batch.aggregating;
         }
 
+        let finishing = Instant::now();
         aggregators_by_scenario.iter_mut()
             .flat_map(|(_k, v)| v.iter_mut())
             .for_each(|a| a.as_mut().finish());
+        aggregating += finishing.elapsed();
+        let plan = QueryPlan {
+            row_provider,
+            scenarios: self.scenario_plans(query, &scenarios, candidate_rows),
+            point_count: point_dictionary.size() as u64,
+            filtering: start.elapsed().saturating_sub(grouping + aggregating),
+            grouping: Some(grouping),
+            aggregating: Some(aggregating),
+        };
+
+        let (aggregate_fields, aggregates) = QueryEngine::aggregated_columns(aggregators_by_scenario, point_dictionary.size());
+        (self.select(query, &queried_scenarios, &cardinalities, point_dictionary, point_names, aggregate_fields, aggregates), plan)
+    }
+
+    /// Recomputes the points of the given scenarios in a previous result of the query, e.g. after
+    /// they were loaded again, and keeps the points of the other scenarios. The query is executed
+    /// again when the previous result cannot be patched: the query does not have the scenario
+    /// coordinate, one of the scenarios is the base one or the query selects its points with a
+    /// having, a top-n, a limit or an offset.
+    pub fn refresh(&self, previous: &DetachedResult, query: &Query, scenarios: &[String]) -> PointListAggregateResult<'a> {
+        let point_names: Vec<String> = query.coordinates.keys().map(|k| k.to_string()).collect();
+        let scenario_index = match point_names.iter().position(|r| *r == SCENARIO_FIELD_NAME) {
+            Some(index) if previous.point_names == point_names => index,
+            _ => return self.execute(query),
+        };
+        if scenarios.iter().any(|s| s == MAIN_SCENARIO_NAME)
+            || !query.having.is_empty() || query.top_n.is_some() || query.limit.is_some() || query.offset > 0 {
+            return self.execute(query);
+        }
+
+        let queried_scenarios = self.compute_queried_scenarios(query);
+        let dictionary = self.store.get_dictionary(SCENARIO_FIELD_NAME);
+        // A reloaded scenario no longer modifying any value is not queried anymore by a wildcard,
+        // its previous points are dropped all the same.
+        let reloaded: Vec<u32> = scenarios.iter().filter_map(|s| dictionary.get_position(s.as_str()).copied()).collect();
+        let refreshed: Vec<u32> = queried_scenarios.iter()
+            .copied()
+            .filter(|position| reloaded.contains(position))
+            .collect();
+        let kept: Vec<u32> = (0..previous.point_dictionary.size() as u32)
+            .filter(|row| !reloaded.contains(&previous.point_dictionary.read(row).unwrap()[scenario_index]))
+            .collect();
+        if refreshed.is_empty() && kept.len() == previous.point_dictionary.size() {
+            return previous.attach(self.store);
+        }
+        let refreshed_result = if refreshed.is_empty() {
+            None
+        } else {
+            let mut refreshed_query = query.clone();
+            refreshed_query.order_by.clear();
+            refreshed_query.coordinates.insert(SCENARIO_FIELD_NAME.to_string(), Some(
+                refreshed.iter().map(|position| dictionary.read(position).unwrap().to_string()).collect()));
+            Some(self.execute(&refreshed_query).detach())
+        };
+
+        let cardinalities: Vec<u32> = point_names.iter().map(|name| self.store.get_dictionary(name).size() as u32).collect();
+        let mut point_dictionary = PointDictionary::with_cardinalities(&cardinalities);
+        for row in kept.iter() {
+            point_dictionary.map(previous.point_dictionary.read(row).unwrap());
+        }
+        let kept = UInt32Array::from(kept);
+        let mut aggregates: Vec<ArrayRef> = previous.aggregates.iter()
+            .map(|previous| take(previous.as_ref(), &kept, None).unwrap())
+            .collect();
+        if let Some(refreshed_result) = refreshed_result {
+            for row in 0..refreshed_result.point_dictionary.size() as u32 {
+                point_dictionary.map(refreshed_result.point_dictionary.read(&row).unwrap());
+            }
+            aggregates = aggregates.iter()
+                .zip(refreshed_result.aggregates.iter())
+                .map(|(kept, refreshed)| concat(&[kept.as_ref(), refreshed.as_ref()]).unwrap())
+                .collect();
+        }
+        self.select(query, &queried_scenarios, &cardinalities, point_dictionary, point_names, previous.aggregate_fields.clone(), aggregates)
+    }
+
+    /// Creates the result from the aggregated points and applies the selection of the query.
+    #[allow(clippy::too_many_arguments)]
+    fn select(&self,
+              query: &Query,
+              queried_scenarios: &[u32],
+              cardinalities: &[u32],
+              mut point_dictionary: PointDictionary,
+              point_names: Vec<String>,
+              aggregate_fields: Vec<Field>,
+              mut aggregates: Vec<ArrayRef>) -> PointListAggregateResult<'a> {
+        let dictionaries: Vec<&Dictionary<String>> = point_names.iter().map(|name| self.store.dictionary_provider.dicos.get(name).unwrap()).collect();
+        let aggregate_names: Vec<String> = aggregate_fields.iter().map(|f| f.name().to_string()).collect();
+        let scenario_index = point_names.iter().position(|r| *r == SCENARIO_FIELD_NAME);
+        let base_scenario = self.store.get_dictionary(SCENARIO_FIELD_NAME)
+            .get_position(&MAIN_SCENARIO_NAME.to_string())
+            .filter(|position| queried_scenarios.contains(position))
+            .copied();
+        let rows = self.select_rows(query, &point_dictionary, &point_names, &dictionaries, &aggregate_names, &aggregates, scenario_index, base_scenario);
+        if let Some(rows) = rows {
+            let selected = UInt32Array::from(rows);
+            let mut selected_points = PointDictionary::with_cardinalities(cardinalities);
+            for row in selected.values() {
+                selected_points.map(point_dictionary.read(row).unwrap());
+            }
+            point_dictionary = selected_points;
+            aggregates = aggregates.iter().map(|a| take(a.as_ref(), &selected, None).unwrap()).collect();
+        }
 
-        let dictionaries = point_names.iter().map(|name| self.store.dictionary_provider.dicos.get(name).unwrap()).collect();
-        PointListAggregateResult::new(point_dictionary,
-                                      point_names,
-                                      dictionaries,
-                                      aggregators_by_scenario)
+        PointListAggregateResult::from_columns(point_dictionary,
+                                               point_names,
+                                               dictionaries,
+                                               aggregate_fields,
+                                               aggregates)
+    }
+
+    /// Applies the having, top-n, order by, offset and limit of the query. Returns the rows to keep
+    /// in their final order, `None` if all the rows are kept in the order of the point dictionary.
+    #[allow(clippy::too_many_arguments)]
+    fn select_rows(&self,
+                   query: &Query,
+                   point_dictionary: &PointDictionary,
+                   point_names: &[String],
+                   dictionaries: &[&Dictionary<String>],
+                   aggregate_names: &[String],
+                   aggregates: &[ArrayRef],
+                   scenario_index: Option<usize>,
+                   base_scenario: Option<u32>) -> Option<Vec<u32>> {
+        if query.having.is_empty() && query.order_by.is_empty() && query.top_n.is_none() && query.limit.is_none() && query.offset == 0 {
+            return None;
+        }
+
+        let mut rows: Vec<u32> = (0..point_dictionary.size() as u32).collect();
+        if !query.having.is_empty() {
+            rows = having::filter(rows, &query.having, point_dictionary, aggregate_names, aggregates, scenario_index, base_scenario);
+        }
+        let comparator = RowComparator::new(&query.order_by, point_dictionary, point_names, dictionaries, aggregate_names, aggregates);
+        if let Some(n) = &query.top_n {
+            rows = top_n(&rows, n, &comparator, point_dictionary, point_names);
+        }
+        if !query.order_by.is_empty() {
+            rows.sort_by(|l, r| comparator.compare(*l, *r));
+        }
+        Some(rows.into_iter()
+            .skip(query.offset)
+            .take(query.limit.unwrap_or(usize::MAX))
+            .collect())
     }
 
     fn compute_accepted_values(&self, query: &Query) -> HashMap<String, HashSet<u32>> {
@@ -85,11 +340,11 @@ impl<'a> QueryEngine<'a> {
                 let dictionary = self.store.dictionary_provider.dicos
                     .get(field)
                     .expect(format!("cannot find dic. for field {}", field).as_str());
+                // Unknown values are ignored but the field stays filtered: no value means no row.
+                let accepted_values = accepted_values_by_field.entry(field.to_string()).or_default();
                 for coord in coords {
                     if let Some(position) = dictionary.get_position(coord) {
-                        accepted_values_by_field.entry(field.to_string())
-                            .or_insert(HashSet::new())
-                            .insert(*position);
+                        accepted_values.insert(*position);
                     }
                 }
             }
@@ -130,10 +385,10 @@ impl<'a> QueryEngine<'a> {
             let mut aggregators: Vec<Box<dyn Aggregator>> = Vec::new();
             if index == 0 {
                 query.measures.iter().for_each(|measure| {
-                    let source = self.store.get_scenario_chunk_array(scenario, measure.field);
+                    let source = self.store.get_scenario_chunk_array(scenario, &measure.field);
                     let aggregator = factory.create(
                         Arc::new(source),
-                        measure.aggregation_function,
+                        &measure.aggregation_function,
                         measure.alias().as_str());
                     aggregators.push(aggregator);
                 });
@@ -142,11 +397,11 @@ impl<'a> QueryEngine<'a> {
                 let x = aggregators_by_scenario.values().next().unwrap();
                 for i in 0..query.measures.len() {
                     let measure = &query.measures[i];
-                    let source = self.store.get_scenario_chunk_array(scenario, measure.field);
+                    let source = self.store.get_scenario_chunk_array(scenario, &measure.field);
                     let aggregator = factory.create_with_destination(
                         Arc::new(source),
                         &*x[i],
-                        measure.aggregation_function);
+                        &measure.aggregation_function);
                     aggregators.push(aggregator);
                 }
             }
@@ -154,4 +409,79 @@ impl<'a> QueryEngine<'a> {
         }
         aggregators_by_scenario
     }
+
+    /// The fields and the aggregated values of the aggregators, one value per point.
+    fn aggregated_columns(aggregators_by_scenario: HashMap<String, Vec<Box<dyn Aggregator>>>, size: usize) -> (Vec<Field>, Vec<ArrayRef>) {
+        // The aggregators of all scenarios share the same destinations, keep the ones of any scenario.
+        let aggregators = aggregators_by_scenario.into_values().next().unwrap_or_default();
+        // The destinations might be larger than the number of points.
+        let aggregate_fields = aggregators.iter().map(|a| a.get_field().clone()).collect();
+        let aggregates = aggregators.iter().map(|a| a.get_destination().slice(0, size)).collect();
+        (aggregate_fields, aggregates)
+    }
+}
+
+/// The rows of a scenario waiting to be aggregated, with buffers reused from one batch to the
+/// next.
+struct RowBatch {
+    rows: Vec<u32>,
+    /// The dictionary positions of the rows, by coordinate.
+    coordinates: Vec<Vec<u32>>,
+    destinations: Vec<u32>,
+    point: Vec<u32>,
+    /// The time spent mapping the points of the rows, and aggregating their values.
+    grouping: Duration,
+    aggregating: Duration,
+}
+
+impl RowBatch {
+    fn new(point_size: usize) -> RowBatch {
+        RowBatch {
+            rows: Vec::with_capacity(AGGREGATION_BATCH_SIZE),
+            coordinates: vec![Vec::with_capacity(AGGREGATION_BATCH_SIZE); point_size],
+            destinations: Vec::with_capacity(AGGREGATION_BATCH_SIZE),
+            point: vec![0; point_size],
+            grouping: Duration::ZERO,
+            aggregating: Duration::ZERO,
+        }
+    }
+
+    /// Maps the points of the rows and aggregates the rows into them. The column of the scenario
+    /// coordinate is `None`, its value is `scenario`.
+    fn aggregate(&mut self,
+                 columns: &[Option<ChunkArrayReader>],
+                 scenario: u32,
+                 point_dictionary: &mut PointDictionary,
+                 aggregators: &mut [Box<dyn Aggregator>]) {
+        if self.rows.is_empty() {
+            return;
+        }
+        let start = Instant::now();
+        for (column, coordinates) in columns.iter().zip(self.coordinates.iter_mut()) {
+            match column {
+                Some(column) => column.gather::<UInt32Type>(&self.rows, coordinates),
+                None => {
+                    coordinates.clear();
+                    coordinates.resize(self.rows.len(), scenario);
+                }
+            }
+        }
+        self.destinations.clear();
+        for index in 0..self.rows.len() {
+            for (value, coordinates) in self.point.iter_mut().zip(self.coordinates.iter()) {
+                *value = coordinates[index];
+            }
+            self.destinations.push(point_dictionary.map(&self.point));
+        }
+        let grouped = Instant::now();
+        self.grouping += grouped - start;
+
+        let capacity = point_dictionary.size().saturating_sub(1);
+        for aggregator in aggregators.iter_mut() {
+            aggregator.ensure_capacity(capacity);
+            aggregator.aggregate_batch(&self.rows, &self.destinations);
+        }
+        self.aggregating += grouped.elapsed();
+        self.rows.clear();
+    }
 }
@@ -0,0 +1,80 @@
+use std::fmt;
+use std::time::Duration;
+
+use comfy_table::Table;
+
+/// How the query engine finds the rows of a query.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RowProvider {
+    /// All the rows of the store, the query has no filter.
+    Range,
+    /// The rows of the bitmap indices of the filtered values, patched with the rows each
+    /// scenario overrides.
+    Bitmap,
+    /// The points of the named aggregate table instead of the rows. A roll up is timed as a
+    /// whole, as aggregating.
+    AggregateTable(String),
+}
+
+impl fmt::Display for RowProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RowProvider::Range => write!(f, "range"),
+            RowProvider::Bitmap => write!(f, "bitmap"),
+            RowProvider::AggregateTable(name) => write!(f, "aggregate table '{}'", name),
+        }
+    }
+}
+
+/// What the query engine reads for a queried scenario.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScenarioPlan {
+    pub scenario: String,
+    /// The fields of the query the scenario modifies, they are read through its row mappings
+    /// instead of from the base scenario.
+    pub simulated_fields: Vec<String>,
+    /// The rows matching the filters of the query, or the points of the aggregate table matching
+    /// them.
+    pub candidate_rows: u64,
+}
+
+/// How a query is executed, see [`crate::query_engine::QueryEngine::explain`] and
+/// [`crate::query_engine::QueryEngine::analyze`]. An explained query is not grouped nor
+/// aggregated: its point count is an upper bound and it has no grouping and aggregating times.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryPlan {
+    pub row_provider: RowProvider,
+    pub scenarios: Vec<ScenarioPlan>,
+    /// The number of points before the having, the top-n, the limit and the offset of the query.
+    pub point_count: u64,
+    pub filtering: Duration,
+    pub grouping: Option<Duration>,
+    pub aggregating: Option<Duration>,
+}
+
+impl QueryPlan {
+    /// Whether the query was executed to give the plan.
+    pub fn is_analyzed(&self) -> bool {
+        self.aggregating.is_some()
+    }
+}
+
+impl fmt::Display for QueryPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Row provider: {}", self.row_provider)?;
+        let mut table = Table::new();
+        table.set_header(vec!["scenario", "simulated fields", "candidate rows"]);
+        for scenario in self.scenarios.iter() {
+            table.add_row(vec![scenario.scenario.clone(), scenario.simulated_fields.join(", "), scenario.candidate_rows.to_string()]);
+        }
+        writeln!(f, "{}", table)?;
+        let bound = if self.is_analyzed() { "" } else { " (at most)" };
+        writeln!(f, "Points: {}{}", self.point_count, bound)?;
+        let milliseconds = |duration: &Duration| format!("{:.3} ms", duration.as_secs_f64() * 1000f64);
+        write!(f, "Filtering: {}", milliseconds(&self.filtering))?;
+        if let (Some(grouping), Some(aggregating)) = (&self.grouping, &self.aggregating) {
+            write!(f, "\nGrouping: {}\nAggregating: {}", milliseconds(grouping), milliseconds(aggregating))?;
+        }
+        Ok(())
+    }
+}
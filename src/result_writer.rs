@@ -0,0 +1,192 @@
+use std::io::Write;
+
+use arrow::array::{Array, Float64Array, StringArray, UInt64Array};
+use arrow::datatypes::DataType;
+use arrow::error::{ArrowError, Result};
+use arrow::ipc::writer::StreamWriter;
+use arrow::record_batch::RecordBatch;
+use serde_json::{Map, Number, Value};
+
+use crate::point_list_aggregates_result::{array_value_to_string, PointListAggregateResult};
+
+/// Options shared by the writers. Not every option applies to every format.
+#[derive(Debug, Clone)]
+pub struct WriterOptions {
+    /// Csv only.
+    pub delimiter: u8,
+    /// Csv only.
+    pub has_header: bool,
+    /// Written in place of a missing value. When not set, csv writes an empty string and json a `null`.
+    pub null_value: Option<String>,
+    /// Number of digits after the decimal point of floating point values. Not applied to arrow ipc.
+    pub float_precision: Option<usize>,
+    /// Json arrays and nested json only.
+    pub pretty: bool,
+}
+
+impl Default for WriterOptions {
+    fn default() -> Self {
+        WriterOptions {
+            delimiter: b',',
+            has_header: true,
+            null_value: None,
+            float_precision: None,
+            pretty: false,
+        }
+    }
+}
+
+/// Writes a [`PointListAggregateResult`] to csv, json or the arrow ipc stream format.
+pub struct ResultWriter {
+    options: WriterOptions,
+}
+
+impl ResultWriter {
+    pub fn new() -> ResultWriter {
+        ResultWriter { options: WriterOptions::default() }
+    }
+
+    pub fn with_options(options: WriterOptions) -> ResultWriter {
+        ResultWriter { options }
+    }
+
+    pub fn write_csv<W: Write>(&self, result: &PointListAggregateResult, writer: W) -> Result<()> {
+        let batch = result.to_record_batch();
+        let mut writer = csv::WriterBuilder::new()
+            .delimiter(self.options.delimiter)
+            .from_writer(writer);
+        if self.options.has_header {
+            writer.write_record(batch.schema().fields().iter().map(|f| f.name()))?;
+        }
+        let null_value = self.options.null_value.as_deref().unwrap_or("");
+        let mut record = Vec::with_capacity(batch.num_columns());
+        for row in 0..batch.num_rows() {
+            record.clear();
+            for column in batch.columns() {
+                record.push(self.format_value(column.as_ref(), row, null_value)?);
+            }
+            writer.write_record(&record)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Writes one json object per point and per line.
+    pub fn write_json_lines<W: Write>(&self, result: &PointListAggregateResult, mut writer: W) -> Result<()> {
+        let batch = result.to_record_batch();
+        for row in 0..batch.num_rows() {
+            let object = self.json_object(&batch, row, 0)?;
+            serde_json::to_writer(&mut writer, &object)?;
+            writer.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+
+    /// Writes a json array with one object per point.
+    pub fn write_json<W: Write>(&self, result: &PointListAggregateResult, mut writer: W) -> Result<()> {
+        let batch = result.to_record_batch();
+        let points = (0..batch.num_rows())
+            .map(|row| self.json_object(&batch, row, 0))
+            .collect::<Result<Vec<Value>>>()?;
+        self.write_value(&Value::Array(points), &mut writer)
+    }
+
+    /// Writes a single json object whose keys are the values of the first coordinate. Each key
+    /// holds the list of points having this coordinate, without the first coordinate.
+    pub fn write_nested_json<W: Write>(&self, result: &PointListAggregateResult, mut writer: W) -> Result<()> {
+        if result.point_names().is_empty() {
+            return Err(ArrowError::InvalidArgumentError("cannot group a result without coordinates".to_string()));
+        }
+        let batch = result.to_record_batch();
+        let mut groups = Map::new();
+        let first_coordinates = batch.column(0).as_any().downcast_ref::<StringArray>().unwrap();
+        for row in 0..batch.num_rows() {
+            let object = self.json_object(&batch, row, 1)?;
+            let group = groups.entry(first_coordinates.value(row))
+                .or_insert_with(|| Value::Array(Vec::new()));
+            if let Value::Array(points) = group {
+                points.push(object);
+            }
+        }
+
+        self.write_value(&Value::Object(groups), &mut writer)
+    }
+
+    pub fn write_ipc_stream<W: Write>(&self, result: &PointListAggregateResult, writer: W) -> Result<()> {
+        let batch = result.to_record_batch();
+        let mut writer = StreamWriter::try_new(writer, &batch.schema())?;
+        writer.write(&batch)?;
+        writer.finish()
+    }
+
+    fn write_value<W: Write>(&self, value: &Value, writer: W) -> Result<()> {
+        if self.options.pretty {
+            serde_json::to_writer_pretty(writer, value)?;
+        } else {
+            serde_json::to_writer(writer, value)?;
+        }
+        Ok(())
+    }
+
+    fn format_value(&self, column: &dyn Array, row: usize, null_value: &str) -> Result<String> {
+        if column.is_null(row) {
+            return Ok(null_value.to_string());
+        }
+        match (column.data_type(), self.options.float_precision) {
+            (DataType::Float64, Some(precision)) => {
+                let array = column.as_any().downcast_ref::<Float64Array>().unwrap();
+                Ok(format!("{:.*}", precision, array.value(row)))
+            }
+            _ => array_value_to_string(column, row),
+        }
+    }
+
+    /// Builds the json object of a row with the columns starting at `first_column`.
+    fn json_object(&self, batch: &RecordBatch, row: usize, first_column: usize) -> Result<Value> {
+        let schema = batch.schema();
+        let mut object = Map::with_capacity(batch.num_columns() - first_column);
+        for index in first_column..batch.num_columns() {
+            let value = self.json_value(batch.column(index).as_ref(), row)?;
+            object.insert(schema.field(index).name().to_string(), value);
+        }
+        Ok(Value::Object(object))
+    }
+
+    fn json_value(&self, column: &dyn Array, row: usize) -> Result<Value> {
+        if column.is_null(row) {
+            return Ok(match &self.options.null_value {
+                None => Value::Null,
+                Some(null_value) => Value::String(null_value.clone()),
+            });
+        }
+        match column.data_type() {
+            DataType::Utf8 => {
+                let array = column.as_any().downcast_ref::<StringArray>().unwrap();
+                Ok(Value::String(array.value(row).to_string()))
+            }
+            DataType::UInt64 => {
+                let array = column.as_any().downcast_ref::<UInt64Array>().unwrap();
+                Ok(Value::Number(Number::from(array.value(row))))
+            }
+            DataType::Float64 => {
+                let array = column.as_any().downcast_ref::<Float64Array>().unwrap();
+                let value = match self.options.float_precision {
+                    Some(precision) => {
+                        let factor = 10f64.powi(precision as i32);
+                        (array.value(row) * factor).round() / factor
+                    }
+                    None => array.value(row),
+                };
+                // NaN and infinity cannot be represented in json.
+                Ok(Number::from_f64(value).map(Value::Number).unwrap_or(Value::Null))
+            }
+            _ => Err(ArrowError::JsonError(format!("{} not supported", column.data_type()))),
+        }
+    }
+}
+
+impl Default for ResultWriter {
+    fn default() -> Self {
+        ResultWriter::new()
+    }
+}
@@ -4,9 +4,13 @@ use std::fmt;
 use std::fmt::Formatter;
 use std::sync::Arc;
 
-pub trait RowMapping {
-    fn map(&self, row: u32, target_row: u32);
+/// Below this ratio of mapped rows, a scenario uses a [`SortedArrayRowMapping`], a
+/// [`BitmapRowMapping`] otherwise. From 1/16 the bitmap, 1.5 bits per row of the store, is smaller
+/// than the sorted rows, 32 bits per mapped row, and faster to look up.
+pub const DENSE_ROW_MAPPING_RATIO: f64 = 1f64 / 16f64;
 
+/// Maps the rows of the store to the rows of a scenario array.
+pub trait RowMapping {
     fn get(&self, row: &u32) -> Option<u32>;
 
     fn debug(&self) -> String;
@@ -24,10 +28,6 @@ impl std::fmt::Debug for dyn RowMapping {
 pub struct IdentityMapping {}
 
 impl RowMapping for IdentityMapping {
-    fn map(&self, _row: u32, _target_row: u32) {
-        // noop
-    }
-
     fn get(&self, row: &u32) -> Option<u32> {
         Some(*row)
     }
@@ -49,18 +49,24 @@ pub struct IntIntMapRowMapping {
 }
 
 impl IntIntMapRowMapping {
-    pub fn new() -> Arc<dyn RowMapping> {
-        Arc::new(IntIntMapRowMapping {
+    pub fn new() -> IntIntMapRowMapping {
+        IntIntMapRowMapping {
             mapping: RefCell::new(HashMap::new()),
-        })
+        }
     }
-}
 
-impl RowMapping for IntIntMapRowMapping {
-    fn map(&self, row: u32, target_row: u32) {
+    pub fn map(&self, row: u32, target_row: u32) {
         self.mapping.borrow_mut().insert(row, target_row);
     }
+}
 
+impl Default for IntIntMapRowMapping {
+    fn default() -> Self {
+        IntIntMapRowMapping::new()
+    }
+}
+
+impl RowMapping for IntIntMapRowMapping {
     fn get(&self, row: &u32) -> Option<u32> {
         self.mapping.borrow().get(row).cloned()
     }
@@ -74,4 +80,159 @@ impl fmt::Display for IntIntMapRowMapping {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "Mapping {:?}", *self.mapping.borrow())
     }
-}
\ No newline at end of file
+}
+
+pub struct RowMappingFactory;
+
+impl RowMappingFactory {
+    /// Creates the mapping of the rows of a scenario array: `rows[i]` is the row of the store of
+    /// the i-th element of the array. The implementation depends on the ratio of mapped rows, see
+    /// [`DENSE_ROW_MAPPING_RATIO`].
+    pub fn create(rows: Vec<u32>, row_count: u64) -> Arc<dyn RowMapping> {
+        if rows.len() as f64 >= row_count as f64 * DENSE_ROW_MAPPING_RATIO {
+            Arc::new(BitmapRowMapping::new(rows))
+        } else {
+            Arc::new(SortedArrayRowMapping::new(rows))
+        }
+    }
+}
+
+/// How the fields of a scenario share the mappings of their rows, chosen when the scenario is
+/// loaded. A shared mapping covers the rows modified by any of its fields: the fields store the
+/// values of all these rows, modified or not, in exchange for a single mapping that a reader can
+/// resolve once per row for all the fields.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum RowMappingSharing {
+    /// Each field maps only the rows it modifies.
+    #[default]
+    PerField,
+    /// All the fields of the scenario share one mapping.
+    PerScenario,
+    /// The fields of each group share a mapping, the fields of no group have their own.
+    PerGroup(Vec<Vec<String>>),
+}
+
+impl RowMappingSharing {
+    /// Splits the fields into the groups sharing a mapping, as indices in `fields`.
+    pub fn groups(&self, fields: &[&str]) -> Vec<Vec<usize>> {
+        match self {
+            RowMappingSharing::PerField => (0..fields.len()).map(|index| vec![index]).collect(),
+            RowMappingSharing::PerScenario if fields.is_empty() => Vec::new(),
+            RowMappingSharing::PerScenario => vec![(0..fields.len()).collect()],
+            RowMappingSharing::PerGroup(groups) => {
+                let mut grouped = vec![false; fields.len()];
+                let mut result = Vec::new();
+                for group in groups {
+                    let indices: Vec<usize> = (0..fields.len())
+                        .filter(|index| !grouped[*index] && group.iter().any(|name| name == fields[*index]))
+                        .collect();
+                    indices.iter().for_each(|index| grouped[*index] = true);
+                    if !indices.is_empty() {
+                        result.push(indices);
+                    }
+                }
+                result.extend((0..fields.len()).filter(|index| !grouped[*index]).map(|index| vec![index]));
+                result
+            }
+        }
+    }
+}
+
+/// Sorted rows and their target rows, looked up with a binary search.
+#[derive(Debug)]
+pub struct SortedArrayRowMapping {
+    rows: Vec<u32>,
+    /// `None` when the target rows are the positions in `rows`.
+    target_rows: Option<Vec<u32>>,
+}
+
+impl SortedArrayRowMapping {
+    /// See [`RowMappingFactory::create`].
+    pub fn new(rows: Vec<u32>) -> SortedArrayRowMapping {
+        let (rows, target_rows) = sort_rows(rows);
+        SortedArrayRowMapping { rows, target_rows }
+    }
+}
+
+impl RowMapping for SortedArrayRowMapping {
+    fn get(&self, row: &u32) -> Option<u32> {
+        let index = self.rows.binary_search(row).ok()?;
+        match &self.target_rows {
+            None => Some(index as u32),
+            Some(target_rows) => Some(target_rows[index]),
+        }
+    }
+
+    fn debug(&self) -> String {
+        format!("sorted_array_row_mapping: {} rows", self.rows.len())
+    }
+}
+
+/// The rows in a bitmap with the number of rows before each word of the bitmap: the rank of a
+/// row, and so its target row, is found in constant time. The rank of roaring is linear in the
+/// number of containers and of words before the row.
+#[derive(Debug)]
+pub struct BitmapRowMapping {
+    words: Vec<u64>,
+    ranks: Vec<u32>,
+    /// The target rows by rank, `None` when the target row of a row is its rank.
+    target_rows: Option<Vec<u32>>,
+}
+
+impl BitmapRowMapping {
+    /// See [`RowMappingFactory::create`].
+    pub fn new(rows: Vec<u32>) -> BitmapRowMapping {
+        let (rows, target_rows) = sort_rows(rows);
+        let mut words = vec![0u64; rows.last().map_or(0, |row| *row as usize / 64 + 1)];
+        for row in rows.iter() {
+            words[*row as usize / 64] |= 1 << (row % 64);
+        }
+        let mut ranks = Vec::with_capacity(words.len());
+        let mut rank = 0;
+        for word in words.iter() {
+            ranks.push(rank);
+            rank += word.count_ones();
+        }
+        BitmapRowMapping { words, ranks, target_rows }
+    }
+}
+
+impl RowMapping for BitmapRowMapping {
+    fn get(&self, row: &u32) -> Option<u32> {
+        let index = *row as usize / 64;
+        let word = *self.words.get(index)?;
+        let bit = 1u64 << (row % 64);
+        if word & bit == 0 {
+            return None;
+        }
+        let rank = self.ranks[index] + (word & (bit - 1)).count_ones();
+        match &self.target_rows {
+            None => Some(rank),
+            Some(target_rows) => Some(target_rows[rank as usize]),
+        }
+    }
+
+    fn debug(&self) -> String {
+        format!("bitmap_row_mapping: {} rows", self.words.iter().map(|w| w.count_ones()).sum::<u32>())
+    }
+}
+
+/// Sorts the rows and returns their target rows, `None` if the rows are already sorted. The last
+/// target row of a duplicated row wins, as with [`IntIntMapRowMapping`].
+fn sort_rows(rows: Vec<u32>) -> (Vec<u32>, Option<Vec<u32>>) {
+    if rows.windows(2).all(|w| w[0] < w[1]) {
+        return (rows, None);
+    }
+    let mut pairs: Vec<(u32, u32)> = rows.into_iter().zip(0..).collect();
+    pairs.sort_unstable();
+    pairs.dedup_by(|next, previous| {
+        if next.0 == previous.0 {
+            *previous = *next;
+            true
+        } else {
+            false
+        }
+    });
+    let (rows, target_rows) = pairs.into_iter().unzip();
+    (rows, Some(target_rows))
+}
@@ -0,0 +1,454 @@
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+use arrow::datatypes::DataType;
+use arrow::ipc::reader::StreamReader;
+use arrow::record_batch::RecordBatch;
+use indexmap::IndexMap;
+use parquet::errors::ParquetError;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::aggregate_table::AggregateTableDefinition;
+use crate::csv_loader::{CsvLoader, CsvLoaderError};
+use crate::datastore::{MAIN_SCENARIO_NAME, SCENARIO_FIELD_NAME, Store};
+use crate::parquet_loader::ParquetLoader;
+use crate::point_list_aggregates_result::PointListAggregateResult;
+use crate::query::Query;
+use crate::query_cache::{QueryCache, QueryCacheConfig};
+use crate::query_engine::QueryEngine;
+use crate::result_writer::ResultWriter;
+use crate::sql;
+
+pub const JSON_CONTENT_TYPE: &str = "application/json";
+pub const CSV_CONTENT_TYPE: &str = "text/csv";
+pub const ARROW_STREAM_CONTENT_TYPE: &str = "application/vnd.apache.arrow.stream";
+pub const DEFAULT_ADDRESS: &str = "127.0.0.1:8080";
+
+#[derive(Debug)]
+pub enum ServerError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    Csv(CsvLoaderError),
+    Parquet(ParquetError),
+    Config(String),
+}
+
+impl fmt::Display for ServerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServerError::Io(e) => write!(f, "{}", e),
+            ServerError::Json(e) => write!(f, "{}", e),
+            ServerError::Csv(e) => write!(f, "{}", e),
+            ServerError::Parquet(e) => write!(f, "{}", e),
+            ServerError::Config(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl Error for ServerError {}
+
+impl From<std::io::Error> for ServerError {
+    fn from(e: std::io::Error) -> Self {
+        ServerError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for ServerError {
+    fn from(e: serde_json::Error) -> Self {
+        ServerError::Json(e)
+    }
+}
+
+impl From<CsvLoaderError> for ServerError {
+    fn from(e: CsvLoaderError) -> Self {
+        ServerError::Csv(e)
+    }
+}
+
+impl From<ParquetError> for ServerError {
+    fn from(e: ParquetError) -> Self {
+        ServerError::Parquet(e)
+    }
+}
+
+/// The json configuration of the server, e.g.
+///
+/// ```json
+/// {
+///   "address": "127.0.0.1:8080",
+///   "source": { "format": "csv", "directory": "test/data", "key_field": "OrderDetailID" },
+///   "query_cache": { "max_entries": 256 },
+///   "aggregate_tables": [{
+///     "name": "by_category",
+///     "coordinates": ["CategoryName"],
+///     "measures": [{ "field": "Quantity", "aggregation_function": "sum" }]
+///   }]
+/// }
+/// ```
+///
+/// The query results are only cached when `query_cache` is set, see [`QueryCache`]. The aggregate
+/// tables are added to the store once it is loaded, see [`AggregateTableDefinition`].
+#[derive(Debug, Clone, Deserialize)]
+pub struct ServerConfig {
+    #[serde(default = "default_address")]
+    pub address: String,
+    pub source: SourceConfig,
+    #[serde(default)]
+    pub query_cache: Option<QueryCacheConfig>,
+    #[serde(default)]
+    pub aggregate_tables: Vec<AggregateTableDefinition>,
+}
+
+fn default_address() -> String {
+    DEFAULT_ADDRESS.to_string()
+}
+
+/// Where the store is loaded from when the server starts.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "format", rename_all = "snake_case")]
+pub enum SourceConfig {
+    /// The files of a directory, one per scenario, see [`CsvLoader`].
+    Csv {
+        directory: PathBuf,
+        key_field: String,
+        #[serde(default)]
+        delimiter: Option<char>,
+        #[serde(default)]
+        file_pattern: Option<String>,
+    },
+    /// One file per scenario. The schema of the store is the one of the base file.
+    Parquet {
+        key_field: String,
+        files: IndexMap<String, PathBuf>,
+    },
+}
+
+impl ServerConfig {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<ServerConfig, ServerError> {
+        Ok(serde_json::from_reader(File::open(path)?)?)
+    }
+
+    pub fn create_store(&self) -> Result<Store, ServerError> {
+        let mut store = self.load_source()?;
+        for definition in self.aggregate_tables.iter() {
+            store.add_aggregate_table(definition.clone()).map_err(|errors| {
+                let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+                ServerError::Config(format!("invalid aggregate table '{}': {}", definition.name, messages.join(", ")))
+            })?;
+        }
+        Ok(store)
+    }
+
+    fn load_source(&self) -> Result<Store, ServerError> {
+        match &self.source {
+            SourceConfig::Csv { directory, key_field, delimiter, file_pattern } => {
+                let mut loader = CsvLoader::new();
+                if let Some(delimiter) = delimiter {
+                    if !delimiter.is_ascii() {
+                        return Err(ServerError::Config(format!("the delimiter '{}' is not an ascii character", delimiter)));
+                    }
+                    loader.set_delimiter(*delimiter as u8);
+                }
+                if let Some(file_pattern) = file_pattern {
+                    loader.set_file_pattern(file_pattern);
+                }
+                Ok(loader.create_store(directory, key_field)?)
+            }
+            SourceConfig::Parquet { key_field, files } => {
+                let base_file = files.get(MAIN_SCENARIO_NAME)
+                    .ok_or_else(|| ServerError::Config(format!("cannot find a file for the {} scenario", MAIN_SCENARIO_NAME)))?;
+                let mut store = ParquetLoader::create_store(base_file, key_field)?;
+                let mut loader = ParquetLoader::new(&mut store);
+                for (scenario, path) in files.iter().filter(|(s, _)| *s != MAIN_SCENARIO_NAME) {
+                    loader.load(scenario, path)?;
+                }
+                Ok(store)
+            }
+        }
+    }
+}
+
+/// The parts of an http request the server looks at.
+pub struct HttpRequest<'a> {
+    pub method: &'a str,
+    pub url: &'a str,
+    pub accept: Option<&'a str>,
+    pub content_type: Option<&'a str>,
+    pub body: &'a [u8],
+}
+
+#[derive(Debug)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    fn json(status: u16, value: &Value) -> HttpResponse {
+        HttpResponse { status, content_type: JSON_CONTENT_TYPE, body: value.to_string().into_bytes() }
+    }
+
+    fn error(status: u16, message: &str) -> HttpResponse {
+        HttpResponse::json(status, &json!({ "error": message }))
+    }
+}
+
+/// Serves a [`Store`] over http. The endpoints are
+///
+/// - `GET /fields`: the fields of the store with their type.
+/// - `GET /fields/{field}/members`: the distinct values of a Utf8 field or of `scenario`.
+/// - `GET /scenarios`: the loaded scenarios.
+/// - `POST /query`: executes a json query, see [`Query`].
+/// - `POST /sql`: executes a sql query, see [`sql::parse_query`].
+/// - `POST /scenarios/{scenario}`: loads a new scenario from a csv file or an arrow ipc stream,
+///   depending on the content type. The scenario batch can be restricted to the key and the
+///   fields it modifies when sent as arrow.
+/// - `PUT /scenarios/{scenario}`: loads a scenario other than the base one again, as above.
+/// - `GET /cache`: the statistics of the query cache, when there is one.
+///
+/// Query results are a json array with an object per point, or an arrow ipc stream when the
+/// request accepts `application/vnd.apache.arrow.stream`. Errors are json objects. The store is
+/// not shared between threads, the requests are handled one at a time.
+pub struct Server {
+    store: Store,
+    cache: Option<QueryCache>,
+}
+
+impl Server {
+    pub fn new(store: Store) -> Server {
+        Server { store, cache: None }
+    }
+
+    pub fn store(&self) -> &Store {
+        &self.store
+    }
+
+    /// Caches the results of the json and sql queries.
+    pub fn set_query_cache(&mut self, cache: QueryCache) -> &mut Server {
+        self.cache = Some(cache);
+        self
+    }
+
+    pub fn query_cache(&self) -> Option<&QueryCache> {
+        self.cache.as_ref()
+    }
+
+    /// Listens on the address until the process stops.
+    pub fn serve(&mut self, address: &str) -> Result<(), ServerError> {
+        let server = tiny_http::Server::http(address)
+            .map_err(|e| ServerError::Config(format!("cannot listen on {}: {}", address, e)))?;
+        for mut request in server.incoming_requests() {
+            let mut body = Vec::new();
+            let response = match request.as_reader().read_to_end(&mut body) {
+                Ok(_) => {
+                    let method = request.method().to_string();
+                    self.handle(&HttpRequest {
+                        method: &method,
+                        url: request.url(),
+                        accept: header(&request, "Accept"),
+                        content_type: header(&request, "Content-Type"),
+                        body: &body,
+                    })
+                }
+                Err(e) => HttpResponse::error(400, &format!("cannot read the request body: {}", e)),
+            };
+            let content_type = tiny_http::Header::from_bytes(&b"Content-Type"[..], response.content_type.as_bytes()).unwrap();
+            let http_response = tiny_http::Response::from_data(response.body)
+                .with_status_code(response.status)
+                .with_header(content_type);
+            if let Err(e) = request.respond(http_response) {
+                eprintln!("cannot send the response: {}", e);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn handle(&mut self, request: &HttpRequest) -> HttpResponse {
+        let path = request.url.split('?').next().unwrap_or("");
+        let segments: Vec<String> = path.split('/').filter(|s| !s.is_empty()).map(percent_decode).collect();
+        let segments: Vec<&str> = segments.iter().map(|s| s.as_str()).collect();
+        match (request.method, segments.as_slice()) {
+            ("GET", ["fields"]) => self.fields(),
+            ("GET", ["fields", field, "members"]) => self.members(field),
+            ("GET", ["scenarios"]) => self.scenarios(),
+            ("POST", ["scenarios", scenario]) => self.load(scenario, request, false),
+            ("PUT", ["scenarios", scenario]) => self.load(scenario, request, true),
+            ("POST", ["query"]) => self.query(request),
+            ("POST", ["sql"]) => self.sql(request),
+            ("GET", ["cache"]) => self.cache_stats(),
+            _ => HttpResponse::error(404, &format!("no endpoint for {} {}", request.method, path)),
+        }
+    }
+
+    fn fields(&self) -> HttpResponse {
+        let key_field = self.store.key_field().name();
+        let fields: Vec<Value> = self.store.schema().fields().iter().map(|f| json!({
+            "name": f.name(),
+            "data_type": f.data_type().to_string(),
+            "key": f.name() == key_field,
+        })).collect();
+        HttpResponse::json(200, &Value::Array(fields))
+    }
+
+    fn members(&self, field: &str) -> HttpResponse {
+        if field != SCENARIO_FIELD_NAME {
+            match self.store.schema().field_with_name(field).map(|f| f.data_type().clone()) {
+                Ok(DataType::Utf8) => {}
+                Ok(data_type) => return HttpResponse::error(400, &format!("'{}' is a {} field, only Utf8 fields have members", field, data_type)),
+                Err(_) => return HttpResponse::error(404, &format!("unknown field '{}'", field)),
+            }
+        }
+        match self.store.dictionary_provider.dicos.get(field) {
+            Some(dictionary) => {
+                let members: Vec<&String> = (0..dictionary.size() as u32).filter_map(|p| dictionary.read(&p)).collect();
+                HttpResponse::json(200, &json!(members))
+            }
+            None => HttpResponse::json(200, &json!([])),
+        }
+    }
+
+    fn scenarios(&self) -> HttpResponse {
+        self.members(SCENARIO_FIELD_NAME)
+    }
+
+    fn query(&mut self, request: &HttpRequest) -> HttpResponse {
+        let query: Query = match serde_json::from_slice(request.body) {
+            Ok(query) => query,
+            Err(e) => return HttpResponse::error(400, &format!("cannot parse the query: {}", e)),
+        };
+        if let Err(errors) = query.validate(&self.store) {
+            let errors: Vec<Value> = errors.iter().map(|e| json!({ "field": e.field, "message": e.message })).collect();
+            return HttpResponse::json(400, &json!({ "errors": errors }));
+        }
+        self.execute(&query, request)
+    }
+
+    fn sql(&mut self, request: &HttpRequest) -> HttpResponse {
+        let text = match std::str::from_utf8(request.body) {
+            Ok(text) => text,
+            Err(e) => return HttpResponse::error(400, &format!("the query is not valid utf-8: {}", e)),
+        };
+        match sql::parse_query(text, &self.store) {
+            Ok(query) => self.execute(&query, request),
+            Err(e) => HttpResponse::error(400, &e.to_string()),
+        }
+    }
+
+    fn execute(&mut self, query: &Query, request: &HttpRequest) -> HttpResponse {
+        match &mut self.cache {
+            Some(cache) => Server::write_result(&cache.execute(&self.store, query), request),
+            None => Server::write_result(&QueryEngine::new(&self.store).execute(query), request),
+        }
+    }
+
+    fn cache_stats(&self) -> HttpResponse {
+        match &self.cache {
+            Some(cache) => {
+                let stats = cache.stats();
+                HttpResponse::json(200, &json!({
+                    "hits": stats.hits,
+                    "misses": stats.misses,
+                    "evictions": stats.evictions,
+                    "invalidations": stats.invalidations,
+                    "refreshes": stats.refreshes,
+                    "entries": stats.entries,
+                    "bytes": stats.bytes,
+                }))
+            }
+            None => HttpResponse::error(404, "the query cache is disabled"),
+        }
+    }
+
+    fn write_result(result: &PointListAggregateResult, request: &HttpRequest) -> HttpResponse {
+        let writer = ResultWriter::new();
+        let mut body = Vec::new();
+        let (written, content_type) = if accepts(request, ARROW_STREAM_CONTENT_TYPE) {
+            (writer.write_ipc_stream(result, &mut body), ARROW_STREAM_CONTENT_TYPE)
+        } else {
+            (writer.write_json(result, &mut body), JSON_CONTENT_TYPE)
+        };
+        match written {
+            Ok(()) => HttpResponse { status: 200, content_type, body },
+            Err(e) => HttpResponse::error(500, &format!("cannot write the result: {}", e)),
+        }
+    }
+
+    fn load(&mut self, scenario: &str, request: &HttpRequest, replace: bool) -> HttpResponse {
+        if replace && scenario == MAIN_SCENARIO_NAME {
+            return HttpResponse::error(400, &format!("the {} scenario cannot be loaded again", MAIN_SCENARIO_NAME));
+        }
+        if !replace && self.store.has_scenario(scenario) {
+            return HttpResponse::error(409, &format!("scenario '{}' is already loaded", scenario));
+        }
+
+        let batch = match request.content_type.map(media_type) {
+            Some(CSV_CONTENT_TYPE) => CsvLoader::new()
+                .read_from(&self.store.schema(), request.body, Path::new(scenario))
+                .map_err(|e| e.to_string()),
+            Some(ARROW_STREAM_CONTENT_TYPE) => Server::read_ipc_stream(request.body),
+            content_type => return HttpResponse::error(415, &format!(
+                "cannot load a scenario from {}, expected {} or {}",
+                content_type.unwrap_or("a request without content type"), CSV_CONTENT_TYPE, ARROW_STREAM_CONTENT_TYPE)),
+        };
+        match batch.and_then(|batch| self.store.prepare_batch(scenario, &batch).map_err(|e| e.to_string())) {
+            Ok(batch) => {
+                let status = if self.store.has_scenario(scenario) { 200 } else { 201 };
+                self.store.load(scenario, &batch);
+                HttpResponse::json(status, &json!({ "scenario": scenario, "rows": batch.num_rows() }))
+            }
+            Err(message) => HttpResponse::error(400, &message),
+        }
+    }
+
+    fn read_ipc_stream(body: &[u8]) -> Result<RecordBatch, String> {
+        let reader = StreamReader::try_new(Cursor::new(body)).map_err(|e| e.to_string())?;
+        let schema = reader.schema();
+        let batches = reader.collect::<arrow::error::Result<Vec<RecordBatch>>>().map_err(|e| e.to_string())?;
+        RecordBatch::concat(&schema, &batches).map_err(|e| e.to_string())
+    }
+
+}
+
+fn header<'a>(request: &'a tiny_http::Request, name: &'static str) -> Option<&'a str> {
+    request.headers().iter()
+        .find(|h| h.field.equiv(name))
+        .map(|h| h.value.as_str())
+}
+
+fn accepts(request: &HttpRequest, content_type: &str) -> bool {
+    request.accept
+        .map(|accept| accept.split(',').any(|a| media_type(a) == content_type))
+        .unwrap_or(false)
+}
+
+/// The media type of a content type, without its parameters e.g. `text/csv; charset=utf-8`.
+fn media_type(content_type: &str) -> &str {
+    content_type.split(';').next().unwrap_or("").trim()
+}
+
+fn percent_decode(segment: &str) -> String {
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
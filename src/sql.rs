@@ -0,0 +1,396 @@
+use std::error::Error;
+use std::fmt;
+
+use arrow::datatypes::DataType;
+use sqlparser::ast::{BinaryOperator, Expr, Function, FunctionArg, FunctionArgExpr, FunctionArguments,
+                     GroupByExpr, ObjectName, OffsetRows, OrderByExpr, Select, SelectItem, SetExpr, Statement,
+                     TableFactor, UnaryOperator, Value};
+use sqlparser::dialect::GenericDialect;
+use sqlparser::parser::{Parser, ParserError};
+
+use crate::datastore::{SCENARIO_FIELD_NAME, Store};
+use crate::having::{Comparison, Having};
+use crate::order_by::OrderBy;
+use crate::point_list_aggregates_result::PointListAggregateResult;
+use crate::query::{AggregatedMeasure, Query, SUPPORTED_AGGREGATION_FUNCTIONS};
+use crate::query_engine::QueryEngine;
+
+/// The name of the only table that can be queried.
+pub const TABLE_NAME: &str = "store";
+
+#[derive(Debug)]
+pub enum SqlError {
+    Parser(ParserError),
+    /// Valid sql that cannot be translated into a [`Query`].
+    Unsupported(String),
+    /// A query that does not match the store, e.g. an unknown column.
+    Invalid(String),
+}
+
+impl fmt::Display for SqlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SqlError::Parser(e) => write!(f, "{}", e),
+            SqlError::Unsupported(message) => write!(f, "unsupported: {}", message),
+            SqlError::Invalid(message) => write!(f, "invalid query: {}", message),
+        }
+    }
+}
+
+impl Error for SqlError {}
+
+impl From<ParserError> for SqlError {
+    fn from(e: ParserError) -> Self {
+        SqlError::Parser(e)
+    }
+}
+
+type Result<T> = std::result::Result<T, SqlError>;
+
+fn unsupported<T>(message: String) -> Result<T> {
+    Err(SqlError::Unsupported(message))
+}
+
+fn invalid<T>(message: String) -> Result<T> {
+    Err(SqlError::Invalid(message))
+}
+
+/// Translates a sql statement into a [`Query`]. The supported statements have the form
+///
+/// ```sql
+/// SELECT scenario, CategoryName, sum(Price)
+/// FROM store
+/// WHERE scenario IN ('base', 's1') AND CategoryName = 'Beverages'
+/// GROUP BY scenario, CategoryName
+/// HAVING sum(Price) > 100
+/// ORDER BY sum(Price) DESC
+/// LIMIT 10 OFFSET 5
+/// ```
+///
+/// `scenario` is a pseudo-column holding the name of the scenario. The selected columns are the
+/// coordinates of the query and must all be grouped by. The where clause is a conjunction of
+/// `=` and `IN` filters on these coordinates. The result lists the coordinates first, then the
+/// aggregates, whatever their order in the select list.
+pub fn parse_query(sql: &str, store: &Store) -> Result<Query> {
+    let mut statements = Parser::parse_sql(&GenericDialect {}, sql)?;
+    if statements.len() != 1 {
+        return unsupported(format!("expected a single statement, found {}", statements.len()));
+    }
+    let query = match statements.remove(0) {
+        Statement::Query(query) => query,
+        statement => return unsupported(format!("only SELECT statements are supported, found '{}'", statement)),
+    };
+    if query.with.is_some() {
+        return unsupported("WITH clauses".to_string());
+    }
+    if query.fetch.is_some() || !query.limit_by.is_empty() || !query.locks.is_empty() {
+        return unsupported("FETCH, LIMIT BY and locking clauses".to_string());
+    }
+    let select = match query.body.as_ref() {
+        SetExpr::Select(select) => select,
+        body => return unsupported(format!("'{}', only a plain SELECT can be queried", body)),
+    };
+
+    let mut planner = Planner { store, query: Query::new() };
+    planner.plan_select(select)?;
+    if let Some(order_by) = &query.order_by {
+        if order_by.interpolate.is_some() {
+            return unsupported("INTERPOLATE".to_string());
+        }
+        for expr in order_by.exprs.iter() {
+            planner.plan_order_by(expr)?;
+        }
+    }
+    if let Some(limit) = &query.limit {
+        planner.query.limit = Some(Planner::parse_count(limit, "LIMIT")?);
+    }
+    if let Some(offset) = &query.offset {
+        if offset.rows != OffsetRows::None {
+            return unsupported(format!("'{}'", offset));
+        }
+        planner.query.offset = Planner::parse_count(&offset.value, "OFFSET")?;
+    }
+    Ok(planner.query)
+}
+
+struct Planner<'a> {
+    store: &'a Store,
+    query: Query,
+}
+
+impl Planner<'_> {
+    fn plan_select(&mut self, select: &Select) -> Result<()> {
+        if select.distinct.is_some() || select.top.is_some() || select.into.is_some() {
+            return unsupported("DISTINCT, TOP and INTO".to_string());
+        }
+        if !select.lateral_views.is_empty() || select.prewhere.is_some() || select.qualify.is_some()
+            || !select.named_window.is_empty() || select.connect_by.is_some() || !select.cluster_by.is_empty()
+            || !select.distribute_by.is_empty() || !select.sort_by.is_empty() {
+            return unsupported("LATERAL VIEW, PREWHERE, QUALIFY, WINDOW, CONNECT BY, CLUSTER BY, DISTRIBUTE BY and SORT BY".to_string());
+        }
+        self.plan_from(select)?;
+
+        for item in select.projection.iter() {
+            match item {
+                SelectItem::UnnamedExpr(Expr::Function(function)) => {
+                    let measure = self.plan_measure(function)?;
+                    if self.query.measures.contains(&measure) {
+                        return unsupported(format!("selecting '{}' twice", item));
+                    }
+                    self.query.measures.push(measure);
+                }
+                SelectItem::UnnamedExpr(expr) => {
+                    let field = self.plan_coordinate(expr)?;
+                    if self.query.coordinates.insert(field, None).is_some() {
+                        return unsupported(format!("selecting '{}' twice", item));
+                    }
+                }
+                SelectItem::ExprWithAlias { .. } => return unsupported(format!("'{}', column aliases are not supported", item)),
+                SelectItem::Wildcard(_) | SelectItem::QualifiedWildcard(..) => {
+                    return unsupported(format!("'{}', the columns must be listed", item))
+                }
+            }
+        }
+        if self.query.measures.is_empty() {
+            return invalid("the query must select at least one aggregate e.g. sum(price)".to_string());
+        }
+
+        let grouped = match &select.group_by {
+            GroupByExpr::Expressions(exprs, modifiers) if modifiers.is_empty() => exprs,
+            group_by => return unsupported(format!("'{}'", group_by)),
+        };
+        let mut grouped_fields = Vec::with_capacity(grouped.len());
+        for expr in grouped {
+            let field = self.plan_coordinate(expr)?;
+            if !self.query.coordinates.contains_key(&field) {
+                return unsupported(format!("grouping by '{}' requires it in the select list", field));
+            }
+            grouped_fields.push(field);
+        }
+        if let Some(field) = self.query.coordinates.keys().find(|f| !grouped_fields.contains(f)) {
+            return invalid(format!("'{}' must appear in the GROUP BY clause", field));
+        }
+
+        if let Some(selection) = &select.selection {
+            self.plan_where(selection)?;
+        }
+        if let Some(having) = &select.having {
+            self.plan_having(having)?;
+        }
+        Ok(())
+    }
+
+    fn plan_from(&self, select: &Select) -> Result<()> {
+        if select.from.len() != 1 || !select.from[0].joins.is_empty() {
+            return unsupported(format!("the query must read the single table '{}', without joins", TABLE_NAME));
+        }
+        match &select.from[0].relation {
+            TableFactor::Table { name, alias: None, args: None, .. } if Planner::is_store(name) => Ok(()),
+            TableFactor::Table { name, .. } if !Planner::is_store(name) => {
+                invalid(format!("unknown table '{}', the only table is '{}'", name, TABLE_NAME))
+            }
+            relation => unsupported(format!("'{}' in the FROM clause", relation)),
+        }
+    }
+
+    /// The store table, possibly quoted or qualified by the `public` schema as sql clients often do.
+    fn is_store(name: &ObjectName) -> bool {
+        match name.0.as_slice() {
+            [table] => table.value == TABLE_NAME,
+            [schema, table] => schema.value == "public" && table.value == TABLE_NAME,
+            _ => false,
+        }
+    }
+
+    /// A column used as a coordinate: the scenario or a string column.
+    fn plan_coordinate(&self, expr: &Expr) -> Result<String> {
+        let field = Planner::parse_column(expr)?;
+        if field == SCENARIO_FIELD_NAME {
+            return Ok(field);
+        }
+        match self.store.schema().field_with_name(&field).map(|f| f.data_type()) {
+            Ok(DataType::Utf8) => Ok(field),
+            Ok(data_type) => invalid(format!("'{}' is a {} column, only Utf8 columns can be grouped by", field, data_type)),
+            Err(_) => invalid(format!("unknown column '{}'", field)),
+        }
+    }
+
+    fn plan_measure(&self, function: &Function) -> Result<AggregatedMeasure> {
+        let aggregation_function = function.name.to_string().to_lowercase();
+        if !SUPPORTED_AGGREGATION_FUNCTIONS.contains(&aggregation_function.as_str()) {
+            return unsupported(format!("'{}', the supported aggregation functions are {:?}", function, SUPPORTED_AGGREGATION_FUNCTIONS));
+        }
+        if function.filter.is_some() || function.over.is_some() || function.null_treatment.is_some()
+            || !matches!(function.parameters, FunctionArguments::None) {
+            return unsupported(format!("'{}', aggregation functions only take a column", function));
+        }
+        let arg = match &function.args {
+            FunctionArguments::List(list) if list.duplicate_treatment.is_none() && list.clauses.is_empty() && list.args.len() == 1 => &list.args[0],
+            _ => return unsupported(format!("'{}', aggregation functions only take a column", function)),
+        };
+        let field = match arg {
+            FunctionArg::Unnamed(FunctionArgExpr::Expr(expr)) => Planner::parse_column(expr)?,
+            _ => return unsupported(format!("'{}', aggregation functions only take a column", function)),
+        };
+        match self.store.schema().field_with_name(&field).map(|f| f.data_type()) {
+            Ok(DataType::UInt32) | Ok(DataType::Float64) => Ok(AggregatedMeasure::new(&field, &aggregation_function)),
+            Ok(data_type) => invalid(format!("cannot aggregate '{}', {} is not supported", field, data_type)),
+            Err(_) => invalid(format!("unknown column '{}'", field)),
+        }
+    }
+
+    /// The alias of a selected measure, as found in the result.
+    fn plan_measure_alias(&self, function: &Function) -> Result<String> {
+        let measure = self.plan_measure(function)?;
+        if !self.query.measures.contains(&measure) {
+            return invalid(format!("'{}' must appear in the select list", function));
+        }
+        Ok(measure.alias())
+    }
+
+    fn plan_where(&mut self, expr: &Expr) -> Result<()> {
+        let (column, values) = match expr {
+            Expr::Nested(expr) => return self.plan_where(expr),
+            Expr::BinaryOp { left, op: BinaryOperator::And, right } => {
+                self.plan_where(left)?;
+                return self.plan_where(right);
+            }
+            Expr::BinaryOp { left, op: BinaryOperator::Eq, right } => (left.as_ref(), vec![Planner::parse_literal(right)?]),
+            Expr::InList { expr, list, negated: false } => {
+                let values = list.iter().map(Planner::parse_literal).collect::<Result<Vec<String>>>()?;
+                (expr.as_ref(), values)
+            }
+            _ => return unsupported(format!("'{}', the WHERE clause must be a conjunction of '=' and 'IN' filters", expr)),
+        };
+
+        let field = self.plan_coordinate(column)?;
+        match self.query.coordinates.get_mut(&field) {
+            None => unsupported(format!("filtering on '{}' requires it in the select list and the GROUP BY clause", field)),
+            Some(accepted) => {
+                // Several filters on the same column must all be satisfied.
+                let values = match accepted.take() {
+                    None => values,
+                    Some(previous) => previous.into_iter().filter(|v| values.contains(v)).collect(),
+                };
+                *accepted = Some(values);
+                Ok(())
+            }
+        }
+    }
+
+    fn plan_having(&mut self, expr: &Expr) -> Result<()> {
+        let (left, op, right) = match expr {
+            Expr::Nested(expr) => return self.plan_having(expr),
+            Expr::BinaryOp { left, op: BinaryOperator::And, right } => {
+                self.plan_having(left)?;
+                return self.plan_having(right);
+            }
+            Expr::BinaryOp { left, op, right } => (left.as_ref(), op, right.as_ref()),
+            _ => return unsupported(format!("'{}', the HAVING clause must be a conjunction of comparisons", expr)),
+        };
+        let comparison = match op {
+            BinaryOperator::Eq => Comparison::Eq,
+            BinaryOperator::NotEq => Comparison::NotEq,
+            BinaryOperator::Lt => Comparison::Lt,
+            BinaryOperator::LtEq => Comparison::LtEq,
+            BinaryOperator::Gt => Comparison::Gt,
+            BinaryOperator::GtEq => Comparison::GtEq,
+            _ => return unsupported(format!("'{}' in the HAVING clause", op)),
+        };
+        // The aggregate can be on either side of the comparison.
+        let (function, value, comparison) = match (left, right) {
+            (Expr::Function(function), value) => (function, value, comparison),
+            (value, Expr::Function(function)) => (function, value, Planner::flip(comparison)),
+            _ => return unsupported(format!("'{}', a comparison must have an aggregate on one side", expr)),
+        };
+        let alias = self.plan_measure_alias(function)?;
+        let value = Planner::parse_number(value)?;
+        self.query.having.push(Having::new(&alias, comparison, value));
+        Ok(())
+    }
+
+    fn plan_order_by(&mut self, expr: &OrderByExpr) -> Result<()> {
+        if expr.with_fill.is_some() {
+            return unsupported(format!("'{}'", expr));
+        }
+        let field = match &expr.expr {
+            Expr::Function(function) => self.plan_measure_alias(function)?,
+            column => {
+                let field = self.plan_coordinate(column)?;
+                if !self.query.coordinates.contains_key(&field) {
+                    return invalid(format!("ordering by '{}' requires it in the select list", field));
+                }
+                field
+            }
+        };
+        let order_by = if expr.asc == Some(false) { OrderBy::desc(&field) } else { OrderBy::asc(&field) };
+        let order_by = match expr.nulls_first {
+            Some(true) => order_by.nulls_first(),
+            Some(false) => order_by.nulls_last(),
+            None => order_by,
+        };
+        self.query.order_by.push(order_by);
+        Ok(())
+    }
+
+    fn flip(comparison: Comparison) -> Comparison {
+        match comparison {
+            Comparison::Lt => Comparison::Gt,
+            Comparison::LtEq => Comparison::GtEq,
+            Comparison::Gt => Comparison::Lt,
+            Comparison::GtEq => Comparison::LtEq,
+            c => c,
+        }
+    }
+
+    fn parse_column(expr: &Expr) -> Result<String> {
+        match expr {
+            Expr::Identifier(ident) => Ok(ident.value.clone()),
+            Expr::CompoundIdentifier(idents) if idents.len() == 2 && idents[0].value == TABLE_NAME => Ok(idents[1].value.clone()),
+            _ => unsupported(format!("'{}', expected a column", expr)),
+        }
+    }
+
+    /// A coordinate. Numbers are accepted as their text.
+    fn parse_literal(expr: &Expr) -> Result<String> {
+        match expr {
+            Expr::Value(Value::SingleQuotedString(s)) | Expr::Value(Value::Number(s, _)) => Ok(s.clone()),
+            _ => unsupported(format!("'{}', expected a literal", expr)),
+        }
+    }
+
+    fn parse_number(expr: &Expr) -> Result<f64> {
+        match expr {
+            Expr::Value(Value::Number(n, _)) => n.parse::<f64>()
+                .or_else(|_| invalid(format!("'{}' is not a number", n))),
+            Expr::UnaryOp { op: UnaryOperator::Minus, expr } => Planner::parse_number(expr).map(|n| -n),
+            Expr::Nested(expr) => Planner::parse_number(expr),
+            _ => unsupported(format!("'{}', expected a number", expr)),
+        }
+    }
+
+    fn parse_count(expr: &Expr, clause: &str) -> Result<usize> {
+        match expr {
+            Expr::Value(Value::Number(n, _)) => n.parse::<usize>()
+                .or_else(|_| invalid(format!("{} expects a positive integer, found '{}'", clause, n))),
+            _ => unsupported(format!("'{}', {} expects a positive integer", expr, clause)),
+        }
+    }
+}
+
+/// Runs sql queries against a [`Store`].
+pub struct SqlEngine<'a> {
+    store: &'a Store,
+    engine: QueryEngine<'a>,
+}
+
+impl<'a> SqlEngine<'a> {
+    pub fn new(store: &'a Store) -> SqlEngine<'a> {
+        SqlEngine { store, engine: QueryEngine::new(store) }
+    }
+
+    pub fn execute(&self, sql: &str) -> Result<PointListAggregateResult<'a>> {
+        let query = parse_query(sql, self.store)?;
+        Ok(self.engine.execute(&query))
+    }
+}
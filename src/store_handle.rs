@@ -0,0 +1,56 @@
+use std::error::Error;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc;
+use std::thread;
+
+use crate::datastore::Store;
+
+type Job = Box<dyn FnOnce(&mut Store) + Send>;
+
+/// Owns a [`Store`] on a dedicated thread and runs closures on it, one at a time. The store is
+/// neither `Send` nor `Sync`, the servers and the integrations that need to share it between
+/// threads go through a handle instead. Handles are cheap to clone.
+#[derive(Clone)]
+pub struct StoreHandle {
+    jobs: mpsc::Sender<Job>,
+}
+
+impl StoreHandle {
+    /// Starts the thread owning the store. The store is created on this thread by `create_store`.
+    pub fn spawn<F: FnOnce() -> Store + Send + 'static>(create_store: F) -> StoreHandle {
+        let (jobs, receiver) = mpsc::channel::<Job>();
+        thread::spawn(move || {
+            let mut store = create_store();
+            for job in receiver {
+                // The store panics on the queries it does not support, keep serving the others.
+                let _ = panic::catch_unwind(AssertUnwindSafe(|| job(&mut store)));
+            }
+        });
+        StoreHandle { jobs }
+    }
+
+    /// Runs `f` on the store and waits for its result.
+    pub fn run<T, F>(&self, f: F) -> Result<T, StoreUnavailable>
+        where T: Send + 'static,
+              F: FnOnce(&mut Store) -> T + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        self.jobs.send(Box::new(move |store| {
+            let _ = sender.send(f(store));
+        })).map_err(|_| StoreUnavailable)?;
+        receiver.recv().map_err(|_| StoreUnavailable)
+    }
+}
+
+/// The store thread stopped, or the job panicked before returning a result.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoreUnavailable;
+
+impl fmt::Display for StoreUnavailable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the store failed to run the request")
+    }
+}
+
+impl Error for StoreUnavailable {}
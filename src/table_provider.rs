@@ -0,0 +1,359 @@
+use std::any::Any;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use arrow::array::{ArrayRef, StringArray};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::record_batch::RecordBatch;
+use async_trait::async_trait;
+use datafusion::datasource::datasource::TableProviderFilterPushDown;
+use datafusion::datasource::{TableProvider, TableType};
+use datafusion::error::{DataFusionError, Result};
+use datafusion::execution::context::{ExecutionConfig, ExecutionContext, ExecutionProps};
+use datafusion::logical_plan::{Column, Expr, LogicalPlan, LogicalPlanBuilder, Operator};
+use datafusion::optimizer::optimizer::OptimizerRule;
+use datafusion::optimizer::utils::optimize_children;
+use datafusion::physical_plan::aggregates::AggregateFunction;
+use datafusion::physical_plan::memory::MemoryExec;
+use datafusion::physical_plan::ExecutionPlan;
+use datafusion::scalar::ScalarValue;
+
+use crate::datastore::{SCENARIO_FIELD_NAME, Store};
+use crate::query::Query;
+use crate::query_engine::QueryEngine;
+use crate::row_iterable_provider::RowIterableProviderFactory;
+use crate::sql;
+use crate::store_handle::StoreHandle;
+
+/// Creates a DataFusion context where the store is registered as [`sql::TABLE_NAME`], see
+/// [`StoreTable`], and the aggregations it can compute are pushed down to the [`QueryEngine`],
+/// see [`AggregatePushDown`].
+pub fn create_context(store: StoreHandle) -> Result<ExecutionContext> {
+    let config = ExecutionConfig::new().add_optimizer_rule(Arc::new(AggregatePushDown));
+    let mut context = ExecutionContext::with_config(config);
+    context.register_table(sql::TABLE_NAME, Arc::new(StoreTable::new(store)?))?;
+    Ok(context)
+}
+
+/// Exposes the effective view of every scenario of a [`Store`] to DataFusion: one row per row of
+/// the store and per scenario, the `scenario` column first followed by the fields of the store.
+///
+/// Equality and `IN` filters on the scenario and on the dictionary encoded fields are evaluated
+/// by the store, the other filters are left to DataFusion. Only the projected fields are read.
+pub struct StoreTable {
+    store: StoreHandle,
+    schema: SchemaRef,
+}
+
+impl StoreTable {
+    pub fn new(store: StoreHandle) -> Result<StoreTable> {
+        let store_schema = store.run(|store| store.schema()).map_err(to_datafusion_error)?;
+        let mut fields = vec![Field::new(SCENARIO_FIELD_NAME, DataType::Utf8, false)];
+        fields.extend(store_schema.fields().iter().cloned());
+        Ok(StoreTable { store, schema: Arc::new(Schema::new(fields)) })
+    }
+
+    /// Returns true if the field can be filtered by the store: the scenario or a dictionary
+    /// encoded field.
+    fn is_filterable(&self, field: &str) -> bool {
+        self.schema.field_with_name(field).map(|f| *f.data_type() == DataType::Utf8).unwrap_or(false)
+    }
+
+    /// Translates the filters into the accepted values of each field. The values of a field
+    /// filtered more than once are intersected.
+    fn accepted_values(&self, filters: &[Expr]) -> HashMap<String, HashSet<String>> {
+        let mut accepted_values: HashMap<String, HashSet<String>> = HashMap::new();
+        for (field, values) in filters.iter().filter_map(|f| self.to_accepted_values(f)) {
+            let values: HashSet<String> = values.into_iter().collect();
+            match accepted_values.get_mut(&field) {
+                Some(existing) => existing.retain(|v| values.contains(v)),
+                None => { accepted_values.insert(field, values); }
+            }
+        }
+        accepted_values
+    }
+
+    /// Returns the field and the values accepted by `field = 'value'` or `field IN ('value', ...)`,
+    /// `None` for the other filters.
+    fn to_accepted_values(&self, filter: &Expr) -> Option<(String, Vec<String>)> {
+        let (column, values) = match filter {
+            Expr::BinaryExpr { left, op: Operator::Eq, right } => match (left.as_ref(), right.as_ref()) {
+                (Expr::Column(column), value) | (value, Expr::Column(column)) => (column, vec![to_string_literal(value)?]),
+                _ => return None,
+            },
+            Expr::InList { expr, list, negated: false } => match expr.as_ref() {
+                Expr::Column(column) => (column, list.iter().map(to_string_literal).collect::<Option<Vec<_>>>()?),
+                _ => return None,
+            },
+            _ => return None,
+        };
+        if self.is_filterable(&column.name) {
+            Some((column.name.clone(), values))
+        } else {
+            None
+        }
+    }
+
+    /// Reads the projected columns of the rows matching the accepted values, scenario by scenario.
+    fn read(store: &Store,
+            schema: &SchemaRef,
+            projection: &[usize],
+            accepted_values: &HashMap<String, HashSet<String>>,
+            limit: Option<usize>) -> RecordBatch {
+        let scenario_dictionary = store.get_dictionary(SCENARIO_FIELD_NAME);
+        let scenarios: Vec<String> = (0..scenario_dictionary.size() as u32)
+            .filter_map(|position| scenario_dictionary.read(&position))
+            .filter(|scenario| store.has_scenario(scenario))
+            .filter(|scenario| accepted_values.get(SCENARIO_FIELD_NAME).is_none_or(|values| values.contains(*scenario)))
+            .cloned()
+            .collect();
+
+        let mut accepted_positions: HashMap<String, HashSet<u32>> = HashMap::new();
+        for (field, values) in accepted_values.iter().filter(|(field, _)| *field != SCENARIO_FIELD_NAME) {
+            let dictionary = store.get_dictionary(field);
+            let positions = values.iter().filter_map(|value| dictionary.get_position(value)).copied().collect();
+            accepted_positions.insert(field.clone(), positions);
+        }
+        let provider = RowIterableProviderFactory::create(store, accepted_positions);
+
+        let limit = limit.unwrap_or(usize::MAX);
+        let mut rows_by_scenario = Vec::with_capacity(scenarios.len());
+        let mut row_count = 0;
+        for scenario in scenarios.iter() {
+            if row_count >= limit {
+                break;
+            }
+            let mut rows = Vec::new();
+            provider.get(scenario).for_each(|row| {
+                if row_count < limit {
+                    rows.push(row);
+                    row_count += 1;
+                }
+            });
+            rows_by_scenario.push((scenario, rows));
+        }
+
+        let columns: Vec<ArrayRef> = projection.iter().map(|index| {
+            let field = schema.field(*index).name();
+            let arrays: Vec<ArrayRef> = rows_by_scenario.iter().map(|(scenario, rows)| {
+                if field == SCENARIO_FIELD_NAME {
+                    Arc::new(StringArray::from(vec![scenario.as_str(); rows.len()])) as ArrayRef
+                } else {
+                    store.read_rows(scenario, field, rows)
+                }
+            }).collect();
+            concat(schema.field(*index).data_type(), &arrays)
+        }).collect();
+        let fields = projection.iter().map(|index| schema.field(*index).clone()).collect();
+        RecordBatch::try_new(Arc::new(Schema::new(fields)), columns).unwrap()
+    }
+}
+
+#[async_trait]
+impl TableProvider for StoreTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        Arc::clone(&self.schema)
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Base
+    }
+
+    async fn scan(&self,
+                  projection: &Option<Vec<usize>>,
+                  filters: &[Expr],
+                  limit: Option<usize>) -> Result<Arc<dyn ExecutionPlan>> {
+        let projection = projection.clone().unwrap_or_else(|| (0..self.schema.fields().len()).collect());
+        let accepted_values = self.accepted_values(filters);
+        let schema = Arc::clone(&self.schema);
+        let batch = self.store
+            .run(move |store| StoreTable::read(store, &schema, &projection, &accepted_values, limit))
+            .map_err(to_datafusion_error)?;
+        Ok(Arc::new(MemoryExec::try_new(&[vec![batch.clone()]], batch.schema(), None)?))
+    }
+
+    fn supports_filter_pushdown(&self, filter: &Expr) -> Result<TableProviderFilterPushDown> {
+        match self.to_accepted_values(filter) {
+            Some(_) => Ok(TableProviderFilterPushDown::Exact),
+            None => Ok(TableProviderFilterPushDown::Unsupported),
+        }
+    }
+}
+
+/// The result of a query executed by the [`QueryEngine`], see [`AggregatePushDown`].
+pub struct QueryTable {
+    store: StoreHandle,
+    query: Query,
+    schema: SchemaRef,
+}
+
+#[async_trait]
+impl TableProvider for QueryTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        Arc::clone(&self.schema)
+    }
+
+    async fn scan(&self,
+                  projection: &Option<Vec<usize>>,
+                  _filters: &[Expr],
+                  _limit: Option<usize>) -> Result<Arc<dyn ExecutionPlan>> {
+        let query = self.query.clone();
+        let batch = self.store
+            .run(move |store| QueryEngine::new(store).execute(&query).to_record_batch())
+            .map_err(to_datafusion_error)?;
+        Ok(Arc::new(MemoryExec::try_new(&[vec![batch]], Arc::clone(&self.schema), projection.clone())?))
+    }
+}
+
+/// Replaces the aggregations of a [`StoreTable`] the [`QueryEngine`] computes by a scan of their
+/// result: `SUM` of the aggregatable fields grouped by the scenario and dictionary encoded fields,
+/// with the filters pushed down to the table.
+///
+/// The scenario must be grouped or filtered on a single value since the query engine only
+/// aggregates the base scenario otherwise. A filter on a field which is not grouped must also have
+/// a single value, the field is added to the coordinates of the query and then projected out.
+pub struct AggregatePushDown;
+
+impl AggregatePushDown {
+    fn push_down(&self, plan: &LogicalPlan) -> Result<Option<LogicalPlan>> {
+        let aggregate = match plan {
+            LogicalPlan::Aggregate(aggregate) => aggregate,
+            _ => return Ok(None),
+        };
+        let scan = match aggregate.input.as_ref() {
+            LogicalPlan::TableScan(scan) if scan.limit.is_none() => scan,
+            _ => return Ok(None),
+        };
+        let table = match scan.source.as_any().downcast_ref::<StoreTable>() {
+            Some(table) => table,
+            None => return Ok(None),
+        };
+        if aggregate.group_expr.is_empty() {
+            return Ok(None);
+        }
+
+        let accepted_values = table.accepted_values(&scan.filters);
+        let mut query = Query::new();
+        let mut groups = Vec::with_capacity(aggregate.group_expr.len());
+        for expr in aggregate.group_expr.iter() {
+            let field = match expr {
+                Expr::Column(column) if table.is_filterable(&column.name) => column.name.as_str(),
+                _ => return Ok(None),
+            };
+            if query.coordinates.contains_key(field) {
+                return Ok(None);
+            }
+            match accepted_values.get(field) {
+                Some(values) => {
+                    let mut values: Vec<&str> = values.iter().map(|v| v.as_str()).collect();
+                    values.sort_unstable();
+                    query.add_coordinates(field, values);
+                }
+                None => { query.add_wildcard_coordinate(field); }
+            };
+            groups.push(field.to_string());
+        }
+        let mut filtered: Vec<(&String, &HashSet<String>)> = accepted_values.iter()
+            .filter(|(field, _)| !groups.contains(field))
+            .collect();
+        filtered.sort_unstable_by_key(|(field, _)| *field);
+        for (field, values) in filtered {
+            if values.len() != 1 {
+                return Ok(None);
+            }
+            query.add_coordinates(field, values.iter().map(|v| v.as_str()).collect());
+        }
+        if !query.coordinates.contains_key(SCENARIO_FIELD_NAME) {
+            return Ok(None);
+        }
+
+        for expr in aggregate.aggr_expr.iter() {
+            let field = match expr {
+                Expr::AggregateFunction { fun: AggregateFunction::Sum, args, distinct: false } => match args.as_slice() {
+                    [Expr::Column(column)] => column.name.as_str(),
+                    _ => return Ok(None),
+                },
+                _ => return Ok(None),
+            };
+            let aggregatable = table.schema.field_with_name(field)
+                .map(|f| matches!(f.data_type(), DataType::UInt32 | DataType::Float64))
+                .unwrap_or(false);
+            if !aggregatable || query.measures.iter().any(|m| m.field == field) {
+                return Ok(None);
+            }
+            query.add_aggregated_measure(field, "sum");
+        }
+
+        let schema = query_schema(&table.schema, &query);
+        let relation = Some(scan.table_name.clone());
+        let mut projection: Vec<Expr> = groups.iter()
+            .map(|name| Expr::Column(Column { relation: relation.clone(), name: name.clone() }))
+            .collect();
+        for (index, measure) in query.measures.iter().enumerate() {
+            let column = Expr::Column(Column { relation: relation.clone(), name: measure.alias() });
+            let name = aggregate.schema.field(groups.len() + index).name();
+            projection.push(column.alias(name));
+        }
+
+        let provider = Arc::new(QueryTable { store: table.store.clone(), query, schema });
+        let plan = LogicalPlanBuilder::scan(scan.table_name.as_str(), provider, None)?
+            .project(projection)?
+            .build()?;
+        Ok(Some(plan))
+    }
+}
+
+impl OptimizerRule for AggregatePushDown {
+    fn optimize(&self, plan: &LogicalPlan, execution_props: &ExecutionProps) -> Result<LogicalPlan> {
+        match self.push_down(plan)? {
+            Some(plan) => Ok(plan),
+            None => optimize_children(self, plan, execution_props),
+        }
+    }
+
+    fn name(&self) -> &str {
+        "aggregate_push_down"
+    }
+}
+
+/// The schema of the result of the query, see `PointListAggregateResult::schema`.
+fn query_schema(table_schema: &SchemaRef, query: &Query) -> SchemaRef {
+    let mut fields: Vec<Field> = query.coordinates.keys()
+        .map(|name| Field::new(name, DataType::Utf8, false))
+        .collect();
+    for measure in query.measures.iter() {
+        let data_type = match table_schema.field_with_name(&measure.field).unwrap().data_type() {
+            DataType::UInt32 => DataType::UInt64,
+            data_type => data_type.clone(),
+        };
+        fields.push(Field::new(&measure.alias(), data_type, false));
+    }
+    Arc::new(Schema::new(fields))
+}
+
+fn to_string_literal(expr: &Expr) -> Option<String> {
+    match expr {
+        Expr::Literal(ScalarValue::Utf8(Some(value))) => Some(value.clone()),
+        _ => None,
+    }
+}
+
+fn concat(data_type: &DataType, arrays: &[ArrayRef]) -> ArrayRef {
+    if arrays.is_empty() {
+        return arrow::array::new_empty_array(data_type);
+    }
+    let arrays: Vec<&dyn arrow::array::Array> = arrays.iter().map(|a| a.as_ref()).collect();
+    arrow::compute::concat(&arrays).unwrap()
+}
+
+fn to_datafusion_error<E: std::error::Error>(e: E) -> DataFusionError {
+    DataFusionError::Execution(e.to_string())
+}
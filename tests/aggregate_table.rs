@@ -0,0 +1,141 @@
+use std::sync::Arc;
+use arrow::array::{Float64Array, Int64Array, StringArray, UInt32Array};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatch;
+
+use rustchristmasdb::aggregate_table::AggregateTableDefinition;
+use rustchristmasdb::datastore::{CHUNK_DEFAULT_SIZE, MAIN_SCENARIO_NAME, SCENARIO_FIELD_NAME, Store};
+use rustchristmasdb::having::{Comparison, Having};
+use rustchristmasdb::order_by::OrderBy;
+use rustchristmasdb::query::Query;
+use rustchristmasdb::query_engine::QueryEngine;
+
+#[test]
+fn test_roll_up() {
+    let mut store = build_and_load();
+    let mut plain_store = build_and_load();
+    store.add_aggregate_table(by_category_and_country()).unwrap();
+    let mut by_category = AggregateTableDefinition::new("by_category", vec!["category"]);
+    by_category.add_aggregated_measure("price", "sum");
+    store.add_aggregate_table(by_category).unwrap();
+
+    let mut query = Query::new();
+    query
+        .add_wildcard_coordinate(SCENARIO_FIELD_NAME)
+        .add_wildcard_coordinate("category")
+        .add_aggregated_measure("price", "sum");
+    assert_eq!("by_category", store.find_aggregate_table(&query).unwrap().definition().name);
+    query.add_aggregated_measure("quantity", "sum");
+    assert_eq!("by_category_and_country", store.find_aggregate_table(&query).unwrap().definition().name);
+    query.add_wildcard_coordinate("product");
+    assert!(store.find_aggregate_table(&query).is_none());
+
+    let mut queries = Vec::new();
+    let mut query = Query::new();
+    query
+        .add_coordinates("country", vec!["France"])
+        .add_aggregated_measure("quantity", "sum");
+    queries.push(query);
+    let mut query = Query::new();
+    query
+        .add_coordinates(SCENARIO_FIELD_NAME, vec!["s1", MAIN_SCENARIO_NAME, "unknown"])
+        .add_wildcard_coordinate("country")
+        .add_coordinates("category", vec!["milk", "fruit"])
+        .add_aggregated_measure("price", "sum")
+        .add_aggregated_measure("quantity", "sum");
+    queries.push(query);
+    let mut query = Query::new();
+    query
+        .add_wildcard_coordinate("category")
+        // The order of the scenarios of a wildcard is the one of each store.
+        .add_coordinates(SCENARIO_FIELD_NAME, vec![MAIN_SCENARIO_NAME, "s1", "s2"])
+        .add_aggregated_measure("price", "sum")
+        .add_having(Having::new("sum(price)", Comparison::Gt, 5f64))
+        .add_order_by(OrderBy::desc("sum(price)"));
+    queries.push(query);
+    for query in queries.iter() {
+        assert!(store.find_aggregate_table(query).is_some());
+        assert_same_result(&store, &plain_store, query);
+    }
+
+    // The tables are updated by the loads.
+    let s2_batch = create_batch(&store, vec![0, 4], vec!["condiment", "milk"], vec!["Italy", "Italy"], vec![7f64, 1f64]);
+    store.load("s2", &s2_batch);
+    plain_store.load("s2", &s2_batch);
+    let s1_batch = create_batch(&store, vec![1], vec!["fruit"], vec!["France"], vec![11f64]);
+    store.load("s1", &s1_batch);
+    plain_store.load("s1", &s1_batch);
+    for query in queries.iter() {
+        assert_same_result(&store, &plain_store, query);
+    }
+}
+
+#[test]
+fn test_invalid_definitions() {
+    let mut store = build_and_load();
+    store.add_aggregate_table(by_category_and_country()).unwrap();
+    assert_eq!("name", store.add_aggregate_table(by_category_and_country()).unwrap_err()[0].field);
+
+    let mut definition = AggregateTableDefinition::new("invalid", vec!["price", SCENARIO_FIELD_NAME]);
+    definition.add_aggregated_measure("product", "sum");
+    let fields: Vec<String> = store.add_aggregate_table(definition).unwrap_err().into_iter().map(|e| e.field).collect();
+    assert_eq!(vec!["coordinates", "coordinates.price", "measures[0].field"], fields);
+    assert_eq!(1, store.aggregate_tables().len());
+}
+
+fn assert_same_result(store: &Store, plain_store: &Store, query: &Query) {
+    let expected = QueryEngine::new(plain_store).execute(query).to_record_batch();
+    assert_eq!(expected, QueryEngine::new(store).execute(query).to_record_batch(), "{:?}", query);
+}
+
+fn by_category_and_country() -> AggregateTableDefinition {
+    let mut definition = AggregateTableDefinition::new("by_category_and_country", vec!["category", "country"]);
+    definition
+        .add_aggregated_measure("price", "sum")
+        .add_aggregated_measure("quantity", "sum");
+    definition
+}
+
+fn build_and_load() -> Store {
+    let schema = Schema::new(vec![
+        Field::new("id", DataType::Int64, false),
+        Field::new("product", DataType::Utf8, false),
+        Field::new("category", DataType::Utf8, false),
+        Field::new("country", DataType::Utf8, false),
+        Field::new("price", DataType::Float64, false),
+        Field::new("quantity", DataType::UInt32, false),
+    ]);
+    let mut store = Store::new(Arc::new(schema), vec![0], CHUNK_DEFAULT_SIZE as u32);
+
+    let main_batch = RecordBatch::try_new(
+        store.schema(),
+        vec![
+            Arc::new(Int64Array::from(vec![0, 1, 2, 3, 4, 5])),
+            Arc::new(StringArray::from(vec!["syrup", "tofu", "mozzarella", "apple", "milk", "pear"])),
+            Arc::new(StringArray::from(vec!["condiment", "milk", "milk", "fruit", "milk", "fruit"])),
+            Arc::new(StringArray::from(vec!["France", "Italy", "Italy", "France", "France", "Spain"])),
+            Arc::new(Float64Array::from(vec![2f64, 8f64, 4f64, 1f64, 3f64, 5f64])),
+            Arc::new(UInt32Array::from(vec![1, 2, 3, 4, 5, 6])),
+        ],
+    ).unwrap();
+    store.load(MAIN_SCENARIO_NAME, &main_batch);
+    let s1_batch = create_batch(&store, vec![0, 1], vec!["condiment", "milk"], vec!["France", "Spain"], vec![3f64, 6f64]);
+    store.load("s1", &s1_batch);
+    store
+}
+
+fn create_batch(store: &Store, ids: Vec<i64>, categories: Vec<&str>, countries: Vec<&str>, prices: Vec<f64>) -> RecordBatch {
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Int64, false),
+        Field::new("category", DataType::Utf8, false),
+        Field::new("country", DataType::Utf8, false),
+        Field::new("price", DataType::Float64, false),
+    ]));
+    let batch = RecordBatch::try_new(schema, vec![
+        Arc::new(Int64Array::from(ids)),
+        Arc::new(StringArray::from(categories)),
+        Arc::new(StringArray::from(countries)),
+        Arc::new(Float64Array::from(prices)),
+    ]).unwrap();
+    store.prepare_batch("s", &batch).unwrap()
+}
@@ -0,0 +1,61 @@
+use std::collections::{HashMap, HashSet};
+
+use rustchristmasdb::bitmap_row_iterable_provider::{BitmapRowIterableProvider, RowIterable, RowIterableProvider};
+use rustchristmasdb::datastore::{MAIN_SCENARIO_NAME, Store};
+
+mod common;
+use common::{build_and_load, create_batch};
+
+#[test]
+fn test_scenario_moving_a_row_out_of_the_filter() {
+    let mut store = build_and_load();
+    // The tofu is no longer a milk.
+    let batch = create_batch(&store, vec![1], vec!["tofu"], vec!["condiment"], vec![8f64]);
+    store.load("s2", &batch);
+
+    let provider = milk_provider(&store);
+    assert_eq!(vec![1, 2], rows(&provider.get(MAIN_SCENARIO_NAME)));
+    let iterable = provider.get("s2");
+    assert_eq!(1, iterable.len());
+    assert_eq!(vec![2], rows(&iterable));
+}
+
+#[test]
+fn test_scenario_moving_a_row_into_the_filter() {
+    let mut store = build_and_load();
+    // The syrup becomes a milk, it comes before the base rows.
+    let batch = create_batch(&store, vec![0], vec!["syrup"], vec!["milk"], vec![2f64]);
+    store.load("s2", &batch);
+
+    let provider = milk_provider(&store);
+    let iterable = provider.get("s2");
+    assert_eq!(3, iterable.len());
+    assert_eq!(vec![0, 1, 2], rows(&iterable));
+    // s1 does not modify the category, it reads the base rows.
+    assert_eq!(vec![1, 2], rows(&provider.get("s1")));
+}
+
+#[test]
+fn test_scenario_moving_rows_out_of_and_into_the_filter() {
+    let mut store = build_and_load();
+    let batch = create_batch(&store, vec![0, 1], vec!["syrup", "tofu"], vec!["milk", "condiment"], vec![2f64, 8f64]);
+    store.load("s2", &batch);
+
+    let provider = milk_provider(&store);
+    let iterable = provider.get("s2");
+    assert_eq!(2, iterable.len());
+    assert_eq!(vec![0, 2], rows(&iterable));
+}
+
+fn milk_provider(store: &Store) -> BitmapRowIterableProvider<'_> {
+    let milk = *store.get_dictionary("category").get_position(&"milk".to_string()).unwrap();
+    BitmapRowIterableProvider::new(HashMap::from([("category".to_string(), HashSet::from([milk]))]), store)
+}
+
+/// The rows in iteration order, checked to be ascending.
+fn rows(iterable: &RowIterable) -> Vec<u32> {
+    let mut rows = Vec::new();
+    iterable.for_each(|row| rows.push(row));
+    assert!(rows.windows(2).all(|w| w[0] < w[1]), "the rows are not ascending: {:?}", rows);
+    rows
+}
@@ -0,0 +1,125 @@
+use rustchristmasdb::cli::{parse_builder, Cli, Control};
+use rustchristmasdb::datastore::{MAIN_SCENARIO_NAME, SCENARIO_FIELD_NAME};
+use rustchristmasdb::having::{Comparison, Having};
+use rustchristmasdb::order_by::OrderBy;
+use rustchristmasdb::query::Query;
+
+mod common;
+use common::build_and_load;
+
+#[test]
+fn test_parse_builder() {
+    let query = parse_builder(r#"Query::new()
+        .add_coordinates("scenario", vec!["base", "s1",])
+        .add_wildcard_coordinate("product")
+        .add_aggregated_measure("price", "sum")
+        .add_having(Having::delta("sum(price)", Comparison::GtEq, -1.5))
+        .add_order_by(OrderBy::desc("sum(price)"))
+        .set_top_n_per_group(["scenario"], 2)
+        .set_offset(1);"#).unwrap();
+
+    let mut expected = Query::new();
+    expected
+        .add_coordinates(SCENARIO_FIELD_NAME, Vec::from([MAIN_SCENARIO_NAME, "s1"]))
+        .add_wildcard_coordinate("product")
+        .add_aggregated_measure("price", "sum")
+        .add_having(Having::delta("sum(price)", Comparison::GtEq, -1.5))
+        .add_order_by(OrderBy::desc("sum(price)"))
+        .set_top_n_per_group(Vec::from([SCENARIO_FIELD_NAME]), 2)
+        .set_offset(1);
+    assert_eq!(expected, query);
+
+    assert!(parse_builder(r#"add_wildcard_coordinate("product"#).is_err());
+    assert!(parse_builder(r#"add_measure("price")"#).is_err());
+}
+
+#[test]
+fn test_execute() {
+    let mut cli = Cli::with_store(build_and_load());
+
+    let output = execute(&mut cli, &[r"\scenarios", r"\members category"]);
+    assert_eq!("base\ns1\ncondiment\nmilk\n", output);
+
+    let output = execute(&mut cli, &[
+        r"\format csv",
+        "SELECT product, sum(price) FROM store GROUP BY product ORDER BY product",
+        r#"add_coordinates("scenario", ["s1"]).add_coordinates("product", ["tofu"]).add_aggregated_measure("price", "sum")"#,
+        r"\format jsonl",
+        r#"{ "coordinates": { "category": ["milk"] }, "measures": [{ "field": "quantity", "aggregation_function": "sum" }] }"#,
+    ]);
+    assert_eq!("product,sum(price)\nmozzarella,4\nsyrup,2\ntofu,8\n\
+                scenario,product,sum(price)\ns1,tofu,6\n\
+                {\"category\":\"milk\",\"sum(quantity)\":9}\n", output);
+
+    let output = execute(&mut cli, &[r"\timing", r#"add_aggregated_measure("price", "sum")"#]);
+    assert!(output.starts_with("Timing is on.\n"), "{}", output);
+    assert!(output.contains("Time: "), "{}", output);
+
+    let output = execute(&mut cli, &[r#"\explain SELECT category, sum(price) FROM store WHERE category = 'milk' GROUP BY category"#]);
+    assert!(output.starts_with("Row provider: bitmap\n"), "{}", output);
+    assert!(output.contains("Points: 1 (at most)"), "{}", output);
+    let output = execute(&mut cli, &[r#"\analyze add_aggregated_measure("price", "sum")"#]);
+    assert!(output.contains("Points: 1\n") && output.contains("Aggregating: "), "{}", output);
+
+    let mut buffer = Vec::new();
+    assert!(cli.execute(r#"\explain add_aggregated_measure("product", "sum")"#, &mut buffer).is_err());
+    assert!(cli.execute(r"\format xml", &mut buffer).is_err());
+    assert!(cli.execute(r#"add_aggregated_measure("product", "sum")"#, &mut buffer).is_err());
+    assert_eq!(Control::Quit, cli.execute(r"\q", &mut buffer).unwrap());
+    assert!(Cli::new().execute(r"\fields", &mut buffer).is_err());
+}
+
+#[test]
+fn test_load() {
+    let mut cli = Cli::with_store(build_and_load());
+    let directory = std::env::temp_dir();
+    let unknown_key = directory.join(format!("rustchristmasdb_cli_unknown_key_{}.csv", std::process::id()));
+    std::fs::write(&unknown_key, "id,product,category,price,quantity\n1,tofu,milk,5,4\n9,rice,cereal,7,1\n").unwrap();
+    let valid = directory.join(format!("rustchristmasdb_cli_valid_{}.csv", std::process::id()));
+    std::fs::write(&valid, "id,product,category,price,quantity\n1,tofu,milk,5,4\n").unwrap();
+
+    let mut buffer = Vec::new();
+    let error = cli.execute(&format!(r"\load s2 {}", unknown_key.display()), &mut buffer).unwrap_err();
+    assert!(error.to_string().contains("key 9 does not exist in the base scenario"), "{}", error);
+    assert_eq!("base\ns1\n", execute(&mut cli, &[r"\scenarios"]));
+
+    let output = execute(&mut cli, &[&format!(r"\load s2 {}", valid.display()), r"\scenarios"]);
+    assert_eq!("base\ns1\ns2\n", output);
+    std::fs::remove_file(unknown_key).unwrap();
+    std::fs::remove_file(valid).unwrap();
+}
+
+#[test]
+fn test_open_csv_with_types() {
+    let directory = concat!(env!("CARGO_MANIFEST_DIR"), "/test/data");
+    let query = r#"add_aggregated_measure("Quantity", "sum")"#;
+    let mut cli = Cli::new();
+    let mut buffer = Vec::new();
+    cli.execute(&format!(r"\open csv {} OrderDetailID", directory), &mut buffer).unwrap();
+    // The quantities are inferred as Int64, which cannot be aggregated.
+    assert!(cli.execute(query, &mut buffer).is_err());
+
+    let output = execute(&mut cli, &[&format!(r"\open csv {} OrderDetailID --type Quantity=UInt32 --type OrderID=uint64", directory), r"\fields"]);
+    for (field, data_type) in [("Quantity", "UInt32"), ("OrderID", "UInt64")] {
+        assert!(output.lines().any(|line| line.starts_with(&format!("| {} ", field)) && line.contains(data_type)), "{}", output);
+    }
+    execute(&mut cli, &[query]);
+
+    for (options, message) in [
+        ("--type Quantity", "invalid type override 'Quantity'"),
+        ("--type Quantity=Int32", "unknown type 'Int32'"),
+        ("--type Unknown=UInt32", "cannot override the type of unknown field 'Unknown'"),
+        ("--types Quantity=UInt32", "unknown option '--types'"),
+    ] {
+        let error = cli.execute(&format!(r"\open csv {} OrderDetailID {}", directory, options), &mut buffer).unwrap_err();
+        assert!(error.to_string().contains(message), "{}", error);
+    }
+}
+
+fn execute(cli: &mut Cli, lines: &[&str]) -> String {
+    let mut buffer = Vec::new();
+    for line in lines {
+        assert_eq!(Control::Continue, cli.execute(line, &mut buffer).unwrap());
+    }
+    String::from_utf8(buffer).unwrap()
+}
@@ -0,0 +1,55 @@
+//! The store of products shared by the tests. Each test crate uses a part of it.
+#![allow(dead_code)]
+
+use std::sync::Arc;
+use arrow::array::{Float64Array, Int64Array, StringArray, UInt32Array};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatch;
+
+use rustchristmasdb::datastore::{CHUNK_DEFAULT_SIZE, MAIN_SCENARIO_NAME, Store};
+
+/// An empty store of products, keyed by id.
+pub fn create_store() -> Store {
+    let schema = Schema::new(vec![
+        Field::new("id", DataType::Int64, false),
+        Field::new("product", DataType::Utf8, false),
+        Field::new("category", DataType::Utf8, false),
+        Field::new("price", DataType::Float64, false),
+        Field::new("quantity", DataType::UInt32, false),
+    ]);
+    Store::new(Arc::new(schema), vec![0], CHUNK_DEFAULT_SIZE as u32)
+}
+
+/// The base scenario with a syrup, a tofu and a mozzarella, and s1 changing the prices of the
+/// syrup and the tofu.
+pub fn build_and_load() -> Store {
+    let mut store = create_store();
+    let main_batch = create_batch(&store, vec![0, 1, 2], vec!["syrup", "tofu", "mozzarella"], vec!["condiment", "milk", "milk"], vec![2f64, 8f64, 4f64]);
+    let s1_batch = create_batch(&store, vec![0, 1], vec!["syrup", "tofu"], vec!["condiment", "milk"], vec![3f64, 6f64]);
+    store.load(MAIN_SCENARIO_NAME, &main_batch);
+    store.load("s1", &s1_batch);
+    store
+}
+
+/// The store of [`build_and_load`] with s2 changing the prices of the syrup and the mozzarella.
+pub fn build_and_load_with_s2() -> Store {
+    let mut store = build_and_load();
+    let s2_batch = create_batch(&store, vec![0, 2], vec!["syrup", "mozzarella"], vec!["condiment", "milk"], vec![4f64, 5f64]);
+    store.load("s2", &s2_batch);
+    store
+}
+
+/// The rows of the products, the quantity of a product is its id plus 3.
+pub fn create_batch(store: &Store, ids: Vec<i64>, products: Vec<&str>, categories: Vec<&str>, prices: Vec<f64>) -> RecordBatch {
+    let quantities: Vec<u32> = ids.iter().map(|id| *id as u32 + 3).collect();
+    RecordBatch::try_new(
+        store.schema(),
+        vec![
+            Arc::new(Int64Array::from(ids)),
+            Arc::new(StringArray::from(products)),
+            Arc::new(StringArray::from(categories)),
+            Arc::new(Float64Array::from(prices)),
+            Arc::new(UInt32Array::from(quantities)),
+        ],
+    ).unwrap()
+}
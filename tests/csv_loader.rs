@@ -0,0 +1,101 @@
+use std::fs;
+use std::path::PathBuf;
+use arrow::datatypes::DataType;
+
+use rustchristmasdb::csv_loader::{CsvLoader, CsvLoaderError};
+use rustchristmasdb::datastore::{MAIN_SCENARIO_NAME, SCENARIO_FIELD_NAME};
+use rustchristmasdb::query::Query;
+use rustchristmasdb::query_engine::QueryEngine;
+
+#[test]
+fn test_infer_schema() {
+    let mut loader = CsvLoader::new();
+    loader.add_type_override("Quantity", DataType::UInt32);
+    let schema = loader.infer_schema("test/data/data_base_scenario.csv").unwrap();
+    assert_eq!(14, schema.fields().len());
+    assert_eq!(&DataType::Int64, schema.field_with_name("OrderDetailID").unwrap().data_type());
+    assert_eq!(&DataType::UInt32, schema.field_with_name("Quantity").unwrap().data_type());
+    assert_eq!(&DataType::Float64, schema.field_with_name("Price").unwrap().data_type());
+    assert_eq!(&DataType::Utf8, schema.field_with_name("OrderDate").unwrap().data_type());
+    assert_eq!(&DataType::Utf8, schema.field_with_name("CategoryName").unwrap().data_type());
+}
+
+#[test]
+fn test_create_store_from_directory() {
+    let mut loader = CsvLoader::new();
+    loader.add_type_override("Quantity", DataType::UInt32);
+    let store = loader.create_store("test/data", "OrderDetailID").unwrap();
+    assert_eq!(518, *store.row_count.borrow());
+    let scenarios: Vec<String> = loader.scenario_files("test/data").unwrap().into_iter().map(|(s, _)| s).collect();
+    assert_eq!(vec![MAIN_SCENARIO_NAME, "s05", "s10", "s25", "s50"], scenarios);
+
+    let mut query = Query::new();
+    let query = query
+        .add_wildcard_coordinate(SCENARIO_FIELD_NAME)
+        .add_aggregated_measure("Quantity", "sum");
+    let qe = QueryEngine::new(&store);
+    let result = qe.execute(query);
+    assert_eq!(5, result.size());
+}
+
+#[test]
+fn test_custom_pattern_and_delimiter() {
+    let directory = temp_directory("custom_pattern");
+    fs::write(directory.join("base.tsv"), "id\tproduct\tprice\n0\tsyrup\t2\n1\ttofu\t8.5\n").unwrap();
+    fs::write(directory.join("up.tsv"), "id\tproduct\tprice\n0\tsyrup\t3\n").unwrap();
+    fs::write(directory.join("ignored.csv"), "not,a,scenario\n").unwrap();
+
+    let mut loader = CsvLoader::new();
+    loader.set_delimiter(b'\t').set_file_pattern("{scenario}.tsv");
+    let store = loader.create_store(&directory, "id").unwrap();
+
+    let mut query = Query::new();
+    let query = query
+        .add_wildcard_coordinate(SCENARIO_FIELD_NAME)
+        .add_wildcard_coordinate("product")
+        .add_aggregated_measure("price", "sum");
+    let qe = QueryEngine::new(&store);
+    let result = qe.execute(query);
+    assert_eq!(4, result.size());
+    result.assert_aggregate(Vec::from([MAIN_SCENARIO_NAME, "syrup"]), 2f64);
+    result.assert_aggregate(Vec::from(["up", "syrup"]), 3f64);
+    result.assert_aggregate(Vec::from(["up", "tofu"]), 8.5f64);
+}
+
+#[test]
+fn test_malformed_rows_are_reported() {
+    let directory = temp_directory("malformed_rows");
+    fs::write(directory.join("data_base_scenario.csv"), "id,product,price\n0,syrup,2\n1,tofu,8\n2,mozzarella,4\n").unwrap();
+    fs::write(directory.join("data_s1_scenario.csv"), "id,product,price\n0,syrup,abc\n1,tofu\n2,mozzarella,5\n3,milk,\n").unwrap();
+
+    let loader = CsvLoader::new();
+    let error = loader.create_store(&directory, "id").err().unwrap();
+    match error {
+        CsvLoaderError::MalformedRows { path, rows } => {
+            assert!(path.ends_with("data_s1_scenario.csv"));
+            let lines: Vec<u64> = rows.iter().map(|r| r.line).collect();
+            assert_eq!(vec![2, 3, 5], lines);
+            assert!(rows[0].message.contains("'abc'"));
+            assert!(rows[0].message.contains("'price'"));
+        }
+        e => panic!("unexpected error {}", e),
+    }
+}
+
+#[test]
+fn test_unknown_keys_are_reported() {
+    let directory = temp_directory("unknown_keys");
+    fs::write(directory.join("data_base_scenario.csv"), "id,product,price\n0,syrup,2\n1,tofu,8\n").unwrap();
+    fs::write(directory.join("data_s1_scenario.csv"), "id,product,price\n1,tofu,9\n7,milk,3\n").unwrap();
+
+    let loader = CsvLoader::new();
+    let error = loader.create_store(&directory, "id").err().unwrap();
+    assert!(matches!(error, CsvLoaderError::Arrow(_)), "{}", error);
+    assert!(error.to_string().contains("key 7 does not exist in the base scenario"), "{}", error);
+}
+
+fn temp_directory(name: &str) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!("rustchristmasdb_{}_{}", name, std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    directory
+}
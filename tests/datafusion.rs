@@ -0,0 +1,149 @@
+use std::sync::Arc;
+use arrow::array::{Float64Array, Int64Array, StringArray};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatch;
+use arrow::util::pretty::pretty_format_batches;
+use datafusion::datasource::MemTable;
+use datafusion::execution::context::ExecutionContext;
+
+use rustchristmasdb::datastore::Store;
+use rustchristmasdb::store_handle::StoreHandle;
+use rustchristmasdb::table_provider::create_context;
+
+mod common;
+
+#[tokio::test]
+async fn test_effective_view() {
+    let mut context = create_context(StoreHandle::spawn(build_and_load)).unwrap();
+
+    assert_eq!(vec![
+        "+----------+----+-------+",
+        "| scenario | id | price |",
+        "+----------+----+-------+",
+        "| base     | 1  | 8     |",
+        "| base     | 2  | 4     |",
+        "| s1       | 1  | 6     |",
+        "| s1       | 2  | 4     |",
+        "| s2       | 0  | 2     |",
+        "| s2       | 1  | 8     |",
+        "| s2       | 2  | 4     |",
+        "+----------+----+-------+",
+    ], sql(&mut context, "SELECT scenario, id, price FROM store WHERE category = 'milk' ORDER BY scenario, id").await);
+
+    // The filters the store does not evaluate are applied by DataFusion.
+    assert_eq!(vec![
+        "+----------+---------+",
+        "| scenario | product |",
+        "+----------+---------+",
+        "| s1       | syrup   |",
+        "| s1       | tofu    |",
+        "+----------+---------+",
+    ], sql(&mut context, "SELECT scenario, product FROM store WHERE scenario IN ('s1', 's3') AND price < 7 AND product <> 'mozzarella' ORDER BY product").await);
+}
+
+#[tokio::test]
+async fn test_join() {
+    let mut context = create_context(StoreHandle::spawn(build_and_load)).unwrap();
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("category", DataType::Utf8, false),
+        Field::new("vat", DataType::Float64, false),
+    ]));
+    let vat = RecordBatch::try_new(Arc::clone(&schema), vec![
+        Arc::new(StringArray::from(vec!["condiment", "milk"])),
+        Arc::new(Float64Array::from(vec![0.5, 0.25])),
+    ]).unwrap();
+    context.register_table("vat", Arc::new(MemTable::try_new(schema, vec![vec![vat]]).unwrap())).unwrap();
+
+    let query = "SELECT s.scenario, SUM(s.price * v.vat) AS vat FROM store s JOIN vat v ON s.category = v.category GROUP BY s.scenario ORDER BY s.scenario";
+    assert_eq!(vec![
+        "+----------+-----+",
+        "| scenario | vat |",
+        "+----------+-----+",
+        "| base     | 4   |",
+        "| s1       | 4   |",
+        "| s2       | 3.5 |",
+        "+----------+-----+",
+    ], sql(&mut context, query).await);
+}
+
+#[tokio::test]
+async fn test_aggregate_push_down() {
+    let mut context = create_context(StoreHandle::spawn(build_and_load)).unwrap();
+
+    let query = "SELECT scenario, category, SUM(price), SUM(quantity) FROM store WHERE scenario IN ('base', 's2') GROUP BY scenario, category ORDER BY scenario, category";
+    assert!(!optimized_plan(&context, query).contains("Aggregate:"));
+    assert_eq!(vec![
+        "+----------+-----------+------------------+---------------------+",
+        "| scenario | category  | SUM(store.price) | SUM(store.quantity) |",
+        "+----------+-----------+------------------+---------------------+",
+        "| base     | condiment | 2                | 3                   |",
+        "| base     | milk      | 12               | 9                   |",
+        "| s2       | milk      | 14               | 12                  |",
+        "+----------+-----------+------------------+---------------------+",
+    ], sql(&mut context, query).await);
+
+    // The scenario and the product are not grouped but pinned by the filters.
+    let query = "SELECT category, SUM(price) AS price FROM store WHERE scenario = 's1' AND product = 'tofu' AND category IN ('milk', 'condiment') GROUP BY category";
+    assert!(!optimized_plan(&context, query).contains("Aggregate:"));
+    assert_eq!(vec![
+        "+----------+-------+",
+        "| category | price |",
+        "+----------+-------+",
+        "| milk     | 6     |",
+        "+----------+-------+",
+    ], sql(&mut context, query).await);
+}
+
+#[tokio::test]
+async fn test_aggregate_not_pushed_down() {
+    let mut context = create_context(StoreHandle::spawn(build_and_load)).unwrap();
+
+    // The query engine would only aggregate the base scenario.
+    let query = "SELECT category, SUM(price) AS price FROM store GROUP BY category ORDER BY category";
+    assert!(optimized_plan(&context, query).contains("Aggregate:"));
+    assert_eq!(vec![
+        "+-----------+-------+",
+        "| category  | price |",
+        "+-----------+-------+",
+        "| condiment | 5     |",
+        "| milk      | 36    |",
+        "+-----------+-------+",
+    ], sql(&mut context, query).await);
+
+    let query = "SELECT scenario, MAX(price) AS price FROM store WHERE scenario = 's1' GROUP BY scenario";
+    assert!(optimized_plan(&context, query).contains("Aggregate:"));
+    assert_eq!(vec![
+        "+----------+-------+",
+        "| scenario | price |",
+        "+----------+-------+",
+        "| s1       | 6     |",
+        "+----------+-------+",
+    ], sql(&mut context, query).await);
+}
+
+async fn sql(context: &mut ExecutionContext, query: &str) -> Vec<String> {
+    let batches = context.sql(query).await.unwrap().collect().await.unwrap();
+    pretty_format_batches(&batches).unwrap().to_string().lines().map(|l| l.to_string()).collect()
+}
+
+fn optimized_plan(context: &ExecutionContext, query: &str) -> String {
+    let plan = context.create_logical_plan(query).unwrap();
+    format!("{:?}", context.optimize(&plan).unwrap())
+}
+
+fn build_and_load() -> Store {
+    let mut store = common::build_and_load();
+
+    // s2 only moves the syrup to the milk category.
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Int64, false),
+        Field::new("category", DataType::Utf8, false),
+    ]));
+    let s2_batch = RecordBatch::try_new(schema, vec![
+        Arc::new(Int64Array::from(vec![0])),
+        Arc::new(StringArray::from(vec!["milk"])),
+    ]).unwrap();
+    let s2_batch = store.prepare_batch("s2", &s2_batch).unwrap();
+    store.load("s2", &s2_batch);
+    store
+}
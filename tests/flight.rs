@@ -0,0 +1,118 @@
+use std::sync::Arc;
+use arrow::array::{Float64Array, Int64Array, StringArray};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::ipc::writer::IpcWriteOptions;
+use arrow::record_batch::RecordBatch;
+use arrow_flight::flight_service_client::FlightServiceClient;
+use arrow_flight::utils::{flight_data_from_arrow_batch, flight_data_to_arrow_batch};
+use arrow_flight::{FlightData, FlightDescriptor, SchemaAsIpc, Ticket};
+use serde_json::{json, Value};
+use tokio::net::TcpListener;
+use tonic::transport::Channel;
+use tonic::{Code, Status};
+
+use rustchristmasdb::datastore::MAIN_SCENARIO_NAME;
+use rustchristmasdb::flight_server::FlightServer;
+use rustchristmasdb::store_handle::StoreHandle;
+
+mod common;
+use common::{create_batch, create_store};
+
+#[tokio::test]
+async fn test_put_and_get() {
+    let mut client = start_server().await;
+
+    let base = create_batch(&create_store(), vec![0, 1, 2], vec!["syrup", "tofu", "mozzarella"], vec!["condiment", "milk", "milk"], vec![2f64, 8f64, 4f64]);
+    assert_eq!(json!({ "scenario": MAIN_SCENARIO_NAME, "rows": 3 }), put(&mut client, MAIN_SCENARIO_NAME, &base).await.unwrap());
+
+    // Only the key and the modified field, with a type the store casts.
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Int64, false),
+        Field::new("price", DataType::Int64, false),
+    ]));
+    let s1 = RecordBatch::try_new(schema, vec![
+        Arc::new(Int64Array::from(vec![1, 2])),
+        Arc::new(Int64Array::from(vec![6, 5])),
+    ]).unwrap();
+    assert_eq!(json!({ "scenario": "s1", "rows": 2 }), put(&mut client, "s1", &s1).await.unwrap());
+    assert_eq!(Code::AlreadyExists, put(&mut client, "s1", &s1).await.unwrap_err().code());
+
+    let query = json!({
+        "coordinates": { "scenario": null, "category": ["milk"] },
+        "measures": [{ "field": "price", "aggregation_function": "sum" }],
+        "order_by": [{ "field": "scenario" }]
+    });
+    let batch = get(&mut client, &query.to_string()).await.unwrap();
+    let scenarios = batch.column(0).as_any().downcast_ref::<StringArray>().unwrap();
+    let prices = batch.column(2).as_any().downcast_ref::<Float64Array>().unwrap();
+    assert_eq!(vec![Some(MAIN_SCENARIO_NAME), Some("s1")], scenarios.iter().collect::<Vec<_>>());
+    assert_eq!(&[12f64, 11f64], prices.values());
+
+    let batch = get(&mut client, "SELECT scenario, category, sum(quantity) FROM store WHERE scenario = 's1' GROUP BY scenario, category").await.unwrap();
+    assert_eq!(2, batch.num_rows());
+}
+
+#[tokio::test]
+async fn test_errors() {
+    let mut client = start_server().await;
+    let base = create_batch(&create_store(), vec![0], vec!["syrup"], vec!["condiment"], vec![2f64]);
+
+    assert_eq!(Code::InvalidArgument, put(&mut client, "s1", &base).await.unwrap_err().code());
+    put(&mut client, MAIN_SCENARIO_NAME, &base).await.unwrap();
+
+    let status = get(&mut client, r#"{ "coordinates": { "color": null }, "measures": [] }"#).await.unwrap_err();
+    assert_eq!(Code::InvalidArgument, status.code());
+    assert_eq!("coordinates.color: unknown field", status.message());
+    assert_eq!(Code::InvalidArgument, get(&mut client, "SELECT * FROM store").await.unwrap_err().code());
+
+    let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Int64, false)]));
+    let unknown_key = RecordBatch::try_new(schema, vec![Arc::new(Int64Array::from(vec![7]))]).unwrap();
+    let status = put(&mut client, "s2", &unknown_key).await.unwrap_err();
+    assert_eq!("Invalid argument error: key 7 does not exist in the base scenario", status.message());
+}
+
+#[tokio::test]
+async fn test_shared_handle() {
+    let store = StoreHandle::spawn(create_store);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(FlightServer::with_handle(store.clone()).serve_with_listener(listener));
+    let mut client = FlightServiceClient::connect(format!("http://{}", address)).await.unwrap();
+
+    let base = create_batch(&create_store(), vec![0, 1], vec!["syrup", "tofu"], vec!["condiment", "milk"], vec![2f64, 8f64]);
+    put(&mut client, MAIN_SCENARIO_NAME, &base).await.unwrap();
+    assert_eq!(Ok(2), store.run(|store| *store.row_count.borrow()));
+
+    // A request that panics fails alone, the store keeps serving the next ones.
+    assert!(store.run(|_| panic!("unsupported request")).is_err());
+    let batch = get(&mut client, "SELECT category, sum(price) FROM store GROUP BY category").await.unwrap();
+    assert_eq!(2, batch.num_rows());
+}
+
+async fn start_server() -> FlightServiceClient<Channel> {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(FlightServer::new(create_store).serve_with_listener(listener));
+    FlightServiceClient::connect(format!("http://{}", address)).await.unwrap()
+}
+
+async fn get(client: &mut FlightServiceClient<Channel>, ticket: &str) -> Result<RecordBatch, Status> {
+    let mut stream = client.do_get(Ticket { ticket: ticket.as_bytes().to_vec() }).await?.into_inner();
+    let schema = Arc::new(Schema::try_from(&stream.message().await?.unwrap()).unwrap());
+    let mut batches = Vec::new();
+    while let Some(data) = stream.message().await? {
+        batches.push(flight_data_to_arrow_batch(&data, Arc::clone(&schema), &[]).unwrap());
+    }
+    Ok(RecordBatch::concat(&schema, &batches).unwrap())
+}
+
+async fn put(client: &mut FlightServiceClient<Channel>, scenario: &str, batch: &RecordBatch) -> Result<Value, Status> {
+    let options = IpcWriteOptions::default();
+    let mut schema: FlightData = SchemaAsIpc::new(&batch.schema(), &options).into();
+    schema.flight_descriptor = Some(FlightDescriptor::new_path(vec![scenario.to_string()]));
+    let (_, data) = flight_data_from_arrow_batch(batch, &options);
+
+    let mut results = client.do_put(futures::stream::iter(vec![schema, data])).await?.into_inner();
+    let result = results.message().await?.unwrap();
+    Ok(serde_json::from_slice(&result.app_metadata).unwrap())
+}
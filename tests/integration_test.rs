@@ -1,11 +1,16 @@
 use std::sync::Arc;
-use arrow::array::{Float64Array, Int64Array, StringArray, UInt32Array};
-use arrow::datatypes::{DataType, Field, Schema};
+use arrow::array::{Float64Array, Int64Array, StringArray, UInt32Array, UInt64Array};
+use arrow::compute::sum;
+use arrow::datatypes::{DataType, Field, Float64Type, Schema, UInt64Type};
 use arrow::record_batch::RecordBatch;
 
 use rustchristmasdb::datastore::{CHUNK_DEFAULT_SIZE, MAIN_SCENARIO_NAME, SCENARIO_FIELD_NAME, Store};
+use rustchristmasdb::having::{Comparison, Having};
+use rustchristmasdb::order_by::OrderBy;
+use rustchristmasdb::point_list_aggregates_result::AggregateValue;
 use rustchristmasdb::query::Query;
 use rustchristmasdb::query_engine::QueryEngine;
+use rustchristmasdb::row_mapping::RowMappingSharing;
 
 #[test]
 fn test_wildcard_without_scenario_in_the_query() {
@@ -139,6 +144,422 @@ fn test_wildcard_scenario_and_list_coordinates_on_other_coordinate() {
     result.assert_aggregate(Vec::from(["s2", "tofu"]), 8f64);
 }
 
+#[test]
+fn test_list_coordinates_on_a_field_modified_by_a_scenario() {
+    let mut store = build_and_load();
+    // s3 moves the tofu to the condiments and the mozzarella to a new category.
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Int64, false),
+        Field::new("category", DataType::Utf8, false),
+    ]));
+    let s3_batch = RecordBatch::try_new(schema, vec![
+        Arc::new(Int64Array::from(vec![1, 2])),
+        Arc::new(StringArray::from(vec!["condiment", "cheese"])),
+    ]).unwrap();
+    let s3_batch = store.prepare_batch("s3", &s3_batch).unwrap();
+    store.load("s3", &s3_batch);
+
+    let mut query = Query::new();
+    let query = query
+        .add_wildcard_coordinate(SCENARIO_FIELD_NAME)
+        .add_coordinates("category", Vec::from(["condiment"]))
+        .add_wildcard_coordinate("product")
+        .add_aggregated_measure("price", "sum");
+    let result = QueryEngine::new(&store).execute(query);
+    assert_eq!(5, result.size());
+    result.assert_aggregate(Vec::from([MAIN_SCENARIO_NAME, "condiment", "syrup"]), 2f64);
+    result.assert_aggregate(Vec::from(["s1", "condiment", "syrup"]), 3f64);
+    result.assert_aggregate(Vec::from(["s2", "condiment", "syrup"]), 4f64);
+    result.assert_aggregate(Vec::from(["s3", "condiment", "syrup"]), 2f64);
+    result.assert_aggregate(Vec::from(["s3", "condiment", "tofu"]), 8f64);
+
+    let mut query = Query::new();
+    let query = query
+        .add_coordinates(SCENARIO_FIELD_NAME, Vec::from([MAIN_SCENARIO_NAME, "s3"]))
+        .add_coordinates("category", Vec::from(["milk", "cheese"]))
+        .add_coordinates("product", Vec::from(["mozzarella", "tofu"]))
+        .add_aggregated_measure("price", "sum");
+    let result = QueryEngine::new(&store).execute(query);
+    assert_eq!(3, result.size());
+    result.assert_aggregate(Vec::from([MAIN_SCENARIO_NAME, "milk", "tofu"]), 8f64);
+    result.assert_aggregate(Vec::from([MAIN_SCENARIO_NAME, "milk", "mozzarella"]), 4f64);
+    result.assert_aggregate(Vec::from(["s3", "cheese", "mozzarella"]), 4f64);
+}
+
+#[test]
+fn test_shared_row_mappings() {
+    let mut store = build_and_load();
+    // Each field modifies a different row.
+    let batch = RecordBatch::try_new(store.schema(), vec![
+        Arc::new(Int64Array::from(vec![0, 1, 2])),
+        Arc::new(StringArray::from(vec!["syrup", "tofu", "mozzarella"])),
+        Arc::new(StringArray::from(vec!["condiment", "condiment", "milk"])),
+        Arc::new(Float64Array::from(vec![7f64, 8f64, 4f64])),
+        Arc::new(UInt32Array::from(vec![5, 3, 9])),
+    ]).unwrap();
+    store.load("per_field", &batch);
+    store.load_with_sharing("per_scenario", &batch, &RowMappingSharing::PerScenario);
+    store.load_with_sharing("per_group", &batch, &RowMappingSharing::PerGroup(vec![vec!["price".to_string(), "quantity".to_string()]]));
+
+    let mapping = |scenario: &str, field: &str| Arc::clone(store.row_mapping_by_field_by_scenario.get(scenario).unwrap().get(field).unwrap());
+    assert!(!Arc::ptr_eq(&mapping("per_field", "price"), &mapping("per_field", "quantity")));
+    assert!(Arc::ptr_eq(&mapping("per_scenario", "price"), &mapping("per_scenario", "category")));
+    assert!(Arc::ptr_eq(&mapping("per_scenario", "price"), &mapping("per_scenario", "quantity")));
+    assert!(Arc::ptr_eq(&mapping("per_group", "price"), &mapping("per_group", "quantity")));
+    assert!(!Arc::ptr_eq(&mapping("per_group", "price"), &mapping("per_group", "category")));
+    // The unmodified fields are read from the base scenario.
+    assert!(store.row_mapping_by_field_by_scenario.get("per_scenario").unwrap().get("product").is_none());
+    assert_eq!(Some(&[0u32].into_iter().collect()), store.get_overridden_rows("per_scenario", "price"));
+
+    let expected = store.get_scenario_batch("per_field");
+    assert_eq!(expected, store.get_scenario_batch("per_scenario"));
+    assert_eq!(expected, store.get_scenario_batch("per_group"));
+
+    let mut query = Query::new();
+    let query = query
+        .add_coordinates(SCENARIO_FIELD_NAME, Vec::from(["per_field", "per_scenario", "per_group"]))
+        .add_coordinates("category", Vec::from(["milk"]))
+        .add_aggregated_measure("price", "sum")
+        .add_aggregated_measure("quantity", "sum");
+    let result = QueryEngine::new(&store).execute(query);
+    assert_eq!(3, result.size());
+    for scenario in ["per_field", "per_scenario", "per_group"] {
+        assert_eq!(Some(4f64), result.get_aggregate::<Float64Type>(&[scenario, "milk"], "sum(price)"));
+        assert_eq!(Some(9), result.get_aggregate::<UInt64Type>(&[scenario, "milk"], "sum(quantity)"));
+    }
+}
+
+#[test]
+fn test_aggregate_more_rows_than_a_batch() {
+    let categories = ["condiment", "milk", "cheese"];
+    let mut store = Store::new(build_and_load().schema(), vec![0], CHUNK_DEFAULT_SIZE as u32);
+    let ids: Vec<i64> = (0..5000).collect();
+    let batch = RecordBatch::try_new(store.schema(), vec![
+        Arc::new(Int64Array::from(ids.clone())),
+        Arc::new(StringArray::from(ids.iter().map(|id| format!("p{}", id)).collect::<Vec<String>>())),
+        Arc::new(StringArray::from(ids.iter().map(|id| categories[*id as usize % 3]).collect::<Vec<&str>>())),
+        Arc::new(Float64Array::from(ids.iter().map(|id| *id as f64).collect::<Vec<f64>>())),
+        Arc::new(UInt32Array::from(ids.iter().map(|id| *id as u32).collect::<Vec<u32>>())),
+    ]).unwrap();
+    store.load(MAIN_SCENARIO_NAME, &batch);
+    // s1 moves every 10th product to the milk and doubles its price.
+    let modified: Vec<i64> = ids.iter().copied().filter(|id| id % 10 == 0).collect();
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Int64, false),
+        Field::new("category", DataType::Utf8, false),
+        Field::new("price", DataType::Float64, false),
+    ]));
+    let s1_batch = RecordBatch::try_new(schema, vec![
+        Arc::new(Int64Array::from(modified.clone())),
+        Arc::new(StringArray::from(vec!["milk"; modified.len()])),
+        Arc::new(Float64Array::from(modified.iter().map(|id| *id as f64 * 2f64).collect::<Vec<f64>>())),
+    ]).unwrap();
+    let s1_batch = store.prepare_batch("s1", &s1_batch).unwrap();
+    store.load("s1", &s1_batch);
+
+    let mut query = Query::new();
+    let query = query
+        .add_wildcard_coordinate(SCENARIO_FIELD_NAME)
+        .add_wildcard_coordinate("category")
+        .add_aggregated_measure("price", "sum")
+        .add_aggregated_measure("quantity", "sum");
+    let result = QueryEngine::new(&store).execute(query);
+    assert_eq!(6, result.size());
+    for (scenario, s1) in [(MAIN_SCENARIO_NAME, false), ("s1", true)] {
+        for (index, category) in categories.iter().enumerate() {
+            let rows = ids.iter().filter(|id| if s1 && **id % 10 == 0 { *category == "milk" } else { **id as usize % 3 == index });
+            let price: f64 = rows.clone().map(|id| if s1 && id % 10 == 0 { *id as f64 * 2f64 } else { *id as f64 }).sum();
+            let quantity: u64 = rows.map(|id| *id as u64).sum();
+            assert_eq!(Some(price), result.get_aggregate::<Float64Type>(&[scenario, category], "sum(price)"));
+            assert_eq!(Some(quantity), result.get_aggregate::<UInt64Type>(&[scenario, category], "sum(quantity)"));
+        }
+    }
+}
+
+#[test]
+fn test_load_a_scenario_again() {
+    let mut store = build_and_load();
+    // s1 now moves the mozzarella to the condiments and no longer modifies the price of the syrup.
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Int64, false),
+        Field::new("category", DataType::Utf8, false),
+    ]));
+    let s1_batch = RecordBatch::try_new(schema, vec![
+        Arc::new(Int64Array::from(vec![2])),
+        Arc::new(StringArray::from(vec!["condiment"])),
+    ]).unwrap();
+    let s1_batch = store.prepare_batch("s1", &s1_batch).unwrap();
+    let version = store.version();
+    store.load("s1", &s1_batch);
+
+    let changes = store.changes_since(version);
+    assert_eq!(1, changes.len());
+    assert_eq!("s1", changes[0].scenario);
+    assert!(!changes[0].listing_changed);
+    let mut fields: Vec<&String> = changes[0].fields.iter().collect();
+    fields.sort();
+    assert_eq!(vec!["category", "price"], fields);
+
+    let mut query = Query::new();
+    let query = query
+        .add_coordinates(SCENARIO_FIELD_NAME, Vec::from(["s1"]))
+        .add_wildcard_coordinate("category")
+        .add_aggregated_measure("price", "sum");
+    let result = QueryEngine::new(&store).execute(query);
+    assert_eq!(2, result.size());
+    result.assert_aggregate(Vec::from(["s1", "condiment"]), 6f64);
+    result.assert_aggregate(Vec::from(["s1", "milk"]), 8f64);
+}
+
+#[test]
+fn test_append() {
+    let mut store = build_and_load();
+    let base_batch = RecordBatch::try_new(store.schema(), vec![
+        Arc::new(Int64Array::from(vec![3])),
+        Arc::new(StringArray::from(vec!["cheddar"])),
+        Arc::new(StringArray::from(vec!["cheese"])),
+        Arc::new(Float64Array::from(vec![10f64])),
+        Arc::new(UInt32Array::from(vec![1])),
+    ]).unwrap();
+    store.append(MAIN_SCENARIO_NAME, &base_batch);
+    assert_eq!(4, *store.row_count.borrow());
+
+    // s1 keeps its prices and moves the syrup to the milks and the mozzarella to the condiments.
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Int64, false),
+        Field::new("category", DataType::Utf8, false),
+    ]));
+    let s1_batch = RecordBatch::try_new(schema, vec![
+        Arc::new(Int64Array::from(vec![2, 0])),
+        Arc::new(StringArray::from(vec!["condiment", "milk"])),
+    ]).unwrap();
+    store.append("s1", &store.prepare_batch("s1", &s1_batch).unwrap());
+    // The syrup is given again, its last price wins.
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Int64, false),
+        Field::new("price", DataType::Float64, false),
+    ]));
+    let s1_batch = RecordBatch::try_new(schema, vec![
+        Arc::new(Int64Array::from(vec![0])),
+        Arc::new(Float64Array::from(vec![7f64])),
+    ]).unwrap();
+    store.append("s1", &store.prepare_batch("s1", &s1_batch).unwrap());
+
+    let mut query = Query::new();
+    let query = query
+        .add_coordinates(SCENARIO_FIELD_NAME, Vec::from([MAIN_SCENARIO_NAME, "s1"]))
+        .add_wildcard_coordinate("category")
+        .add_aggregated_measure("price", "sum");
+    let result = QueryEngine::new(&store).execute(query);
+    assert_eq!(6, result.size());
+    result.assert_aggregate(Vec::from([MAIN_SCENARIO_NAME, "condiment"]), 2f64);
+    result.assert_aggregate(Vec::from([MAIN_SCENARIO_NAME, "milk"]), 12f64);
+    result.assert_aggregate(Vec::from([MAIN_SCENARIO_NAME, "cheese"]), 10f64);
+    result.assert_aggregate(Vec::from(["s1", "condiment"]), 4f64);
+    result.assert_aggregate(Vec::from(["s1", "milk"]), 13f64);
+    result.assert_aggregate(Vec::from(["s1", "cheese"]), 10f64);
+
+    // The appended rows are in the bitmap index.
+    let mut query = Query::new();
+    let query = query
+        .add_coordinates("category", Vec::from(["cheese"]))
+        .add_aggregated_measure("price", "sum");
+    let result = QueryEngine::new(&store).execute(query);
+    assert_eq!(1, result.size());
+    result.assert_aggregate(Vec::from(["cheese"]), 10f64);
+}
+
+#[test]
+fn test_to_record_batch() {
+    let store = build_and_load();
+
+    let mut query = Query::new();
+    let query = query
+        .add_wildcard_coordinate(SCENARIO_FIELD_NAME)
+        .add_wildcard_coordinate("product")
+        .add_aggregated_measure("price", "sum")
+        .add_aggregated_measure("quantity", "sum");
+
+    let qe = QueryEngine::new(&store);
+    let result = qe.execute(query);
+    let batch = RecordBatch::from(&result);
+    assert_eq!(result.schema(), batch.schema());
+    assert_eq!(9, batch.num_rows());
+    let schema = batch.schema();
+    let names: Vec<&str> = schema.fields().iter().map(|f| f.name().as_str()).collect();
+    assert_eq!(vec![SCENARIO_FIELD_NAME, "product", "sum(price)", "sum(quantity)"], names);
+    assert_eq!(&DataType::Utf8, schema.field(1).data_type());
+    assert_eq!(&DataType::Float64, schema.field(2).data_type());
+    assert_eq!(&DataType::UInt64, schema.field(3).data_type());
+
+    let scenarios = batch.column(0).as_any().downcast_ref::<StringArray>().unwrap();
+    let products = batch.column(1).as_any().downcast_ref::<StringArray>().unwrap();
+    let prices = batch.column(2).as_any().downcast_ref::<Float64Array>().unwrap();
+    let row = (0..batch.num_rows())
+        .find(|r| scenarios.value(*r) == "s2" && products.value(*r) == "mozzarella")
+        .unwrap();
+    assert_eq!(5f64, prices.value(row));
+    assert_eq!(Some(44f64), sum(prices));
+    assert_eq!(Some(36), sum(batch.column(3).as_any().downcast_ref::<UInt64Array>().unwrap()));
+}
+
+#[test]
+fn test_accessors() {
+    let store = build_and_load();
+
+    let mut query = Query::new();
+    let query = query
+        .add_wildcard_coordinate(SCENARIO_FIELD_NAME)
+        .add_coordinates("product", Vec::from(["syrup", "tofu"]))
+        .add_aggregated_measure("price", "sum")
+        .add_aggregated_measure("quantity", "sum");
+
+    let qe = QueryEngine::new(&store);
+    let result = qe.execute(query);
+    assert_eq!(&[SCENARIO_FIELD_NAME, "product"], result.point_names());
+    assert_eq!(&["sum(price)", "sum(quantity)"], result.aggregate_names());
+
+    assert_eq!(Some(3f64), result.get_aggregate::<Float64Type>(&["s1", "syrup"], "sum(price)"));
+    assert_eq!(Some(3u64), result.get_aggregate::<UInt64Type>(&["s1", "tofu"], "sum(quantity)"));
+    assert_eq!(None, result.get_aggregate::<UInt64Type>(&["s1", "tofu"], "sum(price)"));
+    assert_eq!(None, result.get_aggregate::<Float64Type>(&["s1", "mozzarella"], "sum(price)"));
+    assert_eq!(None, result.get_aggregate::<Float64Type>(&["s3", "tofu"], "sum(price)"));
+    assert_eq!(None, result.get_aggregate::<Float64Type>(&["s1", "tofu"], "avg(price)"));
+    assert_eq!(
+        Some(vec![AggregateValue::Float64(8f64), AggregateValue::UInt64(3)]),
+        result.get_values(&["s2", "tofu"]));
+
+    let prices = result.get_typed_column::<Float64Type>("sum(price)").unwrap();
+    assert_eq!(6, prices.len());
+    assert_eq!(31f64, prices.iter().sum::<f64>());
+    assert_eq!(6, result.get_column("sum(quantity)").unwrap().len());
+    assert!(result.get_column("sum(id)").is_none());
+
+    let mut rows = 0;
+    for (coordinates, values) in result.iter() {
+        assert_eq!(Some(values), result.get_values(&coordinates));
+        rows += 1;
+    }
+    assert_eq!(6, rows);
+}
+
+#[test]
+fn test_order_by() {
+    let store = build_and_load();
+
+    let mut query = Query::new();
+    let query = query
+        .add_wildcard_coordinate(SCENARIO_FIELD_NAME)
+        .add_wildcard_coordinate("product")
+        .add_aggregated_measure("price", "sum")
+        .add_order_by(OrderBy::desc("sum(price)"))
+        .add_order_by(OrderBy::asc("product"))
+        .add_order_by(OrderBy::asc(SCENARIO_FIELD_NAME));
+
+    let qe = QueryEngine::new(&store);
+    let result = qe.execute(query);
+    let rows: Vec<(Vec<&str>, f64)> = result.iter().map(|(c, v)| (c, v[0].as_f64())).collect();
+    assert_eq!(vec![
+        (vec![MAIN_SCENARIO_NAME, "tofu"], 8f64),
+        (vec!["s2", "tofu"], 8f64),
+        (vec!["s1", "tofu"], 6f64),
+        (vec!["s2", "mozzarella"], 5f64),
+        (vec![MAIN_SCENARIO_NAME, "mozzarella"], 4f64),
+        (vec!["s1", "mozzarella"], 4f64),
+        (vec!["s2", "syrup"], 4f64),
+        (vec!["s1", "syrup"], 3f64),
+        (vec![MAIN_SCENARIO_NAME, "syrup"], 2f64),
+    ], rows);
+}
+
+#[test]
+fn test_order_by_coordinate_with_limit_and_offset() {
+    let store = build_and_load();
+
+    let mut query = Query::new();
+    let query = query
+        .add_coordinates(SCENARIO_FIELD_NAME, Vec::from(["s2"]))
+        .add_wildcard_coordinate("product")
+        .add_aggregated_measure("price", "sum")
+        .add_order_by(OrderBy::desc("product"))
+        .set_offset(1)
+        .set_limit(1);
+
+    let qe = QueryEngine::new(&store);
+    let result = qe.execute(query);
+    assert_eq!(1, result.size());
+    result.assert_aggregate(Vec::from(["s2", "syrup"]), 4f64);
+    assert_eq!(None, result.get_row(&["s2", "tofu"]));
+    assert_eq!(None, result.get_row(&["s2", "mozzarella"]));
+    assert_eq!(1, result.to_record_batch().num_rows());
+}
+
+#[test]
+fn test_top_n_per_group() {
+    let store = build_and_load();
+
+    let mut query = Query::new();
+    let query = query
+        .add_wildcard_coordinate(SCENARIO_FIELD_NAME)
+        .add_wildcard_coordinate("product")
+        .add_aggregated_measure("price", "sum")
+        .add_order_by(OrderBy::asc(SCENARIO_FIELD_NAME))
+        .add_order_by(OrderBy::desc("sum(price)"))
+        .set_top_n_per_group(vec![SCENARIO_FIELD_NAME], 2);
+
+    let qe = QueryEngine::new(&store);
+    let result = qe.execute(query);
+    let rows: Vec<(Vec<&str>, f64)> = result.iter().map(|(c, v)| (c, v[0].as_f64())).collect();
+    assert_eq!(vec![
+        (vec![MAIN_SCENARIO_NAME, "tofu"], 8f64),
+        (vec![MAIN_SCENARIO_NAME, "mozzarella"], 4f64),
+        (vec!["s1", "tofu"], 6f64),
+        (vec!["s1", "mozzarella"], 4f64),
+        (vec!["s2", "tofu"], 8f64),
+        (vec!["s2", "mozzarella"], 5f64),
+    ], rows);
+}
+
+#[test]
+fn test_having() {
+    let store = build_and_load();
+
+    let mut query = Query::new();
+    let query = query
+        .add_wildcard_coordinate(SCENARIO_FIELD_NAME)
+        .add_wildcard_coordinate("product")
+        .add_aggregated_measure("price", "sum")
+        .add_having(Having::new("sum(price)", Comparison::Gt, 4f64));
+
+    let qe = QueryEngine::new(&store);
+    let result = qe.execute(query);
+    assert_eq!(4, result.size());
+    result.assert_aggregate(Vec::from([MAIN_SCENARIO_NAME, "tofu"]), 8f64);
+    result.assert_aggregate(Vec::from(["s1", "tofu"]), 6f64);
+    result.assert_aggregate(Vec::from(["s2", "tofu"]), 8f64);
+    result.assert_aggregate(Vec::from(["s2", "mozzarella"]), 5f64);
+}
+
+#[test]
+fn test_having_delta() {
+    let store = build_and_load();
+
+    let mut query = Query::new();
+    let query = query
+        .add_wildcard_coordinate(SCENARIO_FIELD_NAME)
+        .add_wildcard_coordinate("product")
+        .add_aggregated_measure("price", "sum")
+        .add_having(Having::delta("sum(price)", Comparison::NotEq, 0f64))
+        .add_having(Having::new("sum(price)", Comparison::GtEq, 4f64));
+
+    let qe = QueryEngine::new(&store);
+    let result = qe.execute(query);
+    assert_eq!(3, result.size());
+    result.assert_aggregate(Vec::from(["s1", "tofu"]), 6f64);
+    result.assert_aggregate(Vec::from(["s2", "syrup"]), 4f64);
+    result.assert_aggregate(Vec::from(["s2", "mozzarella"]), 5f64);
+}
+
 fn build_and_load() -> Store {
     let schema = Schema::new(vec![
         Field::new("id", DataType::Int64, false),
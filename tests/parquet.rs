@@ -0,0 +1,195 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use arrow::array::{ArrayRef, Float64Array, Int32Array, Int64Array, StringArray};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::{ArrowReader, ArrowWriter, ParquetFileArrowReader};
+use parquet::file::properties::WriterProperties;
+use parquet::file::reader::{FileReader, SerializedFileReader};
+
+use rustchristmasdb::datastore::{MAIN_SCENARIO_NAME, SCENARIO_FIELD_NAME};
+use rustchristmasdb::parquet_exporter::ParquetExporter;
+use rustchristmasdb::parquet_loader::ParquetLoader;
+use rustchristmasdb::query::Query;
+use rustchristmasdb::query_engine::QueryEngine;
+
+mod common;
+use common::{build_and_load_with_s2, create_store};
+
+#[test]
+fn test_export_and_load_scenarios() {
+    let store = build_and_load_with_s2();
+    let exporter = ParquetExporter::new();
+    let base_path = temp_path("export_and_load_base");
+    let s1_path = temp_path("export_and_load_s1");
+    exporter.write_scenario(&store, MAIN_SCENARIO_NAME, &base_path).unwrap();
+    exporter.write_scenario(&store, "s1", &s1_path).unwrap();
+
+    let mut loaded = create_store();
+    let mut loader = ParquetLoader::new(&mut loaded);
+    loader.load(MAIN_SCENARIO_NAME, &base_path).unwrap();
+    loader.load("s1", &s1_path).unwrap();
+
+    let mut query = Query::new();
+    let query = query
+        .add_wildcard_coordinate(SCENARIO_FIELD_NAME)
+        .add_wildcard_coordinate("product")
+        .add_aggregated_measure("price", "sum");
+    let qe = QueryEngine::new(&loaded);
+    let result = qe.execute(query);
+    assert_eq!(6, result.size());
+    result.assert_aggregate(Vec::from([MAIN_SCENARIO_NAME, "syrup"]), 2f64);
+    result.assert_aggregate(Vec::from([MAIN_SCENARIO_NAME, "tofu"]), 8f64);
+    result.assert_aggregate(Vec::from(["s1", "syrup"]), 3f64);
+    result.assert_aggregate(Vec::from(["s1", "tofu"]), 6f64);
+    result.assert_aggregate(Vec::from(["s1", "mozzarella"]), 4f64);
+}
+
+#[test]
+fn test_load_projected_columns() {
+    let store = build_and_load_with_s2();
+    let base_path = temp_path("projected_columns_base");
+    let s2_path = temp_path("projected_columns_s2");
+    let exporter = ParquetExporter::new();
+    exporter.write_scenario(&store, MAIN_SCENARIO_NAME, &base_path).unwrap();
+    exporter.write_scenario(&store, "s2", &s2_path).unwrap();
+
+    let mut loaded = create_store();
+    let mut loader = ParquetLoader::new(&mut loaded);
+    assert!(loader.load_columns(MAIN_SCENARIO_NAME, &base_path, &["id", "price"]).is_err());
+    assert!(loader.load_columns("s2", &s2_path, &["price"]).is_err());
+    loader.load(MAIN_SCENARIO_NAME, &base_path).unwrap();
+    loader.load_columns("s2", &s2_path, &["id", "price"]).unwrap();
+
+    assert!(loaded.vector_by_field_by_scenario.get("s2").unwrap().contains_key("price"));
+    assert!(!loaded.vector_by_field_by_scenario.get("s2").unwrap().contains_key("quantity"));
+
+    let mut query = Query::new();
+    let query = query
+        .add_coordinates(SCENARIO_FIELD_NAME, Vec::from(["s2"]))
+        .add_wildcard_coordinate("product")
+        .add_aggregated_measure("price", "sum");
+    let qe = QueryEngine::new(&loaded);
+    let result = qe.execute(query);
+    result.assert_aggregate(Vec::from(["s2", "syrup"]), 4f64);
+    result.assert_aggregate(Vec::from(["s2", "tofu"]), 8f64);
+    result.assert_aggregate(Vec::from(["s2", "mozzarella"]), 5f64);
+}
+
+#[test]
+fn test_load_row_groups() {
+    let store = build_and_load_with_s2();
+    let base_path = temp_path("row_groups_base");
+    write_batch(&base_path, &store.get_scenario_batch(MAIN_SCENARIO_NAME), 2);
+    let file_reader = SerializedFileReader::new(std::fs::File::open(&base_path).unwrap()).unwrap();
+    assert_eq!(2, file_reader.metadata().num_row_groups());
+    let loaded = ParquetLoader::create_store(&base_path, "id").unwrap();
+    assert_eq!(store.get_scenario_batch(MAIN_SCENARIO_NAME), loaded.get_scenario_batch(MAIN_SCENARIO_NAME));
+
+    // The syrup is given twice, in two row groups: the last price wins.
+    let mut loaded = create_store();
+    let mut loader = ParquetLoader::new(&mut loaded);
+    loader.load(MAIN_SCENARIO_NAME, &base_path).unwrap();
+    let s1_path = temp_path("row_groups_s1");
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Int64, false),
+        Field::new("price", DataType::Float64, false),
+    ]));
+    let s1_batch = RecordBatch::try_new(schema, vec![
+        Arc::new(Int64Array::from(vec![0, 1, 0])),
+        Arc::new(Float64Array::from(vec![3f64, 6f64, 5f64])),
+    ]).unwrap();
+    write_batch(&s1_path, &s1_batch, 1);
+    loader.load_columns("s1", &s1_path, &["id", "price"]).unwrap();
+
+    let mut query = Query::new();
+    let query = query
+        .add_coordinates(SCENARIO_FIELD_NAME, Vec::from(["s1"]))
+        .add_wildcard_coordinate("category")
+        .add_aggregated_measure("price", "sum");
+    let result = QueryEngine::new(&loaded).execute(query);
+    result.assert_aggregate(Vec::from(["s1", "condiment"]), 5f64);
+    result.assert_aggregate(Vec::from(["s1", "milk"]), 10f64);
+}
+
+#[test]
+fn test_export_result() {
+    let store = build_and_load_with_s2();
+    let mut query = Query::new();
+    let query = query
+        .add_wildcard_coordinate(SCENARIO_FIELD_NAME)
+        .add_aggregated_measure("price", "sum");
+    let qe = QueryEngine::new(&store);
+    let result = qe.execute(query);
+    let path = temp_path("export_result");
+    ParquetExporter::new().write_result(&result, &path).unwrap();
+
+    let file_reader = SerializedFileReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+    let mut arrow_reader = ParquetFileArrowReader::new(Arc::new(file_reader));
+    let batch = arrow_reader.get_record_reader(1024).unwrap().next().unwrap().unwrap();
+    assert_eq!(3, batch.num_rows());
+    assert_eq!(SCENARIO_FIELD_NAME, batch.schema().field(0).name());
+    assert_eq!("sum(price)", batch.schema().field(1).name());
+
+    let scenarios = batch.column(0).as_any().downcast_ref::<StringArray>().unwrap();
+    let prices = batch.column(1).as_any().downcast_ref::<Float64Array>().unwrap();
+    for row in 0..batch.num_rows() {
+        let expected = match scenarios.value(row) {
+            MAIN_SCENARIO_NAME => 14f64,
+            "s1" => 13f64,
+            "s2" => 17f64,
+            s => panic!("unexpected scenario {}", s),
+        };
+        assert_eq!(expected, prices.value(row));
+    }
+}
+
+#[test]
+fn test_invalid_files_are_reported() {
+    let int32_path = temp_path("invalid_int32");
+    write(&int32_path, vec![
+        Field::new("id", DataType::Int64, false),
+        Field::new("quantity", DataType::Int32, false),
+    ], vec![Arc::new(Int64Array::from(vec![0])), Arc::new(Int32Array::from(vec![1]))]);
+    let error = ParquetLoader::create_store(&int32_path, "id").err().unwrap();
+    assert!(error.to_string().contains("type Int32 of field 'quantity' is not supported"), "{}", error);
+
+    let nullable_path = temp_path("invalid_nullable");
+    let nullable_fields = vec![
+        Field::new("id", DataType::Int64, false),
+        Field::new("price", DataType::Float64, true),
+    ];
+    write(&nullable_path, nullable_fields.clone(), vec![Arc::new(Int64Array::from(vec![0, 1])), Arc::new(Float64Array::from(vec![Some(2f64), None]))]);
+    let error = ParquetLoader::create_store(&nullable_path, "id").err().unwrap();
+    assert!(error.to_string().contains("field 'price' contains null values"), "{}", error);
+
+    // A nullable column without null is accepted.
+    write(&nullable_path, nullable_fields.clone(), vec![Arc::new(Int64Array::from(vec![0, 1])), Arc::new(Float64Array::from(vec![2f64, 3f64]))]);
+    let mut store = ParquetLoader::create_store(&nullable_path, "id").unwrap();
+    assert!(!store.schema().field(1).is_nullable());
+
+    let unknown_key_path = temp_path("invalid_unknown_key");
+    write(&unknown_key_path, nullable_fields, vec![Arc::new(Int64Array::from(vec![1, 7])), Arc::new(Float64Array::from(vec![2f64, 3f64]))]);
+    let error = ParquetLoader::new(&mut store).load("s1", &unknown_key_path).err().unwrap();
+    assert!(error.to_string().contains("key 7 does not exist in the base scenario"), "{}", error);
+    assert!(!store.has_scenario("s1"));
+}
+
+fn write(path: &PathBuf, fields: Vec<Field>, columns: Vec<ArrayRef>) {
+    let batch = RecordBatch::try_new(Arc::new(Schema::new(fields)), columns).unwrap();
+    write_batch(path, &batch, batch.num_rows().max(1));
+}
+
+fn write_batch(path: &PathBuf, batch: &RecordBatch, row_group_size: usize) {
+    let properties = WriterProperties::builder()
+        .set_dictionary_enabled(false)
+        .set_max_row_group_size(row_group_size)
+        .build();
+    let mut writer = ArrowWriter::try_new(std::fs::File::create(path).unwrap(), batch.schema(), Some(properties)).unwrap();
+    writer.write(batch).unwrap();
+    writer.close().unwrap();
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("rustchristmasdb_{}_{}.parquet", name, std::process::id()))
+}
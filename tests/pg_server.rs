@@ -0,0 +1,86 @@
+use std::net::TcpListener;
+use std::thread;
+use postgres::{Client, NoTls, SimpleQueryMessage};
+
+use rustchristmasdb::datastore::MAIN_SCENARIO_NAME;
+use rustchristmasdb::pg_server::PgServer;
+
+mod common;
+use common::build_and_load;
+
+#[test]
+fn test_simple_query() {
+    let mut client = connect();
+
+    let messages = client.simple_query("SET application_name = 'test'; SELECT scenario, category, sum(price) FROM store WHERE category = 'milk' GROUP BY scenario, category ORDER BY scenario").unwrap();
+    let rows: Vec<(&str, &str, &str)> = messages.iter().filter_map(|m| match m {
+        SimpleQueryMessage::Row(row) => Some((row.get(0).unwrap(), row.get(1).unwrap(), row.get(2).unwrap())),
+        _ => None,
+    }).collect();
+    assert_eq!(vec![(MAIN_SCENARIO_NAME, "milk", "12"), ("s1", "milk", "10")], rows);
+    assert!(matches!(messages.last(), Some(SimpleQueryMessage::CommandComplete(2))));
+
+    let error = client.simple_query("SELECT * FROM store").unwrap_err();
+    assert_eq!("0A000", error.code().unwrap().code());
+    let error = client.simple_query("DELETE FROM store").unwrap_err();
+    assert_eq!("0A000", error.code().unwrap().code());
+    // The connection is still usable after an error.
+    let messages = client.simple_query("SELECT 1").unwrap();
+    assert!(matches!(&messages[1], SimpleQueryMessage::Row(row) if row.get(0) == Some("1")));
+}
+
+#[test]
+fn test_extended_query() {
+    let mut client = connect();
+
+    let rows = client.query(
+        "SELECT scenario, sum(price), sum(quantity) FROM store WHERE scenario = $1 GROUP BY scenario HAVING sum(price) > $2",
+        &[&"s1", &"5"]).unwrap();
+    assert_eq!(1, rows.len());
+    assert_eq!("s1", rows[0].get::<_, &str>("scenario"));
+    assert_eq!(13.0, rows[0].get::<_, f64>("sum(price)"));
+    assert_eq!(12, rows[0].get::<_, i64>("sum(quantity)"));
+
+    let statement = client.prepare("SELECT category, sum(price) FROM store GROUP BY category ORDER BY category").unwrap();
+    let columns: Vec<(&str, String)> = statement.columns().iter().map(|c| (c.name(), c.type_().name().to_string())).collect();
+    assert_eq!(vec![("category", "text".to_string()), ("sum(price)", "float8".to_string())], columns);
+    let rows = client.query(&statement, &[]).unwrap();
+    assert_eq!(vec!["condiment", "milk"], rows.iter().map(|r| r.get::<_, &str>(0)).collect::<Vec<_>>());
+
+    let error = client.query("SELECT color, sum(price) FROM store GROUP BY color", &[]).unwrap_err();
+    assert_eq!("42000", error.code().unwrap().code());
+    assert_eq!(1, client.query("SELECT 1", &[]).unwrap().len());
+}
+
+#[test]
+fn test_catalog() {
+    let mut client = connect();
+
+    let rows = client.query("SELECT column_name, data_type FROM information_schema.columns WHERE table_name = 'store' ORDER BY ordinal_position", &[]).unwrap();
+    let columns: Vec<(&str, &str)> = rows.iter().map(|r| (r.get(0), r.get(1))).collect();
+    assert_eq!(vec![
+        ("scenario", "text"),
+        ("id", "bigint"),
+        ("product", "text"),
+        ("category", "text"),
+        ("price", "double precision"),
+        ("quantity", "bigint"),
+    ], columns);
+
+    let rows = client.query("SELECT table_name FROM information_schema.tables ORDER BY table_name", &[]).unwrap();
+    assert_eq!(vec!["scenarios", "store"], rows.iter().map(|r| r.get::<_, &str>(0)).collect::<Vec<_>>());
+    let rows = client.query("SELECT * FROM scenarios", &[]).unwrap();
+    assert_eq!(vec![MAIN_SCENARIO_NAME, "s1"], rows.iter().map(|r| r.get::<_, &str>("scenario")).collect::<Vec<_>>());
+
+    let row = client.query_one("SELECT current_database(), version()", &[]).unwrap();
+    assert_eq!("rustchristmasdb", row.get::<_, &str>(0));
+    let row = client.query_one("SHOW server_version", &[]).unwrap();
+    assert_eq!("14.0", row.get::<_, &str>(0));
+}
+
+fn connect() -> Client {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    thread::spawn(move || PgServer::new(build_and_load).serve_listener(listener));
+    Client::connect(&format!("host=127.0.0.1 port={} user=test dbname=rustchristmasdb", port), NoTls).unwrap()
+}
@@ -0,0 +1,80 @@
+use rustchristmasdb::point_dictionary::PointDictionary;
+
+#[test]
+fn test_packed_in_64_bits() {
+    let mut dictionary = PointDictionary::with_cardinalities(&[3, 5]);
+    assert!(format!("{:?}", dictionary).contains("Packed64("), "{:?}", dictionary);
+    assert_round_trips(&mut dictionary, &grid(&[3, 5]));
+
+    // A coordinate wider than its bits, a point of the wrong length.
+    assert_eq!(None, dictionary.get_position(&[4, 0]));
+    assert_eq!(None, dictionary.get_position(&[0, 8]));
+    assert_eq!(None, dictionary.get_position(&[0]));
+    assert_eq!(None, dictionary.get_position(&[0, 0, 0]));
+}
+
+#[test]
+fn test_single_value_coordinate() {
+    // A coordinate with a single value takes no bit.
+    let mut dictionary = PointDictionary::with_cardinalities(&[1, 4]);
+    assert_round_trips(&mut dictionary, &grid(&[1, 4]));
+    assert_eq!(None, dictionary.get_position(&[1, 3]));
+}
+
+#[test]
+fn test_packed_in_128_bits() {
+    // 3 x 32 bits.
+    let cardinalities = [u32::MAX, u32::MAX, u32::MAX];
+    let mut dictionary = PointDictionary::with_cardinalities(&cardinalities);
+    assert!(format!("{:?}", dictionary).contains("Packed128("), "{:?}", dictionary);
+    let points = vec![
+        vec![u32::MAX - 1, 0, 7],
+        vec![0, u32::MAX - 1, 7],
+        vec![7, 0, u32::MAX - 1],
+        vec![0, 0, 0],
+    ];
+    assert_round_trips(&mut dictionary, &points);
+
+    // 4 x 20 bits.
+    let mut dictionary = PointDictionary::with_cardinalities(&[1 << 20; 4]);
+    assert!(format!("{:?}", dictionary).contains("Packed128("), "{:?}", dictionary);
+    assert_round_trips(&mut dictionary, &[vec![(1 << 20) - 1, 0, 1, 2], vec![0, 1, 2, (1 << 20) - 1]]);
+    assert_eq!(None, dictionary.get_position(&[1 << 20, 0, 0, 0]));
+    assert_eq!(None, dictionary.get_position(&[0, 0, 0]));
+}
+
+#[test]
+fn test_wider_than_128_bits() {
+    // 5 x 32 bits.
+    let mut dictionary = PointDictionary::with_cardinalities(&[u32::MAX; 5]);
+    assert!(format!("{:?}", dictionary).contains("Wide("), "{:?}", dictionary);
+    let points: Vec<Vec<u32>> = (0..5)
+        .map(|index| (0..5).map(|coordinate| if coordinate == index { u32::MAX - 1 } else { coordinate }).collect())
+        .collect();
+    assert_round_trips(&mut dictionary, &points);
+    assert_eq!(None, dictionary.get_position(&[1, 1, 1, 1, 1]));
+    assert_eq!(None, dictionary.get_position(&[0, 1, 2, 3]));
+}
+
+/// Maps the distinct points twice and checks that they keep their positions and can be read back.
+fn assert_round_trips(dictionary: &mut PointDictionary, points: &[Vec<u32>]) {
+    for (position, point) in points.iter().enumerate() {
+        assert_eq!(position as u32, dictionary.map(point), "{:?}", point);
+    }
+    for (position, point) in points.iter().enumerate() {
+        assert_eq!(position as u32, dictionary.map(point), "{:?}", point);
+        assert_eq!(Some(&(position as u32)), dictionary.get_position(point), "{:?}", point);
+        assert_eq!(Some(point.as_slice()), dictionary.read(&(position as u32)));
+    }
+    assert_eq!(points.len(), dictionary.size());
+    assert_eq!(None, dictionary.read(&(points.len() as u32)));
+}
+
+/// All the points of the given cardinalities.
+fn grid(cardinalities: &[u32]) -> Vec<Vec<u32>> {
+    cardinalities.iter().fold(vec![Vec::new()], |points, cardinality| {
+        points.iter()
+            .flat_map(|point| (0..*cardinality).map(move |coordinate| [point.as_slice(), &[coordinate]].concat()))
+            .collect()
+    })
+}
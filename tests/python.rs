@@ -0,0 +1,100 @@
+#![cfg(feature = "python")]
+
+use std::sync::Arc;
+use arrow::array::{Float32Array, Float64Array, Int32Array, Int64Array, StringArray};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatch;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+use rustchristmasdb::datastore::{MAIN_SCENARIO_NAME, SCENARIO_FIELD_NAME};
+use rustchristmasdb::python::{to_batches, PyStore};
+use rustchristmasdb::query::Query;
+use rustchristmasdb::query_engine::QueryEngine;
+
+#[test]
+fn test_load_batches() {
+    // The errors are python exceptions.
+    pyo3::prepare_freethreaded_python();
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Int32, false),
+        Field::new("category", DataType::Utf8, false),
+        Field::new("price", DataType::Float32, false),
+    ]));
+    let mut store = PyStore::create(&schema, "id").unwrap();
+    assert_eq!(&DataType::Int64, store.store().schema().field(0).data_type());
+    assert_eq!(&DataType::Float64, store.store().schema().field(2).data_type());
+
+    // The batches are cast to the types of the store and loaded one after the other.
+    let batches = vec![
+        create_batch(&schema, vec![0, 1], vec!["condiment", "milk"], vec![2f32, 8f32]),
+        create_batch(&schema, vec![2], vec!["milk"], vec![4f32]),
+    ];
+    assert_eq!(3, store.load_batches(MAIN_SCENARIO_NAME, &schema, &batches).unwrap());
+    assert!(store.load_batches(MAIN_SCENARIO_NAME, &schema, &batches).is_err());
+    assert_eq!(3, *store.store().row_count.borrow());
+
+    let s1 = vec![create_batch(&schema, vec![0], vec!["milk"], vec![3f32])];
+    assert_eq!(1, store.load_batches("s1", &schema, &s1).unwrap());
+    // A scenario loaded again is replaced.
+    let s1 = vec![create_batch(&schema, vec![2], vec!["condiment"], vec![4f32])];
+    assert_eq!(1, store.load_batches("s1", &schema, &s1).unwrap());
+    // An invalid batch loads nothing.
+    let s2 = vec![
+        create_batch(&schema, vec![1], vec!["milk"], vec![6f32]),
+        create_batch(&schema, vec![7], vec!["milk"], vec![1f32]),
+    ];
+    let error = store.load_batches("s2", &schema, &s2).err().unwrap();
+    assert!(error.to_string().contains("key 7 does not exist in the base scenario"), "{}", error);
+    assert!(!store.store().has_scenario("s2"));
+
+    let mut query = Query::new();
+    let query = query
+        .add_wildcard_coordinate(SCENARIO_FIELD_NAME)
+        .add_wildcard_coordinate("category")
+        .add_aggregated_measure("price", "sum");
+    let result = QueryEngine::new(store.store()).execute(query);
+    assert_eq!(4, result.size());
+    result.assert_aggregate(Vec::from([MAIN_SCENARIO_NAME, "condiment"]), 2f64);
+    result.assert_aggregate(Vec::from([MAIN_SCENARIO_NAME, "milk"]), 12f64);
+    result.assert_aggregate(Vec::from(["s1", "condiment"]), 6f64);
+    result.assert_aggregate(Vec::from(["s1", "milk"]), 8f64);
+}
+
+#[test]
+fn test_to_batches() {
+    pyo3::prepare_freethreaded_python();
+    Python::with_gil(|py| {
+        let pyarrow = match py.import("pyarrow") {
+            Ok(pyarrow) => pyarrow,
+            Err(_) => {
+                eprintln!("pyarrow is not installed, skipping test_to_batches");
+                return;
+            }
+        };
+        let columns = PyDict::new(py);
+        columns.set_item("id", vec![0i64, 1, 2]).unwrap();
+        columns.set_item("price", vec![2f64, 8f64, 4f64]).unwrap();
+        let table = pyarrow.getattr("table").unwrap().call1((columns,)).unwrap();
+
+        let (schema, batches) = to_batches(py, table).unwrap();
+        assert_eq!(vec!["id", "price"], schema.fields().iter().map(|f| f.name().as_str()).collect::<Vec<&str>>());
+        assert_eq!(3, batches.iter().map(|batch| batch.num_rows()).sum::<usize>());
+
+        let batch = table.call_method0("combine_chunks").unwrap().call_method0("to_batches").unwrap().get_item(0).unwrap();
+        let (_, batches) = to_batches(py, batch).unwrap();
+        assert_eq!(1, batches.len());
+        let prices = batches[0].column(1).as_any().downcast_ref::<Float64Array>().unwrap();
+        assert_eq!(&[2f64, 8f64, 4f64], prices.values());
+        let ids = batches[0].column(0).as_any().downcast_ref::<Int64Array>().unwrap();
+        assert_eq!(&[0i64, 1, 2], ids.values());
+    });
+}
+
+fn create_batch(schema: &Arc<Schema>, ids: Vec<i32>, categories: Vec<&str>, prices: Vec<f32>) -> RecordBatch {
+    RecordBatch::try_new(Arc::clone(schema), vec![
+        Arc::new(Int32Array::from(ids)),
+        Arc::new(StringArray::from(categories)),
+        Arc::new(Float32Array::from(prices)),
+    ]).unwrap()
+}
@@ -0,0 +1,155 @@
+use std::collections::HashSet;
+use arrow::datatypes::Float64Type;
+
+use rustchristmasdb::datastore::{MAIN_SCENARIO_NAME, SCENARIO_FIELD_NAME};
+use rustchristmasdb::order_by::OrderBy;
+use rustchristmasdb::query::Query;
+use rustchristmasdb::query_cache::{CacheStats, QueryCache};
+use rustchristmasdb::query_engine::QueryEngine;
+
+mod common;
+use common::{build_and_load, create_batch};
+
+#[test]
+fn test_hits_and_misses() {
+    let store = build_and_load();
+    let mut cache = QueryCache::new();
+
+    let result = cache.execute(&store, &price_by_category(vec!["milk", "condiment"]));
+    assert_eq!(Some(12f64), result.get_aggregate::<Float64Type>(&[MAIN_SCENARIO_NAME, "milk"], "sum(price)"));
+    // The order of the filtered values does not matter.
+    let result = cache.execute(&store, &price_by_category(vec!["condiment", "milk", "milk"]));
+    assert_eq!(Some(12f64), result.get_aggregate::<Float64Type>(&[MAIN_SCENARIO_NAME, "milk"], "sum(price)"));
+    assert_eq!(Some(10f64), result.get_aggregate::<Float64Type>(&["s1", "milk"], "sum(price)"));
+    cache.execute(&store, &price_by_category(vec!["milk"]));
+
+    let stats = cache.stats();
+    assert_eq!((1, 2, 2), (stats.hits, stats.misses, stats.entries));
+    assert!(stats.bytes > 0);
+}
+
+#[test]
+fn test_store_version() {
+    let mut store = build_and_load();
+    assert_eq!(2, store.version());
+    let mut cache = QueryCache::new();
+    let query = price_by_category(vec!["milk"]);
+    cache.execute(&store, &query);
+
+    let batch = create_batch(&store, vec![1], vec!["tofu"], vec!["milk"], vec![1f64]);
+    store.load("s2", &batch);
+    assert_eq!(3, store.version());
+    let result = cache.execute(&store, &query);
+    assert_eq!(Some(5f64), result.get_aggregate::<Float64Type>(&["s2", "milk"], "sum(price)"));
+    let stats = cache.stats();
+    assert_eq!((0, 2, 1, 1), (stats.hits, stats.misses, stats.invalidations, stats.entries));
+}
+
+#[test]
+fn test_size_limits() {
+    let store = build_and_load();
+    let mut cache = QueryCache::new();
+    cache.set_max_entries(2);
+    let milk = price_by_category(vec!["milk"]);
+    let condiment = price_by_category(vec!["condiment"]);
+    let both = price_by_category(vec!["milk", "condiment"]);
+
+    cache.execute(&store, &milk);
+    cache.execute(&store, &condiment);
+    cache.execute(&store, &milk);
+    // The condiment is the least recently used.
+    cache.execute(&store, &both);
+    cache.execute(&store, &milk);
+    assert_eq!(CacheStats { hits: 2, misses: 3, evictions: 1, invalidations: 0, refreshes: 0, entries: 2, bytes: cache.stats().bytes }, cache.stats());
+    cache.execute(&store, &condiment);
+    assert_eq!(4, cache.stats().misses);
+
+    cache.set_max_bytes(0);
+    assert_eq!((0, 0), (cache.stats().entries, cache.stats().bytes));
+    cache.execute(&store, &milk);
+    assert_eq!(0, cache.stats().entries);
+}
+
+#[test]
+fn test_invalidate_the_results_depending_on_a_change() {
+    let mut store = build_and_load();
+    let s2_batch = create_batch(&store, vec![1], vec!["tofu"], vec!["milk"], vec![1f64]);
+    store.load("s2", &s2_batch);
+    let mut cache = QueryCache::new();
+    let base_only = quantity_by_scenario(None);
+    let s1_only = quantity_by_scenario(Some(vec!["s1"]));
+    let all_prices = price_by_category(vec!["milk"]);
+    let all_quantities = quantity_by_scenario(Some(vec![MAIN_SCENARIO_NAME, "s1", "s2"]));
+    for query in [&base_only, &s1_only, &all_prices, &all_quantities] {
+        cache.execute(&store, query);
+    }
+
+    // s2 only modifies prices: the quantities and the queries not reading s2 are still valid.
+    let s2_batch = create_batch(&store, vec![1, 2], vec!["tofu", "mozzarella"], vec!["milk", "milk"], vec![1f64, 2f64]);
+    store.load("s2", &s2_batch);
+    assert_eq!(1, store.changes_since(store.version() - 1).len());
+    assert_eq!(["price"].iter().map(|f| f.to_string()).collect::<HashSet<String>>(), store.changes_since(store.version() - 1)[0].fields);
+    for query in [&base_only, &s1_only, &all_quantities] {
+        cache.execute(&store, query);
+    }
+    assert_eq!((3, 0, 0), (cache.stats().hits, cache.stats().invalidations, cache.stats().refreshes));
+
+    // The prices of s2 are refreshed, the other scenarios are kept.
+    let result = cache.execute(&store, &all_prices);
+    assert_eq!(1, cache.stats().refreshes);
+    assert_eq!(Some(3f64), result.get_aggregate::<Float64Type>(&["s2", "milk"], "sum(price)"));
+    assert_eq!(Some(10f64), result.get_aggregate::<Float64Type>(&["s1", "milk"], "sum(price)"));
+    assert_eq!(Some(12f64), result.get_aggregate::<Float64Type>(&[MAIN_SCENARIO_NAME, "milk"], "sum(price)"));
+    assert_eq!(3, result.size());
+
+    // A new scenario changes the results of the queries on all the scenarios.
+    let s3_batch = create_batch(&store, vec![0], vec!["syrup"], vec!["condiment"], vec![9f64]);
+    store.load("s3", &s3_batch);
+    let result = cache.execute(&store, &all_prices);
+    assert_eq!((1, 4), (cache.stats().invalidations, result.size()));
+    cache.execute(&store, &s1_only);
+    assert_eq!(5, cache.stats().hits);
+}
+
+#[test]
+fn test_refresh() {
+    let mut store = build_and_load();
+    let mut query = price_by_category(vec!["milk", "condiment"]);
+    query.add_order_by(OrderBy::desc("sum(price)"));
+    let previous = QueryEngine::new(&store).execute(&query).detach();
+
+    let s1_batch = create_batch(&store, vec![0, 1], vec!["syrup", "tofu"], vec!["milk", "milk"], vec![30f64, 6f64]);
+    store.load("s1", &s1_batch);
+    let engine = QueryEngine::new(&store);
+    let refreshed = engine.refresh(&previous, &query, &["s1".to_string()]);
+    assert_eq!(engine.execute(&query).to_record_batch(), refreshed.to_record_batch());
+    assert_eq!(vec!["s1", "milk"], refreshed.get_coordinates(0));
+    assert_eq!(None, refreshed.get_row(&["s1", "condiment"]));
+
+    // s1 no longer modifies any value, the wildcard does not list it anymore.
+    let previous = refreshed.detach();
+    let s1_batch = create_batch(&store, vec![0, 1], vec!["syrup", "tofu"], vec!["condiment", "milk"], vec![2f64, 8f64]);
+    store.load("s1", &s1_batch);
+    let engine = QueryEngine::new(&store);
+    let refreshed = engine.refresh(&previous, &query, &["s1".to_string()]);
+    assert_eq!(engine.execute(&query).to_record_batch(), refreshed.to_record_batch());
+    assert_eq!(None, refreshed.get_row(&["s1", "milk"]));
+}
+
+fn quantity_by_scenario(scenarios: Option<Vec<&str>>) -> Query {
+    let mut query = Query::new();
+    if let Some(scenarios) = scenarios {
+        query.add_coordinates(SCENARIO_FIELD_NAME, scenarios);
+    }
+    query.add_aggregated_measure("quantity", "sum");
+    query
+}
+
+fn price_by_category(categories: Vec<&str>) -> Query {
+    let mut query = Query::new();
+    query
+        .add_wildcard_coordinate(SCENARIO_FIELD_NAME)
+        .add_coordinates("category", categories)
+        .add_aggregated_measure("price", "sum");
+    query
+}
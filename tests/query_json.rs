@@ -0,0 +1,85 @@
+use rustchristmasdb::datastore::{MAIN_SCENARIO_NAME, SCENARIO_FIELD_NAME};
+use rustchristmasdb::having::{Comparison, Having};
+use rustchristmasdb::order_by::OrderBy;
+use rustchristmasdb::query::{Query, ValidationError};
+use rustchristmasdb::query_engine::QueryEngine;
+
+mod common;
+use common::build_and_load_with_s2;
+
+#[test]
+fn test_deserialize() {
+    let json = r#"{
+        "coordinates": { "scenario": null, "category": ["milk"] },
+        "measures": [{ "field": "price", "aggregation_function": "sum" }],
+        "having": [{ "alias": "sum(price)", "comparison": "gt_eq", "value": 10.0 }],
+        "order_by": [{ "field": "sum(price)", "descending": true }],
+        "limit": 2
+    }"#;
+    let query: Query = serde_json::from_str(json).unwrap();
+
+    let mut expected = Query::new();
+    expected
+        .add_wildcard_coordinate(SCENARIO_FIELD_NAME)
+        .add_coordinates("category", Vec::from(["milk"]))
+        .add_aggregated_measure("price", "sum")
+        .add_having(Having::new("sum(price)", Comparison::GtEq, 10f64))
+        .add_order_by(OrderBy { field: "sum(price)".to_string(), descending: true, nulls_first: false })
+        .set_limit(2);
+    assert_eq!(expected, query);
+
+    let store = build_and_load_with_s2();
+    assert_eq!(Ok(()), query.validate(&store));
+    let qe = QueryEngine::new(&store);
+    let result = qe.execute(&query);
+    let points: Vec<Vec<&str>> = result.iter().map(|(coordinates, _)| coordinates).collect();
+    assert_eq!(vec![vec!["s2", "milk"], vec![MAIN_SCENARIO_NAME, "milk"]], points);
+}
+
+#[test]
+fn test_serialize_round_trip() {
+    let mut query = Query::new();
+    query
+        .add_coordinates(SCENARIO_FIELD_NAME, Vec::from([MAIN_SCENARIO_NAME, "s1"]))
+        .add_wildcard_coordinate("product")
+        .add_aggregated_measure("quantity", "sum")
+        .add_having(Having::delta("sum(quantity)", Comparison::NotEq, 0f64))
+        .set_top_n_per_group(Vec::from([SCENARIO_FIELD_NAME]), 1)
+        .set_offset(1);
+
+    let json = serde_json::to_string(&query).unwrap();
+    assert!(json.starts_with(r#"{"coordinates":{"scenario":["base","s1"],"product":null},"measures":[{"field":"quantity","aggregation_function":"sum"}]"#), "{}", json);
+    assert_eq!(query, serde_json::from_str::<Query>(&json).unwrap());
+}
+
+#[test]
+fn test_validate() {
+    let store = build_and_load_with_s2();
+    let json = r#"{
+        "coordinates": { "scenario": ["s1"], "price": null, "color": null },
+        "measures": [
+            { "field": "quantity", "aggregation_function": "sum" },
+            { "field": "product", "aggregation_function": "sum" },
+            { "field": "weight", "aggregation_function": "avg" },
+            { "field": "quantity", "aggregation_function": "sum" }
+        ],
+        "having": [{ "alias": "sum(price)", "comparison": "gt", "value": 1.0, "delta": true }],
+        "order_by": [{ "field": "category" }],
+        "top_n": { "group_by": ["product"], "n": 1 }
+    }"#;
+    let query: Query = serde_json::from_str(json).unwrap();
+
+    let errors: Vec<String> = query.validate(&store).unwrap_err().iter().map(ValidationError::to_string).collect();
+    assert_eq!(vec![
+        "coordinates.price: Float64 fields cannot be coordinates, only Utf8 fields can",
+        "coordinates.color: unknown field",
+        "measures[1].field: 'product' cannot be aggregated, Utf8 is not supported",
+        "measures[2].field: unknown field 'weight'",
+        "measures[2].aggregation_function: unknown function 'avg', expected one of [\"sum\"]",
+        "measures[3]: 'sum(quantity)' is already a measure of the query",
+        "having[0].alias: 'sum(price)' is not a measure of the query",
+        "having[0].delta: a delta requires the scenario coordinate and the base scenario in the query",
+        "order_by[0].field: 'category' is neither a coordinate nor a measure of the query",
+        "top_n.group_by[0]: 'product' is not a coordinate of the query",
+    ], errors);
+}
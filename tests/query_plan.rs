@@ -0,0 +1,93 @@
+use rustchristmasdb::aggregate_table::AggregateTableDefinition;
+use rustchristmasdb::datastore::{MAIN_SCENARIO_NAME, SCENARIO_FIELD_NAME, Store};
+use rustchristmasdb::query::Query;
+use rustchristmasdb::query_engine::QueryEngine;
+use rustchristmasdb::query_plan::{RowProvider, ScenarioPlan};
+
+mod common;
+use common::create_batch;
+
+#[test]
+fn test_explain() {
+    let store = build_and_load();
+    let engine = QueryEngine::new(&store);
+
+    let plan = engine.explain(&price_by_category(Some(vec!["milk"])));
+    assert_eq!(RowProvider::Bitmap, plan.row_provider);
+    assert_eq!(vec![
+        scenario_plan(MAIN_SCENARIO_NAME, vec![], 2),
+        scenario_plan("s1", vec!["price"], 2),
+        // The syrup is a milk in s2.
+        scenario_plan("s2", vec!["category", "price"], 3),
+    ], plan.scenarios);
+    // One category in three scenarios.
+    assert_eq!(3, plan.point_count);
+    assert!(!plan.is_analyzed());
+    assert_eq!((None, None), (plan.grouping, plan.aggregating));
+    let text = plan.to_string();
+    assert!(text.starts_with("Row provider: bitmap\n"), "{}", text);
+    assert!(text.contains("Points: 3 (at most)"), "{}", text);
+    assert!(!text.contains("Grouping"), "{}", text);
+
+    let plan = engine.explain(&price_by_category(None));
+    assert_eq!(RowProvider::Range, plan.row_provider);
+    assert_eq!(vec![3, 3, 3], plan.scenarios.iter().map(|s| s.candidate_rows).collect::<Vec<u64>>());
+    assert_eq!(6, plan.point_count);
+}
+
+#[test]
+fn test_analyze() {
+    let mut store = build_and_load();
+    let query = price_by_category(None);
+    let (result, plan) = QueryEngine::new(&store).analyze(&query);
+    assert_eq!(5, result.size());
+    let expected = result.to_record_batch();
+    assert_eq!(RowProvider::Range, plan.row_provider);
+    assert_eq!(5, plan.point_count);
+    assert!(plan.is_analyzed() && plan.grouping.is_some());
+    let text = plan.to_string();
+    assert!(text.contains("Points: 5\n"), "{}", text);
+    assert!(text.contains("Grouping: ") && text.contains("Aggregating: "), "{}", text);
+
+    let mut definition = AggregateTableDefinition::new("by_category", vec!["category"]);
+    definition.add_aggregated_measure("price", "sum");
+    store.add_aggregate_table(definition).unwrap();
+    let engine = QueryEngine::new(&store);
+    let (rolled_up, plan) = engine.analyze(&query);
+    assert_eq!(expected, rolled_up.to_record_batch());
+    assert_eq!(RowProvider::AggregateTable("by_category".to_string()), plan.row_provider);
+    // The points of the table by scenario, s2 has no condiment.
+    assert_eq!(vec![2, 2, 1], plan.scenarios.iter().map(|s| s.candidate_rows).collect::<Vec<u64>>());
+    assert_eq!(plan.row_provider, engine.explain(&query).row_provider);
+    // Only the points of the table matching the filters are candidates.
+    let plan = engine.explain(&price_by_category(Some(vec!["milk"])));
+    assert_eq!(vec![1, 1, 1], plan.scenarios.iter().map(|s| s.candidate_rows).collect::<Vec<u64>>());
+}
+
+fn scenario_plan(scenario: &str, simulated_fields: Vec<&str>, candidate_rows: u64) -> ScenarioPlan {
+    ScenarioPlan {
+        scenario: scenario.to_string(),
+        simulated_fields: simulated_fields.iter().map(|f| f.to_string()).collect(),
+        candidate_rows,
+    }
+}
+
+/// The price by scenario and category, the scenarios are listed to keep their order.
+fn price_by_category(categories: Option<Vec<&str>>) -> Query {
+    let mut query = Query::new();
+    query.add_coordinates(SCENARIO_FIELD_NAME, vec![MAIN_SCENARIO_NAME, "s1", "s2"]);
+    match categories {
+        Some(categories) => query.add_coordinates("category", categories),
+        None => query.add_wildcard_coordinate("category"),
+    };
+    query.add_aggregated_measure("price", "sum");
+    query
+}
+
+/// The common store with s2 moving the syrup to the milk category.
+fn build_and_load() -> Store {
+    let mut store = common::build_and_load();
+    let s2_batch = create_batch(&store, vec![0], vec!["syrup"], vec!["milk"], vec![1f64]);
+    store.load("s2", &s2_batch);
+    store
+}
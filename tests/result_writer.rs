@@ -0,0 +1,131 @@
+use std::io::Cursor;
+use std::sync::Arc;
+use arrow::array::{Float64Array, Int64Array, StringArray, UInt32Array};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::ipc::reader::StreamReader;
+use arrow::record_batch::RecordBatch;
+
+use rustchristmasdb::datastore::{CHUNK_DEFAULT_SIZE, MAIN_SCENARIO_NAME, SCENARIO_FIELD_NAME, Store};
+use rustchristmasdb::query::Query;
+use rustchristmasdb::query_engine::QueryEngine;
+use rustchristmasdb::result_writer::{ResultWriter, WriterOptions};
+
+#[test]
+fn test_write_csv() {
+    let store = build_and_load();
+    let query = create_query();
+    let qe = QueryEngine::new(&store);
+    let result = qe.execute(&query);
+
+    let mut buffer = Vec::new();
+    ResultWriter::new().write_csv(&result, &mut buffer).unwrap();
+    assert_eq!("scenario,product,sum(price),sum(quantity)\n\
+                base,syrup,2,5\n\
+                base,tofu,8,3\n\
+                s1,syrup,3.5,5\n\
+                s1,tofu,6,3\n", String::from_utf8(buffer).unwrap());
+
+    let options = WriterOptions {
+        delimiter: b';',
+        has_header: false,
+        float_precision: Some(2),
+        ..WriterOptions::default()
+    };
+    let mut buffer = Vec::new();
+    ResultWriter::with_options(options).write_csv(&result, &mut buffer).unwrap();
+    assert_eq!("base;syrup;2.00;5\n\
+                base;tofu;8.00;3\n\
+                s1;syrup;3.50;5\n\
+                s1;tofu;6.00;3\n", String::from_utf8(buffer).unwrap());
+}
+
+#[test]
+fn test_write_json_lines() {
+    let store = build_and_load();
+    let query = create_query();
+    let qe = QueryEngine::new(&store);
+    let result = qe.execute(&query);
+
+    let mut buffer = Vec::new();
+    ResultWriter::new().write_json_lines(&result, &mut buffer).unwrap();
+    assert_eq!(r#"{"scenario":"base","product":"syrup","sum(price)":2.0,"sum(quantity)":5}
+{"scenario":"base","product":"tofu","sum(price)":8.0,"sum(quantity)":3}
+{"scenario":"s1","product":"syrup","sum(price)":3.5,"sum(quantity)":5}
+{"scenario":"s1","product":"tofu","sum(price)":6.0,"sum(quantity)":3}
+"#, String::from_utf8(buffer).unwrap());
+}
+
+#[test]
+fn test_write_nested_json() {
+    let store = build_and_load();
+    let query = create_query();
+    let qe = QueryEngine::new(&store);
+    let result = qe.execute(&query);
+
+    let mut buffer = Vec::new();
+    ResultWriter::new().write_nested_json(&result, &mut buffer).unwrap();
+    assert_eq!(r#"{"base":[{"product":"syrup","sum(price)":2.0,"sum(quantity)":5},{"product":"tofu","sum(price)":8.0,"sum(quantity)":3}],"s1":[{"product":"syrup","sum(price)":3.5,"sum(quantity)":5},{"product":"tofu","sum(price)":6.0,"sum(quantity)":3}]}"#,
+               String::from_utf8(buffer).unwrap());
+
+    let options = WriterOptions { pretty: true, ..WriterOptions::default() };
+    let mut buffer = Vec::new();
+    ResultWriter::with_options(options).write_nested_json(&result, &mut buffer).unwrap();
+    assert!(String::from_utf8(buffer).unwrap().starts_with("{\n  \"base\": [\n    {\n      \"product\": \"syrup\","));
+}
+
+#[test]
+fn test_write_ipc_stream() {
+    let store = build_and_load();
+    let query = create_query();
+    let qe = QueryEngine::new(&store);
+    let result = qe.execute(&query);
+
+    let mut buffer = Vec::new();
+    ResultWriter::new().write_ipc_stream(&result, &mut buffer).unwrap();
+    let reader = StreamReader::try_new(Cursor::new(buffer)).unwrap();
+    let batches: Vec<RecordBatch> = reader.map(|b| b.unwrap()).collect();
+    assert_eq!(1, batches.len());
+    assert_eq!(result.to_record_batch(), batches[0]);
+}
+
+fn create_query() -> Query {
+    let mut query = Query::new();
+    query
+        .add_coordinates(SCENARIO_FIELD_NAME, Vec::from([MAIN_SCENARIO_NAME, "s1"]))
+        .add_coordinates("product", Vec::from(["syrup", "tofu"]))
+        .add_aggregated_measure("price", "sum")
+        .add_aggregated_measure("quantity", "sum");
+    query
+}
+
+fn build_and_load() -> Store {
+    let schema = Schema::new(vec![
+        Field::new("id", DataType::Int64, false),
+        Field::new("product", DataType::Utf8, false),
+        Field::new("price", DataType::Float64, false),
+        Field::new("quantity", DataType::UInt32, false),
+    ]);
+    let mut store = Store::new(Arc::new(schema), vec![0], CHUNK_DEFAULT_SIZE as u32);
+
+    let main_batch = RecordBatch::try_new(
+        store.schema(),
+        vec![
+            Arc::new(Int64Array::from(vec![0, 1, 2])),
+            Arc::new(StringArray::from(vec!["syrup", "tofu", "mozzarella"])),
+            Arc::new(Float64Array::from(vec![2f64, 8f64, 4f64])),
+            Arc::new(UInt32Array::from(vec![5, 3, 4])),
+        ],
+    ).unwrap();
+    let s1_batch = RecordBatch::try_new(
+        store.schema(),
+        vec![
+            Arc::new(Int64Array::from(vec![0, 1])),
+            Arc::new(StringArray::from(vec!["syrup", "tofu"])),
+            Arc::new(Float64Array::from(vec![3.5f64, 6f64])),
+            Arc::new(UInt32Array::from(vec![5, 3])),
+        ],
+    ).unwrap();
+    store.load(MAIN_SCENARIO_NAME, &main_batch);
+    store.load("s1", &s1_batch);
+    store
+}
@@ -0,0 +1,57 @@
+use rustchristmasdb::row_mapping::{BitmapRowMapping, DENSE_ROW_MAPPING_RATIO, RowMapping, RowMappingFactory, SortedArrayRowMapping};
+
+#[test]
+fn test_unsorted_rows() {
+    for mapping in create(vec![5, 1, 3]) {
+        assert_eq!(vec![Some(1), None, Some(2), None, Some(0)], get_all(mapping.as_ref(), 1..6), "{:?}", mapping);
+    }
+}
+
+#[test]
+fn test_duplicated_rows() {
+    // The last target row of a row wins.
+    for mapping in create(vec![4, 2, 4, 7, 2]) {
+        assert_eq!(vec![Some(4), None, Some(2), None, None, Some(3)], get_all(mapping.as_ref(), 2..8), "{:?}", mapping);
+    }
+}
+
+#[test]
+fn test_word_boundaries() {
+    for mapping in create(vec![63, 64, 127]) {
+        assert_eq!(vec![None, Some(0), Some(1), None], get_all(mapping.as_ref(), 62..66), "{:?}", mapping);
+        assert_eq!(vec![None, Some(2), None], get_all(mapping.as_ref(), 126..129), "{:?}", mapping);
+    }
+}
+
+#[test]
+fn test_misses() {
+    for mapping in create(vec![]).into_iter().chain(create(vec![3])) {
+        assert_eq!(None, mapping.get(&0), "{:?}", mapping);
+        assert_eq!(None, mapping.get(&2), "{:?}", mapping);
+        // Far beyond the last mapped row.
+        assert_eq!(None, mapping.get(&100_000), "{:?}", mapping);
+        assert_eq!(None, mapping.get(&u32::MAX), "{:?}", mapping);
+    }
+}
+
+#[test]
+fn test_factory_switch() {
+    let row_count = 1024u64;
+    let dense_rows = (row_count as f64 * DENSE_ROW_MAPPING_RATIO) as u32;
+    let bitmap = RowMappingFactory::create((0..dense_rows).map(|row| row * 16).collect(), row_count);
+    assert!(bitmap.debug().starts_with("bitmap_row_mapping"), "{:?}", bitmap);
+    let sorted = RowMappingFactory::create((0..dense_rows - 1).map(|row| row * 16).collect(), row_count);
+    assert!(sorted.debug().starts_with("sorted_array_row_mapping"), "{:?}", sorted);
+    for mapping in [bitmap, sorted] {
+        assert_eq!(vec![Some(1), None], get_all(mapping.as_ref(), 16..18), "{:?}", mapping);
+    }
+}
+
+/// The mapping of the rows with both implementations.
+fn create(rows: Vec<u32>) -> Vec<Box<dyn RowMapping>> {
+    vec![Box::new(SortedArrayRowMapping::new(rows.clone())), Box::new(BitmapRowMapping::new(rows))]
+}
+
+fn get_all<I: Iterator<Item=u32>>(mapping: &dyn RowMapping, rows: I) -> Vec<Option<u32>> {
+    rows.map(|row| mapping.get(&row)).collect()
+}
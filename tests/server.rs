@@ -0,0 +1,183 @@
+use std::io::Cursor;
+use std::sync::Arc;
+use arrow::array::Int64Array;
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::ipc::reader::StreamReader;
+use arrow::ipc::writer::StreamWriter;
+use arrow::record_batch::RecordBatch;
+use serde_json::{json, Value};
+
+use rustchristmasdb::datastore::MAIN_SCENARIO_NAME;
+use rustchristmasdb::query_cache::QueryCache;
+use rustchristmasdb::server::{ARROW_STREAM_CONTENT_TYPE, CSV_CONTENT_TYPE, HttpRequest, HttpResponse, JSON_CONTENT_TYPE, Server, ServerConfig};
+
+mod common;
+use common::build_and_load;
+
+#[test]
+fn test_metadata() {
+    let mut server = Server::new(build_and_load());
+
+    let response = server.handle(&get("/fields"));
+    assert_eq!(200, response.status);
+    assert_eq!(json!([
+        { "name": "id", "data_type": "Int64", "key": true },
+        { "name": "product", "data_type": "Utf8", "key": false },
+        { "name": "category", "data_type": "Utf8", "key": false },
+        { "name": "price", "data_type": "Float64", "key": false },
+        { "name": "quantity", "data_type": "UInt32", "key": false },
+    ]), to_json(&response));
+
+    assert_eq!(json!([MAIN_SCENARIO_NAME, "s1"]), to_json(&server.handle(&get("/scenarios"))));
+    assert_eq!(json!(["condiment", "milk"]), to_json(&server.handle(&get("/fields/category/members"))));
+    assert_eq!(400, server.handle(&get("/fields/price/members")).status);
+    assert_eq!(404, server.handle(&get("/fields/color/members")).status);
+    assert_eq!(404, server.handle(&get("/tables")).status);
+}
+
+#[test]
+fn test_query() {
+    let mut server = Server::new(build_and_load());
+    let query = br#"{
+        "coordinates": { "scenario": null, "category": ["milk"] },
+        "measures": [{ "field": "price", "aggregation_function": "sum" }],
+        "order_by": [{ "field": "scenario" }]
+    }"#;
+
+    let response = server.handle(&post("/query", Some(JSON_CONTENT_TYPE), query));
+    assert_eq!(200, response.status);
+    assert_eq!(json!([
+        { "scenario": MAIN_SCENARIO_NAME, "category": "milk", "sum(price)": 12.0 },
+        { "scenario": "s1", "category": "milk", "sum(price)": 10.0 },
+    ]), to_json(&response));
+
+    let sql = b"SELECT scenario, category, sum(price) FROM store WHERE category = 'milk' GROUP BY scenario, category";
+    let mut request = post("/sql", None, sql);
+    request.accept = Some("text/html, application/vnd.apache.arrow.stream");
+    let response = server.handle(&request);
+    assert_eq!(ARROW_STREAM_CONTENT_TYPE, response.content_type);
+    let batches: Vec<RecordBatch> = StreamReader::try_new(Cursor::new(response.body)).unwrap().map(|b| b.unwrap()).collect();
+    assert_eq!(2, batches[0].num_rows());
+
+    let invalid = br#"{ "coordinates": { "color": null }, "measures": [{ "field": "price", "aggregation_function": "sum" }] }"#;
+    let response = server.handle(&post("/query", Some(JSON_CONTENT_TYPE), invalid));
+    assert_eq!(400, response.status);
+    assert_eq!(json!({ "errors": [{ "field": "coordinates.color", "message": "unknown field" }] }), to_json(&response));
+    assert_eq!(400, server.handle(&post("/sql", None, b"SELECT * FROM store")).status);
+}
+
+#[test]
+fn test_query_cache() {
+    let mut server = Server::new(build_and_load());
+    assert_eq!(404, server.handle(&get("/cache")).status);
+    server.set_query_cache(QueryCache::new());
+
+    let sql = b"SELECT scenario, sum(price) FROM store GROUP BY scenario";
+    let response = to_json(&server.handle(&post("/sql", None, sql)));
+    assert_eq!(response, to_json(&server.handle(&post("/sql", None, sql))));
+    let csv = b"id,product,category,price,quantity\n0,syrup,condiment,10,5\n";
+    assert_eq!(201, server.handle(&post("/scenarios/s2", Some(CSV_CONTENT_TYPE), csv)).status);
+    assert_eq!(3, to_json(&server.handle(&post("/sql", None, sql))).as_array().unwrap().len());
+
+    assert_eq!(json!({ "hits": 1, "misses": 2, "evictions": 0, "invalidations": 1, "refreshes": 0, "entries": 1, "bytes": server.query_cache().unwrap().stats().bytes }),
+               to_json(&server.handle(&get("/cache"))));
+}
+
+#[test]
+fn test_load_scenario() {
+    let mut server = Server::new(build_and_load());
+
+    let csv = b"id,product,category,price,quantity\n0,syrup,condiment,10,5\n";
+    let response = server.handle(&post("/scenarios/s2", Some("text/csv; charset=utf-8"), csv));
+    assert_eq!(201, response.status);
+    assert_eq!(json!({ "scenario": "s2", "rows": 1 }), to_json(&response));
+    assert_eq!(409, server.handle(&post("/scenarios/s2", Some(CSV_CONTENT_TYPE), csv)).status);
+    let mut reload = post("/scenarios/s2", Some(CSV_CONTENT_TYPE), b"id,product,category,price,quantity\n0,syrup,condiment,12,5\n");
+    reload.method = "PUT";
+    assert_eq!(200, server.handle(&reload).status);
+    reload.url = "/scenarios/base";
+    assert_eq!(400, server.handle(&reload).status);
+
+    // Only the key and the modified field, with a type the store casts.
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Int64, false),
+        Field::new("price", DataType::Int64, false),
+    ]));
+    let batch = RecordBatch::try_new(Arc::clone(&schema), vec![
+        Arc::new(Int64Array::from(vec![1])),
+        Arc::new(Int64Array::from(vec![20])),
+    ]).unwrap();
+    let response = server.handle(&post("/scenarios/s%203", Some(ARROW_STREAM_CONTENT_TYPE), &to_ipc(&batch)));
+    assert_eq!(201, response.status);
+
+    let sql = b"SELECT scenario, sum(price) FROM store WHERE scenario IN ('s2', 's 3') GROUP BY scenario";
+    assert_eq!(json!([
+        { "scenario": "s2", "sum(price)": 24.0 },
+        { "scenario": "s 3", "sum(price)": 26.0 },
+    ]), to_json(&server.handle(&post("/sql", None, sql))));
+
+    let unknown_key = RecordBatch::try_new(schema, vec![
+        Arc::new(Int64Array::from(vec![7])),
+        Arc::new(Int64Array::from(vec![20])),
+    ]).unwrap();
+    let response = server.handle(&post("/scenarios/s4", Some(ARROW_STREAM_CONTENT_TYPE), &to_ipc(&unknown_key)));
+    assert_eq!(json!({ "error": "Invalid argument error: key 7 does not exist in the base scenario" }), to_json(&response));
+    assert_eq!(415, server.handle(&post("/scenarios/s4", None, csv)).status);
+}
+
+#[test]
+fn test_config() {
+    let path = std::env::temp_dir().join(format!("rustchristmasdb_server_{}.json", std::process::id()));
+    let directory = concat!(env!("CARGO_MANIFEST_DIR"), "/test/data");
+    std::fs::write(&path, json!({
+        "source": { "format": "csv", "directory": directory, "key_field": "OrderDetailID" },
+        "aggregate_tables": [{
+            "name": "by_category_and_country",
+            "coordinates": ["CategoryName", "Country"],
+            "measures": []
+        }]
+    }).to_string()).unwrap();
+
+    let mut config = ServerConfig::from_file(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!("127.0.0.1:8080", config.address);
+    let store = config.create_store().unwrap();
+    assert_eq!(1, store.aggregate_tables().len());
+    let mut server = Server::new(store);
+    assert_eq!(json!([MAIN_SCENARIO_NAME, "s05", "s10", "s25", "s50"]), to_json(&server.handle(&get("/scenarios"))));
+
+    // The rolled up result is the one of the rows.
+    let query = br#"{ "coordinates": { "scenario": ["base", "s05"], "Country": null }, "measures": [] }"#;
+    let rolled_up = to_json(&server.handle(&post("/query", Some(JSON_CONTENT_TYPE), query)));
+    let tables = std::mem::take(&mut config.aggregate_tables);
+    let mut server = Server::new(config.create_store().unwrap());
+    assert_eq!(to_json(&server.handle(&post("/query", Some(JSON_CONTENT_TYPE), query))), rolled_up);
+
+    config.aggregate_tables = tables;
+    config.aggregate_tables[0].add_aggregated_measure("Quantity", "sum");
+    assert_eq!("invalid aggregate table 'by_category_and_country': measures[0].field: 'Quantity' cannot be aggregated, Int64 is not supported",
+               config.create_store().unwrap_err().to_string());
+}
+
+fn get(url: &str) -> HttpRequest<'_> {
+    HttpRequest { method: "GET", url, accept: None, content_type: None, body: &[] }
+}
+
+fn post<'a>(url: &'a str, content_type: Option<&'a str>, body: &'a [u8]) -> HttpRequest<'a> {
+    HttpRequest { method: "POST", url, accept: None, content_type, body }
+}
+
+fn to_json(response: &HttpResponse) -> Value {
+    assert_eq!(JSON_CONTENT_TYPE, response.content_type);
+    serde_json::from_slice(&response.body).unwrap()
+}
+
+fn to_ipc(batch: &RecordBatch) -> Vec<u8> {
+    let mut buffer = Vec::new();
+    {
+        let mut writer = StreamWriter::try_new(&mut buffer, &batch.schema()).unwrap();
+        writer.write(batch).unwrap();
+        writer.finish().unwrap();
+    }
+    buffer
+}
@@ -0,0 +1,79 @@
+use rustchristmasdb::datastore::{MAIN_SCENARIO_NAME, SCENARIO_FIELD_NAME};
+use rustchristmasdb::having::{Comparison, Having};
+use rustchristmasdb::order_by::OrderBy;
+use rustchristmasdb::query::AggregatedMeasure;
+use rustchristmasdb::sql::{parse_query, SqlEngine, SqlError};
+
+mod common;
+use common::build_and_load_with_s2;
+
+#[test]
+fn test_translate_to_query() {
+    let store = build_and_load_with_s2();
+
+    let sql = parse_query(
+        "SELECT scenario, category, sum(price), SUM(quantity) FROM store \
+         WHERE scenario IN ('s1', 'base') AND (category = 'milk') \
+         GROUP BY category, scenario \
+         HAVING 4 < sum(price) \
+         ORDER BY sum(price) DESC, category \
+         LIMIT 10 OFFSET 1",
+        &store).unwrap();
+
+    assert_eq!(vec![SCENARIO_FIELD_NAME, "category"], sql.coordinates.keys().collect::<Vec<&String>>());
+    assert_eq!(Some(&Some(vec!["s1".to_string(), MAIN_SCENARIO_NAME.to_string()])), sql.coordinates.get(SCENARIO_FIELD_NAME));
+    assert_eq!(Some(&Some(vec!["milk".to_string()])), sql.coordinates.get("category"));
+    assert_eq!(vec![AggregatedMeasure::new("price", "sum"), AggregatedMeasure::new("quantity", "sum")], sql.measures);
+    assert_eq!(vec![Having::new("sum(price)", Comparison::Gt, 4f64)], sql.having);
+    assert_eq!(vec![OrderBy::desc("sum(price)"), OrderBy::asc("category")], sql.order_by);
+    assert_eq!(Some(10), sql.limit);
+    assert_eq!(1, sql.offset);
+}
+
+#[test]
+fn test_execute() {
+    let store = build_and_load_with_s2();
+    let engine = SqlEngine::new(&store);
+
+    let result = engine.execute(
+        "SELECT scenario, category, sum(price) FROM store \
+         WHERE category IN ('milk') AND scenario IN ('s1', 's2') \
+         GROUP BY scenario, category").unwrap();
+    assert_eq!(2, result.size());
+    result.assert_aggregate(Vec::from(["s1", "milk"]), 10f64);
+    result.assert_aggregate(Vec::from(["s2", "milk"]), 13f64);
+
+    let result = engine.execute(
+        "SELECT product, sum(quantity) FROM store GROUP BY product ORDER BY product LIMIT 2").unwrap();
+    let products: Vec<Vec<&str>> = result.iter().map(|(coordinates, _)| coordinates).collect();
+    assert_eq!(vec![vec!["mozzarella"], vec!["syrup"]], products);
+
+    // An unknown value matches no row.
+    let result = engine.execute("SELECT product, sum(price) FROM store WHERE product = 'bread' GROUP BY product").unwrap();
+    assert_eq!(0, result.size());
+}
+
+#[test]
+fn test_errors() {
+    let store = build_and_load_with_s2();
+
+    let errors = [
+        ("SELECT product sum(price) FROM store GROUP BY product", "sql parser error"),
+        ("SELECT product, sum(price) FROM products GROUP BY product", "invalid query: unknown table 'products'"),
+        ("SELECT product, sum(price) FROM store", "invalid query: 'product' must appear in the GROUP BY clause"),
+        ("SELECT product, sum(weight) FROM store GROUP BY product", "invalid query: unknown column 'weight'"),
+        ("SELECT product, sum(product) FROM store GROUP BY product", "invalid query: cannot aggregate 'product'"),
+        ("SELECT product, avg(price) FROM store GROUP BY product", "unsupported: 'avg(price)'"),
+        ("SELECT product, sum(price) AS total FROM store GROUP BY product", "unsupported: 'sum(price) AS total'"),
+        ("SELECT * FROM store", "unsupported: '*'"),
+        ("SELECT product, sum(price) FROM store WHERE category = 'milk' GROUP BY product", "unsupported: filtering on 'category'"),
+        ("SELECT product, sum(price) FROM store WHERE product <> 'tofu' GROUP BY product", "unsupported: 'product <> 'tofu''"),
+        ("SELECT p.product, sum(p.price) FROM store p JOIN store q ON p.id = q.id GROUP BY p.product", "unsupported: the query must read the single table"),
+        ("SELECT product, sum(price) FROM store GROUP BY product HAVING sum(quantity) > 2", "invalid query: 'sum(quantity)' must appear in the select list"),
+        ("DELETE FROM store", "unsupported: only SELECT statements are supported"),
+    ];
+    for (sql, expected) in errors {
+        let error: SqlError = parse_query(sql, &store).unwrap_err();
+        assert!(error.to_string().starts_with(expected), "'{}' gave '{}'", sql, error);
+    }
+}